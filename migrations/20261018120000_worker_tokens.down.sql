@@ -0,0 +1,3 @@
+ALTER TABLE session DROP COLUMN worker_token_id;
+
+DROP TABLE worker_token;
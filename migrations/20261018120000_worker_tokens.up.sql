@@ -0,0 +1,10 @@
+CREATE TABLE worker_token (
+    id uuid default gen_random_uuid() primary key,
+    worker_id uuid not null references worker(id) ON DELETE CASCADE,
+    created_at timestamp with time zone default now() not null,
+    expires_at timestamp with time zone not null,
+    revoked_at timestamp with time zone
+);
+
+ALTER TABLE session
+ADD COLUMN worker_token_id uuid references worker_token(id) ON DELETE SET NULL;
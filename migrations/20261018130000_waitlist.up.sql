@@ -0,0 +1,6 @@
+CREATE TABLE waitlist (
+    shift_id uuid not null references shift(id) ON DELETE CASCADE,
+    worker_id uuid not null references worker(id) ON DELETE CASCADE,
+    created_at timestamp with time zone default now() not null,
+    primary key (shift_id, worker_id)
+);
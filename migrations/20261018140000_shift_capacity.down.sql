@@ -0,0 +1,26 @@
+ALTER TABLE shift ADD COLUMN worker_id uuid references worker(id) ON DELETE SET NULL;
+
+-- Split each shift back into one row per seat, filled seats first. Waitlists
+-- for split shifts are dropped along with the original rows.
+CREATE TEMPORARY TABLE shift_split AS SELECT id FROM shift;
+
+INSERT INTO shift (event_id, date, start_time, end_time, title, description, public_signup, worker_id)
+SELECT s.event_id, s.date, s.start_time, s.end_time, s.title, s.description, s.public_signup, seats.worker_id
+FROM shift s
+INNER JOIN shift_split ON shift_split.id = s.id
+CROSS JOIN LATERAL (
+    SELECT sa.worker_id FROM shift_assignment sa WHERE sa.shift_id = s.id
+    UNION ALL
+    SELECT NULL FROM generate_series(
+        1,
+        s.capacity - (SELECT count(*) FROM shift_assignment sa WHERE sa.shift_id = s.id)
+    )
+) seats;
+
+DELETE FROM shift USING shift_split WHERE shift.id = shift_split.id;
+
+DROP TABLE shift_split;
+
+DROP TABLE shift_assignment;
+
+ALTER TABLE shift DROP COLUMN capacity;
@@ -0,0 +1,51 @@
+ALTER TABLE shift ADD COLUMN capacity integer not null default 1 CHECK (capacity > 0);
+
+CREATE TABLE shift_assignment (
+    shift_id uuid not null references shift(id) ON DELETE CASCADE,
+    worker_id uuid not null references worker(id) ON DELETE CASCADE,
+    created_at timestamp with time zone default now() not null,
+    primary key (shift_id, worker_id)
+);
+
+-- Collapse identical shifts into one shift per group, keeping a filled one
+CREATE TEMPORARY TABLE shift_merge AS
+SELECT
+    id,
+    first_value(id) OVER seats AS keep_id,
+    count(*) OVER seats AS seats
+FROM shift
+WINDOW seats AS (
+    PARTITION BY event_id, date, start_time, end_time, title, description, public_signup
+    ORDER BY worker_id IS NULL, id
+    ROWS BETWEEN UNBOUNDED PRECEDING AND UNBOUNDED FOLLOWING
+);
+
+INSERT INTO shift_assignment (shift_id, worker_id)
+SELECT DISTINCT m.keep_id, s.worker_id
+FROM shift s
+INNER JOIN shift_merge m ON m.id = s.id
+WHERE s.worker_id IS NOT NULL;
+
+INSERT INTO waitlist (shift_id, worker_id, created_at)
+SELECT m.keep_id, wl.worker_id, min(wl.created_at)
+FROM waitlist wl
+INNER JOIN shift_merge m ON m.id = wl.shift_id
+WHERE m.id <> m.keep_id
+GROUP BY m.keep_id, wl.worker_id
+ON CONFLICT DO NOTHING;
+
+UPDATE shift SET capacity = m.seats
+FROM shift_merge m
+WHERE shift.id = m.keep_id;
+
+DELETE FROM shift
+USING shift_merge m
+WHERE shift.id = m.id AND m.id <> m.keep_id;
+
+DELETE FROM waitlist
+USING shift_assignment sa
+WHERE waitlist.shift_id = sa.shift_id AND waitlist.worker_id = sa.worker_id;
+
+DROP TABLE shift_merge;
+
+ALTER TABLE shift DROP COLUMN worker_id;
@@ -0,0 +1,9 @@
+CREATE TABLE email_attachment (
+    id uuid primary key default gen_random_uuid(),
+    email_id uuid not null references email(id) ON DELETE CASCADE,
+    filename text not null,
+    content_type text not null,
+    content bytea not null
+);
+
+CREATE INDEX email_attachment_email_id ON email_attachment (email_id);
@@ -0,0 +1,8 @@
+CREATE TABLE reminder (
+    worker_id uuid not null references worker(id) ON DELETE CASCADE,
+    event_id uuid not null references event(id) ON DELETE CASCADE,
+    date date not null,
+    email_id uuid references email(id) ON DELETE SET NULL,
+    sent_at timestamp with time zone default now() not null,
+    primary key (worker_id, event_id, date)
+);
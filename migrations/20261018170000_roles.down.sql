@@ -0,0 +1,7 @@
+DROP TABLE event_coordinator;
+
+ALTER TABLE admin_invite DROP COLUMN role, DROP COLUMN event_id;
+
+ALTER TABLE users DROP COLUMN role;
+
+DROP TYPE user_role;
@@ -0,0 +1,15 @@
+CREATE TYPE user_role AS ENUM ('owner', 'scheduler', 'viewer', 'coordinator');
+
+-- Everyone was a full admin before roles existed
+ALTER TABLE users ADD COLUMN role user_role not null default 'viewer';
+UPDATE users SET role = 'owner';
+
+ALTER TABLE admin_invite
+    ADD COLUMN role user_role not null default 'viewer',
+    ADD COLUMN event_id uuid references event(id) ON DELETE CASCADE;
+
+CREATE TABLE event_coordinator (
+    user_id uuid not null references users(id) ON DELETE CASCADE,
+    event_id uuid not null references event(id) ON DELETE CASCADE,
+    primary key (user_id, event_id)
+);
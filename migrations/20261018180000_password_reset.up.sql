@@ -0,0 +1,7 @@
+CREATE TABLE password_reset (
+    id uuid default gen_random_uuid() primary key,
+    user_id uuid not null references users(id) ON DELETE CASCADE,
+    created_at timestamp with time zone default now() not null,
+    expires_at timestamp with time zone not null,
+    used_at timestamp with time zone
+);
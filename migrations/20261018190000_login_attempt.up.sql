@@ -0,0 +1,10 @@
+CREATE TABLE login_attempt (
+    id uuid default gen_random_uuid() primary key,
+    created_at timestamp with time zone default now() not null,
+    email varchar not null,
+    ip varchar not null,
+    success boolean not null
+);
+
+CREATE INDEX login_attempt_email ON login_attempt (email, created_at);
+CREATE INDEX login_attempt_ip ON login_attempt (ip, created_at);
@@ -0,0 +1,8 @@
+ALTER TABLE session DROP COLUMN pending_user_id;
+
+DROP TABLE recovery_code;
+
+ALTER TABLE users
+    DROP COLUMN totp_secret,
+    DROP COLUMN totp_pending_secret,
+    DROP COLUMN totp_last_step;
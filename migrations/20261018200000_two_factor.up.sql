@@ -0,0 +1,16 @@
+ALTER TABLE users
+    ADD COLUMN totp_secret varchar,
+    -- Set while enrolling, until the first code is confirmed
+    ADD COLUMN totp_pending_secret varchar,
+    -- The last time step a code was accepted for, so codes can't be reused
+    ADD COLUMN totp_last_step bigint;
+
+CREATE TABLE recovery_code (
+    id uuid default gen_random_uuid() primary key,
+    user_id uuid not null references users(id) ON DELETE CASCADE,
+    code_hash varchar not null,
+    used_at timestamp with time zone
+);
+
+-- Logged in with a password, but still needs to enter a code
+ALTER TABLE session ADD COLUMN pending_user_id uuid references users(id) ON DELETE CASCADE;
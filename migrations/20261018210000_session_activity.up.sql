@@ -0,0 +1,7 @@
+ALTER TABLE session
+    ADD COLUMN last_seen_at timestamp default now() not null,
+    ADD COLUMN ip varchar,
+    ADD COLUMN user_agent varchar;
+
+-- Sessions used to last forever
+UPDATE session SET expires_at = created_at + interval '30 days' WHERE expires_at IS NULL;
@@ -0,0 +1,5 @@
+DROP TABLE notification_digest;
+
+DROP TABLE notification;
+
+DROP TYPE notification_kind;
@@ -0,0 +1,20 @@
+CREATE TYPE notification_kind AS ENUM ('signup', 'cancellation');
+
+CREATE TABLE notification (
+    id uuid default gen_random_uuid() primary key,
+    created_at timestamp with time zone default now() not null,
+    kind notification_kind not null,
+    shift_id uuid not null references shift(id) ON DELETE CASCADE,
+    worker_id uuid not null references worker(id) ON DELETE CASCADE,
+    reason varchar,
+    -- Set once the notification has gone out in a digest
+    sent_at timestamp with time zone
+);
+
+CREATE INDEX notification_unsent ON notification (created_at) WHERE sent_at IS NULL;
+
+-- When each digest went out, so daily digests are only sent once a day
+CREATE TABLE notification_digest (
+    id uuid default gen_random_uuid() primary key,
+    sent_at timestamp with time zone default now() not null
+);
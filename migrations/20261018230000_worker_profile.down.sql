@@ -0,0 +1,11 @@
+DROP TABLE shift_requirement;
+
+DROP TABLE worker_availability;
+
+DROP TABLE worker_skill;
+
+DROP TABLE worker_profile;
+
+DROP TYPE age_bracket;
+DROP TYPE shirt_size;
+DROP TYPE skill;
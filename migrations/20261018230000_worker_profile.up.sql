@@ -0,0 +1,35 @@
+CREATE TYPE skill AS ENUM ('food_handler', 'cashier', 'grill');
+CREATE TYPE shirt_size AS ENUM ('xs', 's', 'm', 'l', 'xl', 'xxl');
+CREATE TYPE age_bracket AS ENUM ('under16', 'teen', 'adult');
+
+CREATE TABLE worker_profile (
+    worker_id uuid primary key references worker(id) ON DELETE CASCADE,
+    shirt_size shirt_size,
+    age_bracket age_bracket,
+    -- Only shown to admins
+    notes text
+);
+
+CREATE TABLE worker_skill (
+    worker_id uuid not null references worker(id) ON DELETE CASCADE,
+    skill skill not null,
+    primary key (worker_id, skill)
+);
+
+-- Days a worker can help, and the hours if they can't all day
+CREATE TABLE worker_availability (
+    worker_id uuid not null references worker(id) ON DELETE CASCADE,
+    event_id uuid not null,
+    date date not null,
+    start_time time,
+    end_time time,
+    primary key (worker_id, event_id, date),
+    foreign key (date, event_id) references day(date, event_id) ON DELETE CASCADE
+);
+
+-- Skills a worker needs to work a shift
+CREATE TABLE shift_requirement (
+    shift_id uuid not null references shift(id) ON DELETE CASCADE,
+    skill skill not null,
+    primary key (shift_id, skill)
+);
@@ -0,0 +1,2 @@
+-- Shifts like the grill that minors can't work
+ALTER TABLE shift ADD COLUMN adults_only boolean not null default false;
@@ -0,0 +1,3 @@
+ALTER TABLE event DROP COLUMN min_break_minutes;
+ALTER TABLE event DROP COLUMN max_shifts;
+ALTER TABLE event DROP COLUMN max_daily_minutes;
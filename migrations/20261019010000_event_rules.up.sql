@@ -0,0 +1,5 @@
+-- Limits on how much each worker can take on in an event. Null means no limit.
+ALTER TABLE event ADD COLUMN max_daily_minutes integer CHECK (max_daily_minutes > 0);
+ALTER TABLE event ADD COLUMN max_shifts integer CHECK (max_shifts > 0);
+-- Time a worker needs between the end of one shift and the start of the next
+ALTER TABLE event ADD COLUMN min_break_minutes integer CHECK (min_break_minutes > 0);
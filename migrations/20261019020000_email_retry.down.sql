@@ -0,0 +1,13 @@
+ALTER TABLE email
+    DROP COLUMN attempts,
+    DROP COLUMN last_error,
+    DROP COLUMN next_attempt_at;
+
+-- Values can't be dropped from an enum, so it has to be rebuilt
+ALTER TYPE email_status RENAME TO email_status_old;
+CREATE TYPE email_status AS ENUM ('draft', 'pending', 'sent', 'failed');
+ALTER TABLE email ALTER COLUMN status DROP DEFAULT;
+ALTER TABLE email ALTER COLUMN status TYPE email_status
+    USING (CASE WHEN status = 'dead' THEN 'failed' ELSE status::text END)::email_status;
+ALTER TABLE email ALTER COLUMN status SET DEFAULT 'draft';
+DROP TYPE email_status_old;
@@ -0,0 +1,7 @@
+-- Emails that failed too many times, or can never be sent, and won't be retried
+ALTER TYPE email_status ADD VALUE 'dead';
+
+ALTER TABLE email
+    ADD COLUMN attempts int not null default 0,
+    ADD COLUMN last_error text,
+    ADD COLUMN next_attempt_at timestamp with time zone;
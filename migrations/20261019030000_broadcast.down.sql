@@ -0,0 +1,3 @@
+ALTER TABLE email DROP COLUMN broadcast_id;
+DROP TABLE broadcast;
+DROP TYPE broadcast_audience;
@@ -0,0 +1,19 @@
+CREATE TYPE broadcast_audience AS ENUM ('event', 'day', 'prospects');
+
+-- An email written by an admin to a group of workers. Each recipient gets
+-- their own row in email, as a draft until the broadcast is sent.
+CREATE TABLE broadcast (
+    id uuid default gen_random_uuid() primary key,
+    created_at timestamp with time zone default now() not null,
+    sent_at timestamp with time zone,
+    event_id uuid not null references event(id) ON DELETE CASCADE,
+    audience broadcast_audience not null,
+    -- Only set for the day audience
+    date date,
+    subject text not null,
+    -- Markdown, with placeholders filled in for each recipient
+    body text not null
+);
+
+ALTER TABLE email ADD COLUMN broadcast_id uuid references broadcast(id) ON DELETE SET NULL;
+CREATE INDEX ON email (broadcast_id);
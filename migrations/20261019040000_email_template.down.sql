@@ -0,0 +1,2 @@
+DROP TABLE email_template;
+DROP TYPE email_template_kind;
@@ -0,0 +1,12 @@
+CREATE TYPE email_template_kind AS ENUM ('signup', 'reminder', 'invite');
+
+-- An event's own wording for one of the built-in emails
+CREATE TABLE email_template (
+    event_id uuid not null references event(id) ON DELETE CASCADE,
+    kind email_template_kind not null,
+    subject text not null,
+    -- Markdown, with variables like {first_name} filled in when it's sent
+    body text not null,
+    updated_at timestamp with time zone default now() not null,
+    primary key (event_id, kind)
+);
@@ -0,0 +1,8 @@
+-- Tokens for subscribing to an event's schedule from a calendar app, which
+-- can't log in. An event has at most one that hasn't been revoked.
+CREATE TABLE event_feed (
+    id uuid default gen_random_uuid() primary key,
+    event_id uuid not null references event(id) ON DELETE CASCADE,
+    created_at timestamp with time zone default now() not null,
+    revoked_at timestamp with time zone
+);
@@ -0,0 +1 @@
+-- Nothing to undo, next_attempt_at is dropped with the email_retry migration
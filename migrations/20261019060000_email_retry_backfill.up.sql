@@ -0,0 +1,4 @@
+-- Emails that failed before retries were added had no next attempt, so they
+-- were never picked up again
+UPDATE email SET next_attempt_at = now()
+WHERE status = 'failed' AND next_attempt_at IS NULL;
@@ -0,0 +1,10 @@
+-- Tokens for subscribing to a worker's shifts from a calendar app. Kept apart
+-- from worker_token, which can change the worker's details and shifts, since
+-- calendar services store and share the url. A worker has at most one that
+-- hasn't been revoked.
+CREATE TABLE worker_feed (
+    id uuid default gen_random_uuid() primary key,
+    worker_id uuid not null references worker(id) ON DELETE CASCADE,
+    created_at timestamp with time zone default now() not null,
+    revoked_at timestamp with time zone
+);
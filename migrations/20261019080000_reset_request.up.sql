@@ -0,0 +1,11 @@
+-- Password reset requests, for limiting how often they can be made. Kept apart
+-- from login_attempt so asking for resets can't lock anyone out of logging in.
+CREATE TABLE reset_request (
+    id uuid default gen_random_uuid() primary key,
+    created_at timestamp with time zone default now() not null,
+    email varchar not null,
+    ip varchar not null
+);
+
+CREATE INDEX reset_request_email ON reset_request (email, created_at);
+CREATE INDEX reset_request_ip ON reset_request (ip, created_at);
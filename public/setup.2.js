@@ -0,0 +1,22 @@
+document.body.addEventListener('htmx:beforeSwap', function(evt) {
+  if(evt.detail.xhr.status === 400 || evt.detail.xhr.status === 403){
+    evt.detail.shouldSwap = true;
+    evt.detail.isError = false;
+  } else if (evt.detail.xhr.status === 500 || evt.detail.xhr.status === 404) {
+    evt.detail.shouldSwap = true;
+    evt.detail.target = htmx.find("#content");
+  }
+})
+
+// Every request that changes something has to carry the session's token
+document.body.addEventListener('htmx:configRequest', function(evt) {
+  evt.detail.headers['X-CSRF-Token'] = document.querySelector('meta[name="csrf-token"]').content;
+})
+
+// Logging in or out changes the token
+document.body.addEventListener('htmx:afterRequest', function(evt) {
+  const token = evt.detail.xhr.getResponseHeader('X-CSRF-Token');
+  if (token) {
+    document.querySelector('meta[name="csrf-token"]').content = token;
+  }
+})
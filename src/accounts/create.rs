@@ -12,7 +12,7 @@ use uuid::Uuid;
 
 use crate::config;
 use crate::models::User;
-use crate::models::{AdminInvite, CreateUser};
+use crate::models::{AdminInvite, CreateUser, Role};
 use crate::session::Session;
 
 #[derive(Template)]
@@ -26,7 +26,8 @@ pub async fn account_creation_form(
 ) -> Result<impl IntoResponse, AppError> {
     let _ = sqlx::query_as!(
         AdminInvite,
-        "SELECT * FROM admin_invite WHERE accepted_at IS NULL AND id = $1",
+        r#"SELECT id, created_at, accepted_at, email, role as "role: Role", event_id
+        FROM admin_invite WHERE accepted_at IS NULL AND id = $1"#,
         invite_id
     )
     .fetch_one(config().pool())
@@ -49,7 +50,8 @@ pub async fn create_account(
 
     let invite = sqlx::query_as!(
         AdminInvite,
-        "UPDATE admin_invite SET accepted_at = now() WHERE accepted_at IS NULL AND id = $1 RETURNING *",
+        r#"UPDATE admin_invite SET accepted_at = now() WHERE accepted_at IS NULL AND id = $1
+        RETURNING id, created_at, accepted_at, email, role as "role: Role", event_id"#,
         invite_id
     )
     .fetch_one(&mut *transaction)
@@ -74,16 +76,29 @@ pub async fn create_account(
 
     user.password = pwd_fut.await??;
 
+    // The new user gets the role they were invited with
     let new_user = sqlx::query_as!(
         User,
-        "INSERT INTO users (name, email, password) VALUES ($1, $2, $3) RETURNING *",
+        r#"INSERT INTO users (name, email, password, role) VALUES ($1, $2, $3, $4)
+        RETURNING id, email, password, name, role as "role: Role", totp_secret"#,
         user.name,
         invite.email,
-        user.password
+        user.password,
+        invite.role as Role,
     )
     .fetch_one(&mut *transaction)
     .await?;
 
+    if let Some(event_id) = invite.event_id {
+        sqlx::query!(
+            "INSERT INTO event_coordinator (user_id, event_id) VALUES ($1, $2)",
+            new_user.id,
+            event_id
+        )
+        .execute(&mut *transaction)
+        .await?;
+    }
+
     transaction.commit().await?;
 
     session.set_auth_user(new_user).await?;
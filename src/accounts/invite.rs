@@ -8,7 +8,8 @@ use uuid::Uuid;
 
 use crate::{
     config,
-    models::{AdminInvite, User},
+    email::{find_template, TemplateKind, Variables},
+    models::{AdminInvite, Role, User},
     session::Session,
 };
 
@@ -28,7 +29,8 @@ pub struct InviteListTempl {
 pub async fn invite_list() -> Result<InviteListTempl, AppError> {
     let invites = sqlx::query_as!(
         AdminInvite,
-        "SELECT * from admin_invite WHERE accepted_at IS NULL"
+        r#"SELECT id, created_at, accepted_at, email, role as "role: Role", event_id
+        FROM admin_invite WHERE accepted_at IS NULL"#
     )
     .fetch_all(config().pool())
     .await?;
@@ -38,6 +40,9 @@ pub async fn invite_list() -> Result<InviteListTempl, AppError> {
 #[derive(Deserialize)]
 pub struct InviteForm {
     email: String,
+    #[serde(default)]
+    role: Role,
+    event_id: Option<String>,
 }
 
 pub async fn invite_user(
@@ -48,7 +53,7 @@ pub async fn invite_user(
         unreachable!()
     };
 
-    let user = sqlx::query!("SELECT * FROM users WHERE id = $1", user_id)
+    let user = sqlx::query!("SELECT name FROM users WHERE id = $1", user_id)
         .fetch_one(config().pool())
         .await?;
 
@@ -58,12 +63,31 @@ pub async fn invite_user(
         return Err(AppError::inline(StatusCode::BAD_REQUEST, "Invalid email"));
     }
 
+    // Coordinators are invited to a single event, other roles don't have one
+    let event_id = match invite.role {
+        Role::Coordinator => Some(
+            invite
+                .event_id
+                .as_deref()
+                .and_then(|id| id.parse::<Uuid>().ok())
+                .ok_or(AppError::inline(
+                    StatusCode::BAD_REQUEST,
+                    "Choose an event for the coordinator",
+                ))?,
+        ),
+        _ => None,
+    };
+
     // Start transaction
     let mut tran = config().pool().begin().await?;
 
-    let existing = sqlx::query_as!(User, "SELECT * FROM users WHERE email = $1", invite.email)
-        .fetch_optional(&mut *tran)
-        .await?;
+    let existing = sqlx::query_as!(
+        User,
+        r#"SELECT id, email, password, name, role as "role: Role", totp_secret FROM users WHERE email = $1"#,
+        invite.email
+    )
+    .fetch_optional(&mut *tran)
+    .await?;
     if existing.is_some() {
         return Err(AppError::inline(
             StatusCode::BAD_REQUEST,
@@ -72,26 +96,48 @@ pub async fn invite_user(
     }
 
     let id = sqlx::query_scalar!(
-        "INSERT INTO admin_invite (email) VALUES ($1) RETURNING id",
-        invite.email
+        "INSERT INTO admin_invite (email, role, event_id) VALUES ($1, $2, $3) RETURNING id",
+        invite.email,
+        invite.role as Role,
+        event_id,
     )
     .fetch_one(&mut *tran)
     .await?;
 
-    // Email
-    let message = InviteMessage {
-        user: user.name.clone(),
-        url: format!("{}/account/create/{}", config().url(), id),
+    // Email, using the event's own invite for coordinators if it has one
+    let url = format!("{}/account/create/{}", config().url(), id);
+    let custom = match event_id {
+        Some(event_id) => find_template(event_id, TemplateKind::Invite, &mut *tran)
+            .await?
+            .map(|custom| (event_id, custom)),
+        None => None,
+    };
+    let (subject, message) = match custom {
+        Some((event_id, custom)) => {
+            let variables = Variables::for_event(event_id, &mut *tran)
+                .await?
+                .set("inviter", user.name)
+                .set("link", url);
+            custom.render(&variables)?
+        }
+        None => (
+            format!(
+                "{} has invited you to join the Cornerstone Cafe!",
+                user.name
+            ),
+            InviteMessage {
+                user: user.name.clone(),
+                url,
+            }
+            .render()?,
+        ),
     };
     sqlx::query!(
         "INSERT INTO email (status, kind, address, subject, message)
         VALUES ('pending', 'html', $1, $2, $3)",
         invite.email,
-        format!(
-            "{} has invited you to join the Cornerstone Cafe!",
-            user.name
-        ),
-        message.render()?,
+        subject,
+        message,
     )
     .execute(&mut *tran)
     .await?;
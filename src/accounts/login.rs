@@ -1,8 +1,10 @@
+use std::net::SocketAddr;
+
 use askama::Template;
 use axum::{
-    extract::{Query, RawQuery},
+    extract::{ConnectInfo, Query, RawQuery},
     http::StatusCode,
-    response::IntoResponse,
+    response::{IntoResponse, Response},
     Form,
 };
 use cafe_website::{templates::Card, AppError, Redirect};
@@ -14,7 +16,16 @@ use serde::{Deserialize, Serialize};
 use tokio::task::spawn_blocking;
 use tracing::info;
 
-use crate::{config, models::User, session::Session};
+use crate::{
+    config,
+    models::{Role, User},
+    session::Session,
+};
+
+use super::{
+    throttle,
+    two_factor::{self, CodeForm},
+};
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct LoginParams {
@@ -50,9 +61,10 @@ pub async fn login_form(RawQuery(query): RawQuery) -> impl IntoResponse {
 
 pub async fn login(
     session: Session,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
     Query(params): Query<LoginParams>,
     Form(login): Form<LoginRequest>,
-) -> Result<impl IntoResponse, AppError> {
+) -> Result<Response, AppError> {
     let con = config().pool();
 
     if session.is_authenticated() {
@@ -62,20 +74,51 @@ pub async fn login(
         ));
     }
 
-    let user = sqlx::query_as!(User, "SELECT * FROM users WHERE email = $1", login.email)
-        .fetch_one(con)
-        .await
-        .map_err(login_err)?;
+    // Attempts are tracked by lowercased email so changing case doesn't get
+    // around the limits
+    let (email, ip) = (login.email.to_lowercase(), addr.ip().to_string());
+    throttle::check(&email, &ip).await?;
+
+    let user = sqlx::query_as!(
+        User,
+        r#"SELECT id, email, password, name, role as "role: Role", totp_secret FROM users WHERE email = $1"#,
+        login.email
+    )
+    .fetch_optional(con)
+    .await?;
 
     // Check password
-    let pw_hash = user.password.clone();
-    spawn_blocking(move || {
-        let hash = PasswordHash::new(&pw_hash)?;
-        Scrypt
-            .verify_password(login.password.as_bytes(), &hash)
-            .map_err(login_err)
-    })
-    .await??;
+    let verified = match &user {
+        Some(user) => {
+            let pw_hash = user.password.clone();
+            spawn_blocking(move || -> Result<bool, AppError> {
+                let hash = PasswordHash::new(&pw_hash)?;
+                Ok(Scrypt
+                    .verify_password(login.password.as_bytes(), &hash)
+                    .is_ok())
+            })
+            .await??
+        }
+        None => false,
+    };
+    let user = match user {
+        Some(user) if verified => user,
+        user => {
+            throttle::record_failure(&email, &ip, user.map(|u| (u.id, u.name))).await?;
+            return Err(login_err(()));
+        }
+    };
+
+    // The attempt only counts as a success once the second factor is checked
+    if user.totp_secret.is_some() {
+        session.set_pending_user(user.id).await?;
+        return Ok(Redirect::to(format!(
+            "/login/verify?{}",
+            serde_urlencoded::to_string(&params)?
+        ))
+        .into_response());
+    }
+    throttle::record_success(&email, &ip).await?;
 
     let user_name = user.name.clone();
 
@@ -83,6 +126,83 @@ pub async fn login(
 
     info!("{} logged in", user_name);
 
+    Ok((
+        [("HX-Trigger", "auth-change".to_owned())],
+        Redirect::to(params.from.unwrap_or("/".to_string())),
+    )
+        .into_response())
+}
+
+#[derive(Template)]
+#[template(path = "accounts/verify.html")]
+struct VerifyTemplate {
+    query: String,
+}
+
+fn login_expired() -> AppError {
+    AppError::redirect(
+        StatusCode::UNAUTHORIZED,
+        "Please log in again",
+        "/login".to_owned(),
+    )
+}
+
+pub async fn verify_form(
+    session: Session,
+    RawQuery(query): RawQuery,
+) -> Result<impl IntoResponse, AppError> {
+    if session.pending_user_id().is_none() {
+        return Err(login_expired());
+    }
+    Ok(Card {
+        class: Some("w-fit"),
+        title: "Enter Your Code".to_owned(),
+        child: VerifyTemplate {
+            query: query.unwrap_or_default(),
+        },
+        show_x: false,
+    })
+}
+
+/// Finishes logging in with a code from the user's authenticator app or a
+/// recovery code
+pub async fn verify(
+    session: Session,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    Query(params): Query<LoginParams>,
+    Form(CodeForm { code }): Form<CodeForm>,
+) -> Result<impl IntoResponse, AppError> {
+    let user_id = session.pending_user_id().ok_or_else(login_expired)?;
+    let user = sqlx::query_as!(
+        User,
+        r#"SELECT id, email, password, name, role as "role: Role", totp_secret FROM users WHERE id = $1"#,
+        user_id
+    )
+    .fetch_one(config().pool())
+    .await?;
+
+    // Wrong codes count against the same limits as wrong passwords
+    let (email, ip) = (user.email.to_lowercase(), addr.ip().to_string());
+    throttle::check(&email, &ip).await?;
+
+    let mut tran = config().pool().begin().await?;
+    let verified = two_factor::verify_code(user.id, &code, &mut tran).await?;
+    tran.commit().await?;
+    if !verified {
+        throttle::record_failure(&email, &ip, Some((user.id, user.name))).await?;
+        return Err(AppError::inline(
+            StatusCode::BAD_REQUEST,
+            "That code isn't right",
+        ));
+    }
+    throttle::record_success(&email, &ip).await?;
+
+    let user_name = user.name.clone();
+
+    session.set_auth_user(user).await?;
+
+    info!("{} logged in with a second factor", user_name);
+
     Ok((
         [("HX-Trigger", "auth-change".to_owned())],
         Redirect::to(params.from.unwrap_or("/".to_string())),
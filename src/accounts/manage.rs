@@ -1,36 +1,98 @@
+use std::collections::HashMap;
+
 use askama::Template;
 
 use askama_axum::IntoResponse;
-use axum::{extract::Path, http::StatusCode};
+use axum::{extract::Path, http::StatusCode, Form};
 use cafe_website::{AppError, Redirect};
+use serde::Deserialize;
 use uuid::Uuid;
 
-use crate::{config, models::User, session::Session};
+use crate::{
+    config,
+    models::{Event, Role, User},
+    session::Session,
+};
 
 #[derive(Template)]
 #[template(path = "accounts/admin.html")]
-pub struct UserAdminTempl {}
+pub struct UserAdminTempl {
+    roles: [Role; 4],
+}
+
+impl UserAdminTempl {
+    fn is_default(&self, role: &Role) -> bool {
+        *role == Role::default()
+    }
+}
+
+pub struct UserRow {
+    user: User,
+    /// The events a coordinator can edit
+    events: Vec<Event>,
+}
+
+impl UserRow {
+    fn has_role(&self, role: &Role) -> bool {
+        self.user.role == *role
+    }
+
+    fn is_coordinator(&self) -> bool {
+        self.user.role == Role::Coordinator
+    }
+
+    fn coordinates(&self, event: &Event) -> bool {
+        self.events.iter().any(|e| e.id == event.id)
+    }
+}
 
 #[derive(Template)]
 #[template(path = "accounts/user_list.html")]
 pub struct UserListTempl {
-    users: Vec<User>,
+    users: Vec<UserRow>,
+    events: Vec<Event>,
+    roles: [Role; 4],
     current: Uuid,
 }
 
 pub async fn admin() -> UserAdminTempl {
-    UserAdminTempl {}
+    UserAdminTempl { roles: Role::ALL }
 }
 
 pub async fn user_list(session: Session) -> Result<UserListTempl, AppError> {
     let Some(user) = session.user_id() else {
         unreachable!()
     };
-    let users = sqlx::query_as!(User, "SELECT * FROM users")
+    let users = sqlx::query_as!(
+        User,
+        r#"SELECT id, email, password, name, role as "role: Role", totp_secret FROM users ORDER BY name"#
+    )
+    .fetch_all(config().pool())
+    .await?;
+    let events = sqlx::query_as!(Event, "SELECT * FROM event ORDER BY name")
+        .fetch_all(config().pool())
+        .await?;
+    let coordinated = sqlx::query!("SELECT user_id, event_id FROM event_coordinator")
         .fetch_all(config().pool())
         .await?;
+
+    let mut by_user: HashMap<Uuid, Vec<Event>> = HashMap::new();
+    for row in coordinated {
+        if let Some(event) = events.iter().find(|e| e.id == row.event_id) {
+            by_user.entry(row.user_id).or_default().push(event.clone());
+        }
+    }
+
     Ok(UserListTempl {
-        users,
+        users: users
+            .into_iter()
+            .map(|user| UserRow {
+                events: by_user.remove(&user.id).unwrap_or_default(),
+                user,
+            })
+            .collect(),
+        events,
+        roles: Role::ALL,
         current: user,
     })
 }
@@ -53,3 +115,72 @@ pub async fn remove_user(
         .await?;
     Ok(Redirect::to("/account/manage".to_owned()))
 }
+
+#[derive(Deserialize)]
+pub struct RoleForm {
+    role: Role,
+}
+
+pub async fn set_role(
+    Path(id): Path<Uuid>,
+    session: Session,
+    Form(RoleForm { role }): Form<RoleForm>,
+) -> Result<impl IntoResponse, AppError> {
+    let Some(user_id) = session.user_id() else {
+        unreachable!()
+    };
+    // Stops the last owner from locking everyone out
+    if id == user_id {
+        return Err(AppError::inline(
+            StatusCode::BAD_REQUEST,
+            "You may not change your own role",
+        ));
+    }
+
+    let mut tran = config().pool().begin().await?;
+    sqlx::query!("UPDATE users SET role = $1 WHERE id = $2", role as Role, id)
+        .execute(&mut *tran)
+        .await?;
+    if role != Role::Coordinator {
+        sqlx::query!("DELETE FROM event_coordinator WHERE user_id = $1", id)
+            .execute(&mut *tran)
+            .await?;
+    }
+    tran.commit().await?;
+
+    Ok(Redirect::to("/account/manage".to_owned()))
+}
+
+#[derive(Deserialize)]
+pub struct CoordinatorForm {
+    event_id: Uuid,
+}
+
+pub async fn add_coordinator_event(
+    Path(id): Path<Uuid>,
+    Form(CoordinatorForm { event_id }): Form<CoordinatorForm>,
+) -> Result<impl IntoResponse, AppError> {
+    sqlx::query!(
+        "INSERT INTO event_coordinator (user_id, event_id)
+        SELECT id, $2 FROM users WHERE id = $1 AND role = 'coordinator'
+        ON CONFLICT DO NOTHING",
+        id,
+        event_id
+    )
+    .execute(config().pool())
+    .await?;
+    Ok(Redirect::to("/account/manage".to_owned()))
+}
+
+pub async fn remove_coordinator_event(
+    Path((id, event_id)): Path<(Uuid, Uuid)>,
+) -> Result<impl IntoResponse, AppError> {
+    sqlx::query!(
+        "DELETE FROM event_coordinator WHERE user_id = $1 AND event_id = $2",
+        id,
+        event_id
+    )
+    .execute(config().pool())
+    .await?;
+    Ok(Redirect::to("/account/manage".to_owned()))
+}
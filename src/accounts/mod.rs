@@ -1,5 +1,6 @@
 use axum::{
-    routing::{delete, get},
+    middleware,
+    routing::{delete, get, post, put},
     Router,
 };
 
@@ -7,15 +8,30 @@ mod create;
 mod invite;
 mod login;
 mod manage;
+mod password;
+mod permission;
+mod sessions;
+mod throttle;
+mod two_factor;
 
 use create::{account_creation_form, create_account};
-pub use login::{login, login_form, logout};
+pub use login::{login, login_form, logout, verify, verify_form};
+pub use permission::{current_role, AnyEventEditor, EventEditor, Owner, Scheduler, ShiftEditor};
 
 pub fn public_router() -> Router {
-    Router::new().route(
-        "/create/:id",
-        get(account_creation_form).post(create_account),
-    )
+    Router::new()
+        .route(
+            "/create/:id",
+            get(account_creation_form).post(create_account),
+        )
+        .route(
+            "/forgot",
+            get(password::forgot_form).post(password::request_reset),
+        )
+        .route(
+            "/reset/:id",
+            get(password::reset_form).post(password::reset_password),
+        )
 }
 
 pub fn protected_router() -> Router {
@@ -27,5 +43,31 @@ pub fn protected_router() -> Router {
             get(invite::invite_list).post(invite::invite_user),
         )
         .route("/invites/:id", delete(invite::cancel_invite))
+        .route("/attempts", get(throttle::attempt_list))
+        .route("/:id/two_factor", delete(two_factor::reset))
         .route("/:id", delete(manage::remove_user))
+        .route("/:id/role", put(manage::set_role))
+        .route("/:id/events", post(manage::add_coordinator_event))
+        .route(
+            "/:id/events/:event_id",
+            delete(manage::remove_coordinator_event),
+        )
+        // Only owners can manage users
+        .route_layer(middleware::from_extractor::<Owner>())
+        .route(
+            "/password",
+            get(password::change_form).post(password::change_password),
+        )
+        .route(
+            "/two_factor",
+            get(two_factor::two_factor)
+                .post(two_factor::enable)
+                .delete(two_factor::disable),
+        )
+        .route("/two_factor/recovery", post(two_factor::regenerate_codes))
+        .route(
+            "/sessions",
+            get(sessions::session_list).delete(sessions::revoke_others),
+        )
+        .route("/sessions/:id", delete(sessions::revoke))
 }
@@ -0,0 +1,270 @@
+use std::net::SocketAddr;
+
+use askama::Template;
+use axum::{
+    extract::{ConnectInfo, Path},
+    http::StatusCode,
+    Form,
+};
+use cafe_website::{templates::Card, AppError, Redirect};
+use chrono::{Duration, Utc};
+use scrypt::{
+    password_hash::{
+        self, rand_core::OsRng, PasswordHash, PasswordHasher, PasswordVerifier, SaltString,
+    },
+    Scrypt,
+};
+use serde::Deserialize;
+use tokio::task::spawn_blocking;
+use tracing::info;
+use uuid::Uuid;
+
+use crate::{
+    config,
+    session::{end_user_sessions, Session},
+};
+
+use super::throttle;
+
+/// How long a reset link works for
+const RESET_MINUTES: i64 = 60;
+
+fn expired() -> AppError {
+    AppError::redirect(
+        StatusCode::UNAUTHORIZED,
+        "Your link has expired",
+        "/account/forgot".to_owned(),
+    )
+}
+
+async fn hash_password(password: String) -> Result<String, AppError> {
+    Ok(spawn_blocking(move || -> password_hash::Result<String> {
+        let salt = SaltString::generate(&mut OsRng);
+        Ok(Scrypt
+            .hash_password(password.as_bytes(), &salt)?
+            .to_string())
+    })
+    .await??)
+}
+
+fn check_new_password(password: &str) -> Result<(), AppError> {
+    if password.is_empty() {
+        return Err(AppError::inline(
+            StatusCode::BAD_REQUEST,
+            "Enter a new password",
+        ));
+    }
+    Ok(())
+}
+
+#[derive(Template)]
+#[template(path = "accounts/forgot.html")]
+pub enum ForgotForm {
+    Empty,
+    Sent(String),
+}
+
+pub async fn forgot_form() -> Card<ForgotForm> {
+    Card {
+        class: Some("w-fit"),
+        title: "Forgot Password".to_owned(),
+        child: ForgotForm::Empty,
+        show_x: false,
+    }
+}
+
+#[derive(Template)]
+#[template(path = "email/messages/password_reset.html")]
+struct ResetMessage {
+    name: String,
+    url: String,
+    minutes: i64,
+}
+
+#[derive(Deserialize)]
+pub struct ForgotRequest {
+    email: String,
+}
+
+/// Requests are rate limited, so the form can't be used to flood someone's
+/// inbox
+pub async fn request_reset(
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    Form(req): Form<ForgotRequest>,
+) -> Result<ForgotForm, AppError> {
+    let (email, ip) = (req.email.to_lowercase(), addr.ip().to_string());
+    throttle::check_reset(&email, &ip).await?;
+
+    let mut tran = config().pool().begin().await?;
+    let user = sqlx::query!(
+        "SELECT id, name, email FROM users WHERE lower(email) = $1",
+        email
+    )
+    .fetch_optional(&mut *tran)
+    .await?;
+
+    // Respond the same way whether or not there's an account, so the form
+    // can't be used to find out who has one
+    if let Some(user) = user {
+        // Only the newest link works
+        sqlx::query!(
+            "UPDATE password_reset SET expires_at = now()
+            WHERE user_id = $1 AND used_at IS NULL AND expires_at > now()",
+            user.id
+        )
+        .execute(&mut *tran)
+        .await?;
+        let id = sqlx::query_scalar!(
+            "INSERT INTO password_reset (user_id, expires_at) VALUES ($1, $2) RETURNING id",
+            user.id,
+            Utc::now() + Duration::minutes(RESET_MINUTES),
+        )
+        .fetch_one(&mut *tran)
+        .await?;
+        let message = ResetMessage {
+            name: user.name,
+            url: format!("{}/account/reset/{}", config().url(), id),
+            minutes: RESET_MINUTES,
+        };
+        sqlx::query!(
+            "INSERT INTO email (status, kind, address, subject, message)
+            VALUES ('pending', 'html', $1, $2, $3)",
+            user.email,
+            "Reset your Cornerstone Cafe password",
+            message.render()?,
+        )
+        .execute(&mut *tran)
+        .await?;
+        info!("Sent a password reset link to user {}", user.id);
+    }
+    tran.commit().await?;
+
+    Ok(ForgotForm::Sent(req.email))
+}
+
+#[derive(Template)]
+#[template(path = "accounts/reset.html")]
+pub struct ResetTemplate {
+    reset_id: Uuid,
+}
+
+pub async fn reset_form(Path(reset_id): Path<Uuid>) -> Result<Card<ResetTemplate>, AppError> {
+    sqlx::query_scalar!(
+        "SELECT id FROM password_reset
+        WHERE id = $1 AND used_at IS NULL AND expires_at > now()",
+        reset_id
+    )
+    .fetch_optional(config().pool())
+    .await?
+    .ok_or_else(expired)?;
+
+    Ok(Card {
+        class: Some("w-fit"),
+        title: "Reset Password".to_owned(),
+        child: ResetTemplate { reset_id },
+        show_x: false,
+    })
+}
+
+#[derive(Deserialize)]
+pub struct ResetRequest {
+    password: String,
+}
+
+pub async fn reset_password(
+    Path(reset_id): Path<Uuid>,
+    Form(req): Form<ResetRequest>,
+) -> Result<Redirect, AppError> {
+    check_new_password(&req.password)?;
+    let password = hash_password(req.password).await?;
+
+    let mut tran = config().pool().begin().await?;
+    let user_id = sqlx::query_scalar!(
+        "UPDATE password_reset SET used_at = now()
+        WHERE id = $1 AND used_at IS NULL AND expires_at > now()
+        RETURNING user_id",
+        reset_id
+    )
+    .fetch_optional(&mut *tran)
+    .await?
+    .ok_or_else(expired)?;
+    sqlx::query!(
+        "UPDATE users SET password = $1 WHERE id = $2",
+        password,
+        user_id
+    )
+    .execute(&mut *tran)
+    .await?;
+    // Whoever knew the old password shouldn't stay logged in
+    let ended = end_user_sessions(user_id, None, &mut tran).await?;
+    tran.commit().await?;
+    info!(
+        "User {} reset their password, ending {} sessions",
+        user_id, ended
+    );
+
+    Ok(Redirect::to("/login".to_owned()))
+}
+
+#[derive(Template)]
+#[template(path = "accounts/change_password.html")]
+pub struct ChangeTemplate {}
+
+pub async fn change_form() -> Card<ChangeTemplate> {
+    Card {
+        class: Some("w-fit"),
+        title: "Change Password".to_owned(),
+        child: ChangeTemplate {},
+        show_x: false,
+    }
+}
+
+#[derive(Deserialize)]
+pub struct ChangeRequest {
+    current: String,
+    password: String,
+}
+
+pub async fn change_password(
+    session: Session,
+    Form(req): Form<ChangeRequest>,
+) -> Result<&'static str, AppError> {
+    let Some(user_id) = session.user_id() else {
+        unreachable!()
+    };
+    check_new_password(&req.password)?;
+
+    let pw_hash = sqlx::query_scalar!("SELECT password FROM users WHERE id = $1", user_id)
+        .fetch_one(config().pool())
+        .await?;
+    spawn_blocking(move || {
+        let hash = PasswordHash::new(&pw_hash)?;
+        Scrypt
+            .verify_password(req.current.as_bytes(), &hash)
+            .map_err(|_| {
+                AppError::inline(
+                    StatusCode::BAD_REQUEST,
+                    "Your current password is incorrect",
+                )
+            })
+    })
+    .await??;
+    let password = hash_password(req.password).await?;
+
+    let mut tran = config().pool().begin().await?;
+    sqlx::query!(
+        "UPDATE users SET password = $1 WHERE id = $2",
+        password,
+        user_id
+    )
+    .execute(&mut *tran)
+    .await?;
+    let ended = end_user_sessions(user_id, Some(session.id()), &mut tran).await?;
+    tran.commit().await?;
+    info!(
+        "User {} changed their password, ending {} other sessions",
+        user_id, ended
+    );
+
+    Ok("Your password has been changed, and you've been logged out everywhere else")
+}
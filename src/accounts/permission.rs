@@ -0,0 +1,193 @@
+use axum::{
+    async_trait,
+    extract::{FromRequestParts, RawPathParams},
+    http::{request::Parts, StatusCode},
+};
+use cafe_website::AppError;
+use uuid::Uuid;
+
+use crate::{config, models::Role, session::Session};
+
+fn forbidden() -> AppError {
+    AppError::inline(
+        StatusCode::FORBIDDEN,
+        "You don't have permission to do that",
+    )
+}
+
+/// Returns the role of the user logged in on the session, if there is one
+pub async fn current_role(session: &Session) -> Result<Option<Role>, sqlx::Error> {
+    let Some(user_id) = session.user_id() else {
+        return Ok(None);
+    };
+    sqlx::query_scalar!(
+        r#"SELECT role as "role: Role" FROM users WHERE id = $1"#,
+        user_id
+    )
+    .fetch_optional(config().pool())
+    .await
+}
+
+async fn user_role<S: Send + Sync>(parts: &mut Parts, state: &S) -> Result<(Uuid, Role), AppError> {
+    let session = Session::from_request_parts(parts, state).await?;
+    let user_id = session.user_id().ok_or_else(forbidden)?;
+    let role = current_role(&session).await?.ok_or_else(forbidden)?;
+    Ok((user_id, role))
+}
+
+/// The `:id` segment of the matched route
+async fn path_id<S: Send + Sync>(parts: &mut Parts, state: &S) -> Result<Uuid, AppError> {
+    let params = RawPathParams::from_request_parts(parts, state)
+        .await
+        .map_err(|_| cafe_website::error::ISE)?;
+    params
+        .iter()
+        .find(|(key, _)| *key == "id")
+        .and_then(|(_, value)| value.parse().ok())
+        .ok_or(cafe_website::error::NOT_FOUND)
+}
+
+/// Only allows owners, who manage the other users. Use as an extractor or with
+/// `middleware::from_extractor` on a route.
+pub struct Owner;
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for Owner {
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        match user_role(parts, state).await? {
+            (_, Role::Owner) => Ok(Owner),
+            _ => Err(forbidden()),
+        }
+    }
+}
+
+/// Allows owners and schedulers, for changes that aren't tied to one event
+pub struct Scheduler;
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for Scheduler {
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        match user_role(parts, state).await? {
+            (_, role) if role.can_schedule() => Ok(Scheduler),
+            _ => Err(forbidden()),
+        }
+    }
+}
+
+/// Allows anyone who can change the event in the route's `:id`, which includes
+/// its coordinators
+pub struct EventEditor;
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for EventEditor {
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let (user_id, role) = user_role(parts, state).await?;
+        if role.can_schedule() {
+            return Ok(EventEditor);
+        }
+        if role != Role::Coordinator {
+            return Err(forbidden());
+        }
+        let event_id = path_id(parts, state).await?;
+        if coordinates_event(user_id, event_id).await? {
+            Ok(EventEditor)
+        } else {
+            Err(forbidden())
+        }
+    }
+}
+
+async fn coordinates_event(user_id: Uuid, event_id: Uuid) -> Result<bool, sqlx::Error> {
+    sqlx::query_scalar!(
+        r#"SELECT EXISTS (
+            SELECT 1 FROM event_coordinator WHERE user_id = $1 AND event_id = $2
+        ) as "exists!""#,
+        user_id,
+        event_id
+    )
+    .fetch_one(config().pool())
+    .await
+}
+
+/// Allows owners, schedulers and coordinators, for routes where the event isn't
+/// in the path. Handlers check the event once they know it with
+/// [`AnyEventEditor::check`].
+pub struct AnyEventEditor {
+    user_id: Uuid,
+    can_schedule: bool,
+}
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for AnyEventEditor {
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        match user_role(parts, state).await? {
+            (user_id, role) if role.can_schedule() || role == Role::Coordinator => {
+                Ok(AnyEventEditor {
+                    user_id,
+                    can_schedule: role.can_schedule(),
+                })
+            }
+            _ => Err(forbidden()),
+        }
+    }
+}
+
+impl AnyEventEditor {
+    /// Errors unless the user can change the event
+    pub async fn check(&self, event_id: Uuid) -> Result<(), AppError> {
+        if self.can_schedule || coordinates_event(self.user_id, event_id).await? {
+            Ok(())
+        } else {
+            Err(forbidden())
+        }
+    }
+
+    /// The coordinator to limit lists to their own events, or `None` if the
+    /// user can see every event
+    pub fn coordinator_id(&self) -> Option<Uuid> {
+        (!self.can_schedule).then_some(self.user_id)
+    }
+}
+
+/// Allows anyone who can change the event of the shift in the route's `:id`
+pub struct ShiftEditor;
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for ShiftEditor {
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let (user_id, role) = user_role(parts, state).await?;
+        if role.can_schedule() {
+            return Ok(ShiftEditor);
+        }
+        if role != Role::Coordinator {
+            return Err(forbidden());
+        }
+        let shift_id = path_id(parts, state).await?;
+        let coordinates = sqlx::query_scalar!(
+            r#"SELECT EXISTS (
+                SELECT 1 FROM event_coordinator as ec
+                INNER JOIN shift as s ON s.event_id = ec.event_id
+                WHERE ec.user_id = $1 AND s.id = $2
+            ) as "exists!""#,
+            user_id,
+            shift_id
+        )
+        .fetch_one(config().pool())
+        .await?;
+        if coordinates {
+            Ok(ShiftEditor)
+        } else {
+            Err(forbidden())
+        }
+    }
+}
@@ -0,0 +1,154 @@
+use askama::Template;
+use axum::{extract::Path, http::StatusCode};
+use cafe_website::{filters, templates::Card, AppError, Redirect};
+use chrono::{DateTime, NaiveDateTime};
+use chrono_tz::Tz;
+use tracing::info;
+use uuid::Uuid;
+
+use crate::{
+    config,
+    session::{end_user_sessions, Session},
+};
+
+/// A rough description of the browser and system from a user agent string,
+/// good enough for someone to recognise their own devices
+fn describe_device(user_agent: &str) -> String {
+    let browser = [
+        ("Edg/", "Edge"),
+        ("OPR/", "Opera"),
+        ("Firefox/", "Firefox"),
+        ("Chrome/", "Chrome"),
+        ("Safari/", "Safari"),
+    ]
+    .into_iter()
+    .find(|(token, _)| user_agent.contains(token))
+    .map(|(_, name)| name);
+    let system = [
+        ("iPhone", "iPhone"),
+        ("iPad", "iPad"),
+        ("Android", "Android"),
+        ("Windows", "Windows"),
+        ("Mac OS X", "Mac"),
+        ("CrOS", "ChromeOS"),
+        ("Linux", "Linux"),
+    ]
+    .into_iter()
+    .find(|(token, _)| user_agent.contains(token))
+    .map(|(_, name)| name);
+
+    match (browser, system) {
+        (Some(browser), Some(system)) => format!("{browser} on {system}"),
+        (Some(name), None) | (None, Some(name)) => name.to_owned(),
+        (None, None) => "Unknown device".to_owned(),
+    }
+}
+
+fn local(at: NaiveDateTime) -> DateTime<Tz> {
+    at.and_utc().with_timezone(&config().timezone())
+}
+
+pub struct ActiveSession {
+    id: Uuid,
+    created_at: DateTime<Tz>,
+    last_seen_at: DateTime<Tz>,
+    ip: Option<String>,
+    device: String,
+}
+
+#[derive(Template)]
+#[template(path = "accounts/sessions.html")]
+pub struct SessionsTemplate {
+    sessions: Vec<ActiveSession>,
+    current: Uuid,
+    created_at: DateTime<Tz>,
+    expires_at: DateTime<Tz>,
+}
+
+impl SessionsTemplate {
+    fn is_current(&self, session: &ActiveSession) -> bool {
+        session.id == self.current
+    }
+}
+
+/// Lists where the user is logged in, so they can log out of anywhere they
+/// don't recognise
+pub async fn session_list(session: Session) -> Result<Card<SessionsTemplate>, AppError> {
+    let Some(user_id) = session.user_id() else {
+        unreachable!()
+    };
+    // Only sessions that haven't expired get this far
+    let Some(expires_at) = session.expires_at() else {
+        unreachable!()
+    };
+    let sessions = sqlx::query!(
+        "SELECT id, created_at, last_seen_at, ip, user_agent FROM session
+        WHERE user_id = $1 AND expires_at > now()
+            AND last_seen_at > now() - make_interval(hours => $2)
+        ORDER BY last_seen_at DESC",
+        user_id,
+        config().sessions.idle_hours as i32,
+    )
+    .fetch_all(config().pool())
+    .await?
+    .into_iter()
+    .map(|row| ActiveSession {
+        id: row.id,
+        created_at: local(row.created_at),
+        last_seen_at: local(row.last_seen_at),
+        ip: row.ip,
+        device: row
+            .user_agent
+            .as_deref()
+            .map_or_else(|| "Unknown device".to_owned(), describe_device),
+    })
+    .collect();
+
+    Ok(Card {
+        class: Some("w-fit"),
+        title: "Active Sessions".to_owned(),
+        child: SessionsTemplate {
+            sessions,
+            current: session.id(),
+            created_at: local(session.created_at()),
+            expires_at: local(expires_at),
+        },
+        show_x: false,
+    })
+}
+
+pub async fn revoke(Path(id): Path<Uuid>, session: Session) -> Result<Redirect, AppError> {
+    let Some(user_id) = session.user_id() else {
+        unreachable!()
+    };
+    if id == session.id() {
+        return Err(AppError::inline(
+            StatusCode::BAD_REQUEST,
+            "Log out to end this session",
+        ));
+    }
+    // Only ends the user's own sessions
+    sqlx::query!(
+        "UPDATE session SET expires_at = now()
+        WHERE id = $1 AND user_id = $2 AND expires_at > now()",
+        id,
+        user_id
+    )
+    .execute(config().pool())
+    .await?;
+    info!("User {} ended session {}", user_id, id);
+
+    Ok(Redirect::to("/account/sessions".to_owned()))
+}
+
+pub async fn revoke_others(session: Session) -> Result<Redirect, AppError> {
+    let Some(user_id) = session.user_id() else {
+        unreachable!()
+    };
+    let mut tran = config().pool().begin().await?;
+    let ended = end_user_sessions(user_id, Some(session.id()), &mut tran).await?;
+    tran.commit().await?;
+    info!("User {} ended {} other sessions", user_id, ended);
+
+    Ok(Redirect::to("/account/sessions".to_owned()))
+}
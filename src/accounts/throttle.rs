@@ -0,0 +1,242 @@
+use askama::Template;
+use axum::http::StatusCode;
+use cafe_website::{filters, AppError};
+use chrono::{DateTime, Duration, Utc};
+use chrono_tz::Tz;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use crate::config;
+
+/// Failures older than this are forgotten
+const WINDOW_MINUTES: i64 = 60;
+/// Failures allowed before having to wait between attempts
+const FREE_ATTEMPTS: i64 = 3;
+/// Failures before an email address is locked out
+const EMAIL_LOCKOUT: i64 = 10;
+/// Failures before an ip address is locked out. Higher than for an email so a
+/// shared connection doesn't lock out everyone on it as quickly.
+const IP_LOCKOUT: i64 = 30;
+const LOCKOUT_MINUTES: i64 = 15;
+/// The longest wait between attempts before being locked out
+const MAX_DELAY_SECONDS: i64 = 5 * 60;
+
+fn too_many() -> AppError {
+    AppError::inline(
+        StatusCode::TOO_MANY_REQUESTS,
+        "Too many failed attempts. Please wait and try again",
+    )
+}
+
+/// How long to wait after the last of `failures` failed attempts. Doubles with
+/// each failure, until enough failures lock the account or address out.
+fn delay(failures: i64, lockout: i64) -> Duration {
+    if failures < FREE_ATTEMPTS {
+        Duration::zero()
+    } else if failures >= lockout {
+        Duration::minutes(LOCKOUT_MINUTES)
+    } else {
+        let exponent = (failures - FREE_ATTEMPTS).min(16) as u32;
+        Duration::seconds(2_i64.pow(exponent).min(MAX_DELAY_SECONDS))
+    }
+}
+
+struct Failures {
+    count: i64,
+    last: Option<DateTime<Utc>>,
+}
+
+impl Failures {
+    fn blocked_until(&self, lockout: i64) -> Option<DateTime<Utc>> {
+        self.last
+            .map(|last| last + delay(self.count, lockout))
+            .filter(|until| *until > Utc::now())
+    }
+}
+
+/// Recent failures for an email, since its last successful login
+async fn email_failures(email: &str) -> Result<Failures, sqlx::Error> {
+    let row = sqlx::query!(
+        r#"SELECT count(*) as "count!", max(created_at) as last FROM login_attempt
+        WHERE email = $1 AND NOT success
+            AND created_at > now() - make_interval(mins => $2)
+            AND created_at > coalesce(
+                (SELECT max(created_at) FROM login_attempt WHERE email = $1 AND success),
+                '-infinity'
+            )"#,
+        email,
+        WINDOW_MINUTES as i32,
+    )
+    .fetch_one(config().pool())
+    .await?;
+    Ok(Failures {
+        count: row.count,
+        last: row.last,
+    })
+}
+
+/// Recent failures from an ip address, since its last successful login
+async fn ip_failures(ip: &str) -> Result<Failures, sqlx::Error> {
+    let row = sqlx::query!(
+        r#"SELECT count(*) as "count!", max(created_at) as last FROM login_attempt
+        WHERE ip = $1 AND NOT success
+            AND created_at > now() - make_interval(mins => $2)
+            AND created_at > coalesce(
+                (SELECT max(created_at) FROM login_attempt WHERE ip = $1 AND success),
+                '-infinity'
+            )"#,
+        ip,
+        WINDOW_MINUTES as i32,
+    )
+    .fetch_one(config().pool())
+    .await?;
+    Ok(Failures {
+        count: row.count,
+        last: row.last,
+    })
+}
+
+/// Errors if the email or ip address has to wait before trying again. Checked
+/// before the password so blocked attempts don't cost a hash.
+pub async fn check(email: &str, ip: &str) -> Result<(), AppError> {
+    let (by_email, by_ip) = tokio::try_join!(email_failures(email), ip_failures(ip))?;
+    if by_email.blocked_until(EMAIL_LOCKOUT).is_some() || by_ip.blocked_until(IP_LOCKOUT).is_some()
+    {
+        info!("Blocked a login attempt for {} from {}", email, ip);
+        return Err(too_many());
+    }
+    Ok(())
+}
+
+pub async fn record_success(email: &str, ip: &str) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "INSERT INTO login_attempt (email, ip, success) VALUES ($1, $2, true)",
+        email,
+        ip
+    )
+    .execute(config().pool())
+    .await?;
+    Ok(())
+}
+
+#[derive(Template)]
+#[template(path = "email/messages/lockout.html")]
+struct LockoutMessage {
+    name: String,
+    ip: String,
+    minutes: i64,
+    reset_url: String,
+}
+
+/// Records a failed attempt, and lets the owner of the account know if it's
+/// now locked out
+pub async fn record_failure(
+    email: &str,
+    ip: &str,
+    user: Option<(Uuid, String)>,
+) -> Result<(), AppError> {
+    sqlx::query!(
+        "INSERT INTO login_attempt (email, ip, success) VALUES ($1, $2, false)",
+        email,
+        ip
+    )
+    .execute(config().pool())
+    .await?;
+
+    let Some((user_id, name)) = user else {
+        return Ok(());
+    };
+    if email_failures(email).await?.count != EMAIL_LOCKOUT {
+        return Ok(());
+    }
+    warn!(
+        "Locked out user {} after failed logins from {}",
+        user_id, ip
+    );
+    let message = LockoutMessage {
+        name,
+        ip: ip.to_owned(),
+        minutes: LOCKOUT_MINUTES,
+        reset_url: format!("{}/account/forgot", config().url()),
+    };
+    sqlx::query!(
+        "INSERT INTO email (status, kind, address, subject, message)
+        VALUES ('pending', 'html', $1, $2, $3)",
+        email,
+        "Your Cornerstone Cafe account has been locked",
+        message.render()?,
+    )
+    .execute(config().pool())
+    .await?;
+    Ok(())
+}
+
+/// Password reset requests allowed for an email within the window
+const RESET_EMAIL_LIMIT: i64 = 3;
+/// Password reset requests allowed from an ip address within the window
+const RESET_IP_LIMIT: i64 = 10;
+
+/// Errors if too many password resets have been asked for recently, then
+/// records this request. Counted apart from logins, so asking for resets can't
+/// lock anyone out.
+pub async fn check_reset(email: &str, ip: &str) -> Result<(), AppError> {
+    let row = sqlx::query!(
+        r#"SELECT
+            count(*) FILTER (WHERE email = $1) as "by_email!",
+            count(*) FILTER (WHERE ip = $2) as "by_ip!"
+        FROM reset_request
+        WHERE (email = $1 OR ip = $2) AND created_at > now() - make_interval(mins => $3)"#,
+        email,
+        ip,
+        WINDOW_MINUTES as i32,
+    )
+    .fetch_one(config().pool())
+    .await?;
+    if row.by_email >= RESET_EMAIL_LIMIT || row.by_ip >= RESET_IP_LIMIT {
+        info!("Blocked a password reset request for {} from {}", email, ip);
+        return Err(AppError::inline(
+            StatusCode::TOO_MANY_REQUESTS,
+            "Too many reset requests. Please wait and try again",
+        ));
+    }
+    sqlx::query!(
+        "INSERT INTO reset_request (email, ip) VALUES ($1, $2)",
+        email,
+        ip
+    )
+    .execute(config().pool())
+    .await?;
+    Ok(())
+}
+
+pub struct FailedAttempt {
+    at: DateTime<Tz>,
+    email: String,
+    ip: String,
+}
+
+#[derive(Template)]
+#[template(path = "accounts/attempt_list.html")]
+pub struct AttemptListTempl {
+    attempts: Vec<FailedAttempt>,
+}
+
+/// The most recent failed logins, for spotting anyone guessing passwords
+pub async fn attempt_list() -> Result<AttemptListTempl, AppError> {
+    let attempts = sqlx::query!(
+        "SELECT created_at, email, ip FROM login_attempt
+        WHERE NOT success
+        ORDER BY created_at DESC
+        LIMIT 50"
+    )
+    .fetch_all(config().pool())
+    .await?
+    .into_iter()
+    .map(|row| FailedAttempt {
+        at: row.created_at.with_timezone(&config().timezone()),
+        email: row.email,
+        ip: row.ip,
+    })
+    .collect();
+    Ok(AttemptListTempl { attempts })
+}
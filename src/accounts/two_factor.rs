@@ -0,0 +1,313 @@
+use askama::Template;
+use axum::{extract::Path, http::StatusCode, response::IntoResponse, Form};
+use cafe_website::{error::ISE, templates::Card, AppError, Redirect};
+use chrono::Utc;
+use qrcode::{render::svg, QrCode};
+use rand::{distributions::Slice, rngs::OsRng, Rng};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use sqlx::PgConnection;
+use totp_rs::{Algorithm, Secret, TOTP};
+use tracing::info;
+use uuid::Uuid;
+
+use crate::{config, session::Session};
+
+const ISSUER: &str = "Cornerstone Cafe";
+const STEP_SECONDS: u64 = 30;
+const RECOVERY_CODES: usize = 10;
+/// Leaves out characters that are easy to mix up
+const RECOVERY_ALPHABET: &[u8] = b"abcdefghjkmnpqrstuvwxyz23456789";
+
+fn totp(secret: &str, email: &str) -> Result<TOTP, AppError> {
+    let bytes = Secret::Encoded(secret.to_owned()).to_bytes().map_err(|e| {
+        tracing::error!("Invalid totp secret: {e:?}");
+        ISE
+    })?;
+    TOTP::new(
+        Algorithm::SHA1,
+        6,
+        1,
+        STEP_SECONDS,
+        bytes,
+        Some(ISSUER.to_owned()),
+        email.to_owned(),
+    )
+    .map_err(|e| {
+        tracing::error!("Couldn't set up totp: {e}");
+        ISE
+    })
+}
+
+/// The time step the code is for, allowing a step of clock drift either way
+fn code_step(totp: &TOTP, code: &str) -> Option<i64> {
+    let code: String = code.chars().filter(|c| !c.is_whitespace()).collect();
+    let now = Utc::now().timestamp() as u64 / STEP_SECONDS;
+    [now - 1, now, now + 1]
+        .into_iter()
+        .find(|step| totp.generate(step * STEP_SECONDS) == code)
+        .map(|step| step as i64)
+}
+
+fn hash_recovery_code(code: &str) -> String {
+    let normalized: String = code
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .map(|c| c.to_ascii_lowercase())
+        .collect();
+    format!("{:x}", Sha256::digest(normalized.as_bytes()))
+}
+
+/// Replaces the user's recovery codes with new ones, returning them. Only the
+/// hashes are kept, so this is the only time they can be shown.
+async fn replace_recovery_codes(
+    user_id: Uuid,
+    conn: &mut PgConnection,
+) -> Result<Vec<String>, sqlx::Error> {
+    let chars = Slice::new(RECOVERY_ALPHABET).expect("Alphabet isn't empty");
+    let codes: Vec<String> = (0..RECOVERY_CODES)
+        .map(|_| {
+            let code: String = OsRng
+                .sample_iter(&chars)
+                .take(10)
+                .map(|&c| c as char)
+                .collect();
+            format!("{}-{}", &code[..5], &code[5..])
+        })
+        .collect();
+
+    sqlx::query!("DELETE FROM recovery_code WHERE user_id = $1", user_id)
+        .execute(&mut *conn)
+        .await?;
+    for code in &codes {
+        sqlx::query!(
+            "INSERT INTO recovery_code (user_id, code_hash) VALUES ($1, $2)",
+            user_id,
+            hash_recovery_code(code)
+        )
+        .execute(&mut *conn)
+        .await?;
+    }
+    Ok(codes)
+}
+
+/// Checks a code from the user's authenticator app, or one of their unused
+/// recovery codes. Either can only be used once.
+pub async fn verify_code(
+    user_id: Uuid,
+    code: &str,
+    conn: &mut PgConnection,
+) -> Result<bool, AppError> {
+    let user = sqlx::query!(
+        "SELECT email, totp_secret, totp_last_step FROM users WHERE id = $1 FOR UPDATE",
+        user_id
+    )
+    .fetch_one(&mut *conn)
+    .await?;
+    let Some(secret) = user.totp_secret else {
+        return Ok(false);
+    };
+
+    if let Some(step) = code_step(&totp(&secret, &user.email)?, code) {
+        if user.totp_last_step.is_some_and(|last| step <= last) {
+            return Ok(false);
+        }
+        sqlx::query!(
+            "UPDATE users SET totp_last_step = $1 WHERE id = $2",
+            step,
+            user_id
+        )
+        .execute(&mut *conn)
+        .await?;
+        return Ok(true);
+    }
+
+    let recovered = sqlx::query_scalar!(
+        "UPDATE recovery_code SET used_at = now()
+        WHERE user_id = $1 AND code_hash = $2 AND used_at IS NULL
+        RETURNING id",
+        user_id,
+        hash_recovery_code(code)
+    )
+    .fetch_optional(&mut *conn)
+    .await?;
+    if recovered.is_some() {
+        info!("User {} used a recovery code", user_id);
+    }
+    Ok(recovered.is_some())
+}
+
+fn invalid_code() -> AppError {
+    AppError::inline(StatusCode::BAD_REQUEST, "That code isn't right")
+}
+
+#[derive(Template)]
+#[template(path = "accounts/two_factor.html")]
+pub enum TwoFactorTemplate {
+    Enroll { secret: String, qr: String },
+    Enabled { remaining: i64 },
+    Codes(Vec<String>),
+}
+
+/// Shows new recovery codes in place of the whole page, rather than where
+/// errors go
+fn show_codes(codes: Vec<String>) -> impl IntoResponse {
+    (
+        [("HX-Retarget", "#two-factor"), ("HX-Reswap", "outerHTML")],
+        TwoFactorTemplate::Codes(codes),
+    )
+}
+
+fn card(child: TwoFactorTemplate) -> Card<TwoFactorTemplate> {
+    Card {
+        class: Some("w-fit"),
+        title: "Two Factor Authentication".to_owned(),
+        child,
+        show_x: false,
+    }
+}
+
+pub async fn two_factor(session: Session) -> Result<Card<TwoFactorTemplate>, AppError> {
+    let Some(user_id) = session.user_id() else {
+        unreachable!()
+    };
+    let user = sqlx::query!(
+        "SELECT email, totp_secret FROM users WHERE id = $1",
+        user_id
+    )
+    .fetch_one(config().pool())
+    .await?;
+
+    if user.totp_secret.is_some() {
+        let remaining = sqlx::query_scalar!(
+            r#"SELECT count(*) as "count!" FROM recovery_code
+            WHERE user_id = $1 AND used_at IS NULL"#,
+            user_id
+        )
+        .fetch_one(config().pool())
+        .await?;
+        return Ok(card(TwoFactorTemplate::Enabled { remaining }));
+    }
+
+    // A new secret each time the page is opened, until one is confirmed
+    let secret = Secret::generate_secret().to_encoded().to_string();
+    let url = totp(&secret, &user.email)?.get_url();
+    let qr = QrCode::new(url.as_bytes())
+        .map_err(|e| {
+            tracing::error!("Couldn't make a QR code: {e}");
+            ISE
+        })?
+        .render::<svg::Color>()
+        .min_dimensions(200, 200)
+        .build();
+    sqlx::query!(
+        "UPDATE users SET totp_pending_secret = $1 WHERE id = $2",
+        secret,
+        user_id
+    )
+    .execute(config().pool())
+    .await?;
+
+    Ok(card(TwoFactorTemplate::Enroll { secret, qr }))
+}
+
+#[derive(Deserialize)]
+pub struct CodeForm {
+    pub code: String,
+}
+
+/// Turns two factor on once the user shows their app is giving the right codes
+pub async fn enable(
+    session: Session,
+    Form(CodeForm { code }): Form<CodeForm>,
+) -> Result<impl IntoResponse, AppError> {
+    let Some(user_id) = session.user_id() else {
+        unreachable!()
+    };
+    let mut tran = config().pool().begin().await?;
+    let user = sqlx::query!(
+        "SELECT email, totp_pending_secret FROM users WHERE id = $1 FOR UPDATE",
+        user_id
+    )
+    .fetch_one(&mut *tran)
+    .await?;
+    let secret = user.totp_pending_secret.ok_or(AppError::inline(
+        StatusCode::BAD_REQUEST,
+        "Reload the page to start again",
+    ))?;
+    let step = code_step(&totp(&secret, &user.email)?, &code).ok_or_else(invalid_code)?;
+
+    sqlx::query!(
+        "UPDATE users
+        SET totp_secret = totp_pending_secret, totp_pending_secret = NULL, totp_last_step = $1
+        WHERE id = $2",
+        step,
+        user_id
+    )
+    .execute(&mut *tran)
+    .await?;
+    let codes = replace_recovery_codes(user_id, &mut tran).await?;
+    tran.commit().await?;
+    info!("User {} turned on two factor authentication", user_id);
+
+    Ok(show_codes(codes))
+}
+
+pub async fn regenerate_codes(
+    session: Session,
+    Form(CodeForm { code }): Form<CodeForm>,
+) -> Result<impl IntoResponse, AppError> {
+    let Some(user_id) = session.user_id() else {
+        unreachable!()
+    };
+    let mut tran = config().pool().begin().await?;
+    if !verify_code(user_id, &code, &mut tran).await? {
+        return Err(invalid_code());
+    }
+    let codes = replace_recovery_codes(user_id, &mut tran).await?;
+    tran.commit().await?;
+
+    Ok(show_codes(codes))
+}
+
+async fn turn_off(user_id: Uuid, conn: &mut PgConnection) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "UPDATE users SET totp_secret = NULL, totp_last_step = NULL WHERE id = $1",
+        user_id
+    )
+    .execute(&mut *conn)
+    .await?;
+    sqlx::query!("DELETE FROM recovery_code WHERE user_id = $1", user_id)
+        .execute(&mut *conn)
+        .await?;
+    Ok(())
+}
+
+pub async fn disable(
+    session: Session,
+    Form(CodeForm { code }): Form<CodeForm>,
+) -> Result<Redirect, AppError> {
+    let Some(user_id) = session.user_id() else {
+        unreachable!()
+    };
+    let mut tran = config().pool().begin().await?;
+    if !verify_code(user_id, &code, &mut tran).await? {
+        return Err(invalid_code());
+    }
+    turn_off(user_id, &mut tran).await?;
+    tran.commit().await?;
+    info!("User {} turned off two factor authentication", user_id);
+
+    Ok(Redirect::to("/account/two_factor".to_owned()))
+}
+
+/// Lets an owner turn off two factor for a user who lost their device and
+/// recovery codes
+pub async fn reset(Path(id): Path<Uuid>) -> Result<Redirect, AppError> {
+    let mut tran = config().pool().begin().await?;
+    turn_off(id, &mut tran).await?;
+    tran.commit().await?;
+    info!("Two factor authentication was reset for user {}", id);
+
+    Ok(Redirect::to("/account/manage".to_owned()))
+}
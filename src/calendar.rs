@@ -0,0 +1,214 @@
+use axum::{
+    http::header,
+    response::{IntoResponse, Response},
+};
+use chrono::{
+    DateTime, Duration, FixedOffset, NaiveDate, NaiveDateTime, NaiveTime, Offset, TimeZone, Utc,
+};
+use chrono_tz::{OffsetComponents, OffsetName, Tz};
+use uuid::Uuid;
+
+use crate::{config, models::Shift};
+
+pub const CONTENT_TYPE: &str = "text/calendar; charset=utf-8";
+
+/// A single VEVENT. Times are local to the configured timezone.
+#[derive(Debug, Clone)]
+pub struct CalendarEvent {
+    pub uid: Uuid,
+    pub date: NaiveDate,
+    pub start_time: NaiveTime,
+    pub end_time: NaiveTime,
+    pub summary: String,
+    pub description: Option<String>,
+    pub url: String,
+}
+
+impl From<&Shift> for CalendarEvent {
+    fn from(shift: &Shift) -> Self {
+        CalendarEvent {
+            uid: shift.id,
+            date: shift.date,
+            start_time: shift.start_time,
+            end_time: shift.end_time,
+            summary: shift.title.clone(),
+            description: shift.description.clone().filter(|d| !d.trim().is_empty()),
+            url: format!("{}/shift/{}", config().url(), shift.id),
+        }
+    }
+}
+
+/// An RFC 5545 calendar, rendered with a VTIMEZONE for `Config::timezone()`
+/// so clients show shifts at the right local time.
+#[derive(Debug, Clone)]
+pub struct Calendar {
+    pub name: String,
+    pub events: Vec<CalendarEvent>,
+}
+
+impl Calendar {
+    pub fn new(name: String, events: Vec<CalendarEvent>) -> Self {
+        Calendar { name, events }
+    }
+
+    pub fn render(&self) -> String {
+        let tz = config().timezone();
+        let stamp = format_utc(Utc::now());
+        let mut lines = vec![
+            "BEGIN:VCALENDAR".to_owned(),
+            "VERSION:2.0".to_owned(),
+            "PRODID:-//Cornerstone Cafe//Shifts//EN".to_owned(),
+            "CALSCALE:GREGORIAN".to_owned(),
+            "METHOD:PUBLISH".to_owned(),
+            format!("X-WR-CALNAME:{}", escape(&self.name)),
+            format!("X-WR-TIMEZONE:{}", tz.name()),
+        ];
+
+        let first = self.events.iter().map(|e| e.date).min();
+        let last = self.events.iter().map(|e| e.date).max();
+        if let Some((first, last)) = first.zip(last) {
+            lines.extend(vtimezone(tz, first, last));
+        }
+
+        for event in &self.events {
+            let start = event.date.and_time(event.start_time);
+            let end = event.date.and_time(event.end_time);
+
+            lines.push("BEGIN:VEVENT".to_owned());
+            lines.push(format!("UID:{}@{}", event.uid, config().domain()));
+            lines.push(format!("DTSTAMP:{stamp}"));
+            lines.push(format!(
+                "DTSTART;TZID={}:{}",
+                tz.name(),
+                format_local(start)
+            ));
+            lines.push(format!("DTEND;TZID={}:{}", tz.name(), format_local(end)));
+            lines.push(format!("SUMMARY:{}", escape(&event.summary)));
+            if let Some(description) = &event.description {
+                lines.push(format!("DESCRIPTION:{}", escape(description)));
+            }
+            lines.push(format!("URL:{}", event.url));
+            lines.push("END:VEVENT".to_owned());
+        }
+        lines.push("END:VCALENDAR".to_owned());
+
+        lines.iter().fold(String::new(), |mut out, line| {
+            fold(&mut out, line);
+            out
+        })
+    }
+}
+
+impl IntoResponse for Calendar {
+    fn into_response(self) -> Response {
+        ([(header::CONTENT_TYPE, CONTENT_TYPE)], self.render()).into_response()
+    }
+}
+
+/// Builds a VTIMEZONE with an observance for every offset change from a year
+/// before the first date to a year after the last. chrono-tz doesn't expose
+/// the zone's rules, so the transitions are found by scanning.
+fn vtimezone(tz: Tz, first: NaiveDate, last: NaiveDate) -> Vec<String> {
+    let start = (first - Duration::days(366))
+        .and_time(NaiveTime::MIN)
+        .and_utc();
+    let end = (last + Duration::days(366))
+        .and_time(NaiveTime::MIN)
+        .and_utc();
+
+    let mut lines = vec!["BEGIN:VTIMEZONE".to_owned(), format!("TZID:{}", tz.name())];
+    let mut offset = tz.offset_from_utc_datetime(&start.naive_utc()).fix();
+    lines.extend(observance(tz, start, offset, offset));
+
+    let mut day = start;
+    while day < end {
+        let next = day + Duration::days(1);
+        let next_offset = tz.offset_from_utc_datetime(&next.naive_utc()).fix();
+        if next_offset != offset {
+            // Narrow down to the minute the offset changed
+            let (mut lo, mut hi) = (0, 24 * 60);
+            while hi - lo > 1 {
+                let mid = (lo + hi) / 2;
+                let at = day + Duration::minutes(mid);
+                if tz.offset_from_utc_datetime(&at.naive_utc()).fix() == offset {
+                    lo = mid;
+                } else {
+                    hi = mid;
+                }
+            }
+            lines.extend(observance(
+                tz,
+                day + Duration::minutes(hi),
+                offset,
+                next_offset,
+            ));
+            offset = next_offset;
+        }
+        day = next;
+    }
+    lines.push("END:VTIMEZONE".to_owned());
+    lines
+}
+
+fn observance(tz: Tz, at: DateTime<Utc>, from: FixedOffset, to: FixedOffset) -> Vec<String> {
+    let tz_offset = tz.offset_from_utc_datetime(&at.naive_utc());
+    let kind = if tz_offset.dst_offset().is_zero() {
+        "STANDARD"
+    } else {
+        "DAYLIGHT"
+    };
+    // DTSTART is the wall clock time just before the change
+    let local = at.naive_utc() + Duration::seconds(from.local_minus_utc().into());
+    vec![
+        format!("BEGIN:{kind}"),
+        format!("DTSTART:{}", format_local(local)),
+        format!("TZOFFSETFROM:{}", format_offset(from)),
+        format!("TZOFFSETTO:{}", format_offset(to)),
+        format!("TZNAME:{}", escape(tz_offset.abbreviation())),
+        format!("END:{kind}"),
+    ]
+}
+
+fn format_local(dt: NaiveDateTime) -> String {
+    dt.format("%Y%m%dT%H%M%S").to_string()
+}
+
+fn format_utc(dt: DateTime<Utc>) -> String {
+    dt.format("%Y%m%dT%H%M%SZ").to_string()
+}
+
+fn format_offset(offset: FixedOffset) -> String {
+    let secs = offset.local_minus_utc();
+    let sign = if secs < 0 { '-' } else { '+' };
+    let secs = secs.abs();
+    let (h, m, s) = (secs / 3600, secs / 60 % 60, secs % 60);
+    if s == 0 {
+        format!("{sign}{h:02}{m:02}")
+    } else {
+        format!("{sign}{h:02}{m:02}{s:02}")
+    }
+}
+
+/// Escapes a TEXT value
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace("\r\n", "\\n")
+        .replace('\n', "\\n")
+        .replace('\r', "")
+}
+
+/// Writes a content line, folded so no line is longer than 75 octets
+fn fold(out: &mut String, line: &str) {
+    let mut len = 0;
+    for c in line.chars() {
+        if len + c.len_utf8() > 75 {
+            out.push_str("\r\n ");
+            len = 1;
+        }
+        out.push(c);
+        len += c.len_utf8();
+    }
+    out.push_str("\r\n");
+}
@@ -3,6 +3,12 @@ use std::{error, fmt, io};
 #[derive(Debug)]
 pub struct ConfigError(String);
 
+impl ConfigError {
+    pub(super) fn new(message: &str) -> Self {
+        Self(message.to_owned())
+    }
+}
+
 impl fmt::Display for ConfigError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "{}", self.0)
@@ -1,17 +1,22 @@
-use std::{path::Path, sync::OnceLock};
+use std::{
+    any::Any,
+    path::Path,
+    sync::{Arc, OnceLock},
+};
 
 use axum::extract::FromRef;
 use axum_extra::extract::cookie::Key;
 use axum_server::tls_rustls::RustlsConfig;
 use chrono_tz::Tz;
-use lettre::{Address, AsyncSmtpTransport, Tokio1Executor};
+use lettre::Address;
 use sqlx::{postgres::PgPoolOptions, Pool, Postgres};
 use tokio::{fs::File, io::AsyncReadExt};
 
 pub use error::ConfigError;
 
 use self::text::TextConfig;
-pub use self::text::{Admin, Website};
+pub use self::text::{Admin, DigestMode, Notifications, Reminders, Sessions, Website};
+use crate::email::{MemoryTransport, Transport};
 
 mod error;
 mod text;
@@ -21,7 +26,7 @@ static CONFIG: OnceLock<Config> = OnceLock::new();
 #[derive(Clone)]
 pub struct Config {
     db_pool: Pool<Postgres>,
-    mailer: Option<AsyncSmtpTransport<Tokio1Executor>>,
+    mailer: Option<Arc<dyn Transport>>,
     address: Option<Address>,
     session_key: Key,
     tls_config: Option<RustlsConfig>,
@@ -29,6 +34,9 @@ pub struct Config {
 
     pub website: Website,
     pub admin: Admin,
+    pub reminders: Option<Reminders>,
+    pub sessions: Sessions,
+    pub notifications: Option<Notifications>,
 }
 
 pub fn config() -> &'static Config {
@@ -41,6 +49,7 @@ impl Config {
         let mut cfg_string = String::new();
         cfg_file.read_to_string(&mut cfg_string).await?;
         let text = toml::from_str::<TextConfig>(&cfg_string)?;
+        text.validate()?;
 
         // Initialize db pool
         let pool = PgPoolOptions::new()
@@ -68,7 +77,7 @@ impl Config {
             mailer: text
                 .email
                 .as_ref()
-                .map_or(Ok(None), |em| em.mailer().map(Some))?,
+                .map_or(Ok(None), |em| em.transport().map(Some))?,
             address: text.email.map(|em| em.address()),
             session_key,
             tls_config,
@@ -76,6 +85,9 @@ impl Config {
 
             website: text.website,
             admin: text.admin,
+            reminders: text.reminders,
+            sessions: text.sessions,
+            notifications: text.notifications,
         };
         _ = CONFIG.set(config);
         Ok(())
@@ -104,8 +116,13 @@ impl Config {
         self.website.base_url.clone()
     }
 
-    pub fn mailer(&self) -> Option<&AsyncSmtpTransport<Tokio1Executor>> {
-        self.mailer.as_ref()
+    pub fn mailer(&self) -> Option<&dyn Transport> {
+        self.mailer.as_deref()
+    }
+    /// Emails kept by the memory transport, if that's the one being used
+    pub fn outbox(&self) -> Option<&MemoryTransport> {
+        let mailer: &dyn Any = self.mailer.as_deref()?;
+        mailer.downcast_ref()
     }
     pub fn mailing_address(&self) -> Option<&Address> {
         self.address.as_ref()
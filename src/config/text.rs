@@ -1,11 +1,15 @@
-use std::path::PathBuf;
+use std::{path::PathBuf, sync::Arc};
 
+use chrono::NaiveTime;
 use chrono_tz::Tz;
 use lettre::{
     transport::smtp::authentication::Credentials, Address, AsyncSmtpTransport, Tokio1Executor,
 };
 use serde::Deserialize;
 
+use super::ConfigError;
+use crate::email::{FileTransport, MemoryTransport, Transport};
+
 #[derive(Debug, Deserialize, Clone)]
 pub struct TextConfig {
     pub website: Website,
@@ -13,6 +17,43 @@ pub struct TextConfig {
     pub database: Database,
     pub ssl: Option<Ssl>,
     pub email: Option<Email>,
+    pub reminders: Option<Reminders>,
+    #[serde(default)]
+    pub sessions: Sessions,
+    pub notifications: Option<Notifications>,
+}
+
+impl TextConfig {
+    /// Catches settings that parse fine but can't work
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        if let Some(reminders) = &self.reminders {
+            if reminders.lead_time() <= chrono::Duration::zero() {
+                return Err(ConfigError::new(
+                    "reminders needs days_before or hours_before set above 0",
+                ));
+            }
+            if reminders.check_minutes == 0 {
+                return Err(ConfigError::new(
+                    "reminders.check_minutes must be at least 1",
+                ));
+            }
+        }
+        if self
+            .notifications
+            .as_ref()
+            .is_some_and(|n| n.check_minutes == 0)
+        {
+            return Err(ConfigError::new(
+                "notifications.check_minutes must be at least 1",
+            ));
+        }
+        if self.sessions.sweep_minutes == 0 {
+            return Err(ConfigError::new(
+                "sessions.sweep_minutes must be at least 1",
+            ));
+        }
+        Ok(())
+    }
 }
 
 #[derive(Debug, Deserialize, Clone)]
@@ -23,12 +64,31 @@ pub struct Website {
     pub session_key: Option<String>,
     pub otel_endpoint: Option<String>,
     pub timezone: Option<Tz>,
+    #[serde(default = "default_worker_link_days")]
+    pub worker_link_days: i64,
+    #[serde(default = "default_cancel_cutoff_hours")]
+    pub cancel_cutoff_hours: i64,
 }
 
 fn default_port() -> u16 {
     3000
 }
 
+fn default_worker_link_days() -> i64 {
+    90
+}
+
+fn default_cancel_cutoff_hours() -> i64 {
+    48
+}
+
+impl Website {
+    /// How close to a shift's start workers can no longer cancel it themselves
+    pub fn cancel_cutoff(&self) -> chrono::Duration {
+        chrono::Duration::hours(self.cancel_cutoff_hours)
+    }
+}
+
 #[derive(Debug, Deserialize, Clone)]
 pub struct Admin {
     pub name: String,
@@ -47,24 +107,142 @@ pub struct Ssl {
     pub key: PathBuf,
 }
 
+#[derive(Debug, Deserialize, Clone, Copy, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum TransportKind {
+    #[default]
+    Smtp,
+    /// One `.eml` file per email in `path`
+    File,
+    /// A maildir at `path`
+    Maildir,
+    /// Kept in memory and shown at /email/outbox
+    Memory,
+}
+
 #[derive(Debug, Deserialize, Clone)]
 pub struct Email {
-    server: String,
     address: Address,
-    password: String,
+    #[serde(default)]
+    transport: TransportKind,
+    server: Option<String>,
+    password: Option<String>,
+    path: Option<PathBuf>,
 }
 
 impl Email {
-    pub fn mailer(
-        &self,
-    ) -> Result<AsyncSmtpTransport<Tokio1Executor>, lettre::transport::smtp::Error> {
-        let builder = AsyncSmtpTransport::<Tokio1Executor>::relay(&self.server)?.credentials(
-            Credentials::new(self.address.user().to_owned(), self.password.clone()),
-        );
-        Ok(builder.build())
+    pub fn transport(&self) -> Result<Arc<dyn Transport>, ConfigError> {
+        Ok(match self.transport {
+            TransportKind::Smtp => {
+                let (Some(server), Some(password)) = (&self.server, &self.password) else {
+                    return Err(ConfigError::new(
+                        "The smtp email transport needs a server and password",
+                    ));
+                };
+                let builder = AsyncSmtpTransport::<Tokio1Executor>::relay(server)?.credentials(
+                    Credentials::new(self.address.user().to_owned(), password.clone()),
+                );
+                Arc::new(builder.build())
+            }
+            TransportKind::File | TransportKind::Maildir => {
+                let Some(path) = &self.path else {
+                    return Err(ConfigError::new(
+                        "The file and maildir email transports need a path",
+                    ));
+                };
+                Arc::new(FileTransport::new(
+                    path.clone(),
+                    matches!(self.transport, TransportKind::Maildir),
+                ))
+            }
+            TransportKind::Memory => Arc::new(MemoryTransport::default()),
+        })
     }
 
     pub fn address(&self) -> Address {
         self.address.clone()
     }
 }
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct Reminders {
+    #[serde(default)]
+    pub days_before: i64,
+    #[serde(default)]
+    pub hours_before: i64,
+    #[serde(default = "default_check_minutes")]
+    pub check_minutes: u64,
+}
+
+fn default_check_minutes() -> u64 {
+    15
+}
+
+impl Reminders {
+    /// How long before a worker's first shift of the day to remind them
+    pub fn lead_time(&self) -> chrono::Duration {
+        chrono::Duration::days(self.days_before) + chrono::Duration::hours(self.hours_before)
+    }
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum DigestMode {
+    /// Sent at the next check after anything happens
+    Immediate,
+    /// Sent once a day at `send_at`
+    Daily,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct Notifications {
+    #[serde(default = "default_digest_mode")]
+    pub mode: DigestMode,
+    #[serde(
+        default = "default_send_at",
+        deserialize_with = "crate::time_ext::deserialize_time"
+    )]
+    pub send_at: NaiveTime,
+    #[serde(default = "default_check_minutes")]
+    pub check_minutes: u64,
+}
+
+fn default_digest_mode() -> DigestMode {
+    DigestMode::Daily
+}
+
+fn default_send_at() -> NaiveTime {
+    NaiveTime::from_hms_opt(7, 0, 0).expect("Valid time")
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct Sessions {
+    #[serde(default = "default_absolute_days")]
+    pub absolute_days: i64,
+    #[serde(default = "default_idle_hours")]
+    pub idle_hours: i64,
+    #[serde(default = "default_sweep_minutes")]
+    pub sweep_minutes: u64,
+}
+
+fn default_absolute_days() -> i64 {
+    30
+}
+
+fn default_idle_hours() -> i64 {
+    72
+}
+
+fn default_sweep_minutes() -> u64 {
+    60
+}
+
+impl Default for Sessions {
+    fn default() -> Self {
+        Sessions {
+            absolute_days: default_absolute_days(),
+            idle_hours: default_idle_hours(),
+            sweep_minutes: default_sweep_minutes(),
+        }
+    }
+}
@@ -0,0 +1,507 @@
+use askama::Template;
+use axum::{
+    extract::{Path, Query},
+    http::StatusCode,
+};
+use axum_extra::extract::Form;
+use cafe_website::{error::NOT_FOUND, filters, templates::Card, AppError, Redirect};
+use chrono::NaiveDate;
+use serde::Deserialize;
+use sqlx::PgConnection;
+use tracing::info;
+use uuid::Uuid;
+
+use crate::{
+    accounts::AnyEventEditor,
+    config,
+    config::Admin,
+    models::{Event, Shift},
+    shift::assignment,
+    worker::{self, Worker},
+};
+
+use super::{template::Variables, Audience, Broadcast, EmailStatus};
+
+/// Filled in for each recipient, in the subject or the body
+pub const PLACEHOLDERS: [(&str, &str); 4] = [
+    ("{first_name}", "Their first name"),
+    ("{last_name}", "Their last name"),
+    (
+        "{shifts}",
+        "A list of their shifts in the event, or on the day",
+    ),
+    (
+        "{link}",
+        "A link to their page, where they can manage their shifts",
+    ),
+];
+
+#[derive(Template)]
+#[template(path = "email/messages/broadcast.html")]
+pub struct BroadcastEmail {
+    body: String,
+    admin: &'static Admin,
+}
+
+pub struct Recipient {
+    pub email_id: Uuid,
+    pub name_first: String,
+    pub name_last: String,
+    pub address: String,
+    pub status: EmailStatus,
+}
+
+pub struct BroadcastRow {
+    pub id: Uuid,
+    pub subject: String,
+    pub event_name: String,
+    pub audience: Audience,
+    pub date: Option<NaiveDate>,
+    pub sent: bool,
+    pub recipients: i64,
+}
+
+#[derive(Template)]
+#[template(path = "email/broadcast_list.html")]
+pub struct BroadcastListTemplate {
+    broadcasts: Vec<BroadcastRow>,
+}
+
+/// The compose form, also used to edit a draft
+#[derive(Template)]
+#[template(path = "email/compose.html")]
+pub struct ComposeTemplate {
+    broadcast: Option<Broadcast>,
+    event_id: Option<Uuid>,
+    events: Vec<Event>,
+    days: Vec<NaiveDate>,
+}
+
+impl ComposeTemplate {
+    async fn new(
+        editor: &AnyEventEditor,
+        broadcast: Option<Broadcast>,
+        event_id: Option<Uuid>,
+    ) -> Result<Self, AppError> {
+        let events = sqlx::query_as!(
+            Event,
+            "SELECT * FROM event WHERE $1::uuid IS NULL
+                OR id IN (SELECT event_id FROM event_coordinator WHERE user_id = $1)
+            ORDER BY name",
+            editor.coordinator_id()
+        )
+        .fetch_all(config().pool())
+        .await?;
+        let event_id = event_id.or(events.first().map(|e| e.id));
+        let days = sqlx::query_scalar!(
+            "SELECT date FROM day WHERE event_id = $1 ORDER BY date",
+            event_id
+        )
+        .fetch_all(config().pool())
+        .await?;
+        Ok(ComposeTemplate {
+            broadcast,
+            event_id,
+            events,
+            days,
+        })
+    }
+
+    fn event_selected(&self, id: &Uuid) -> bool {
+        self.event_id == Some(*id)
+    }
+
+    fn audience_selected(&self, audience: &Audience) -> bool {
+        self.broadcast
+            .as_ref()
+            .map_or(*audience == Audience::Event, |b| b.audience == *audience)
+    }
+
+    fn day_label(&self, date: &NaiveDate) -> String {
+        filters::date_long(date).unwrap_or_default()
+    }
+
+    fn date_selected(&self, date: &NaiveDate) -> bool {
+        self.broadcast
+            .as_ref()
+            .is_some_and(|b| b.date == Some(*date))
+    }
+}
+
+#[derive(Template)]
+#[template(path = "email/broadcast.html")]
+pub struct BroadcastTemplate {
+    broadcast: Broadcast,
+    event_name: String,
+    recipients: Vec<Recipient>,
+    compose: ComposeTemplate,
+}
+
+pub async fn broadcast_list(
+    editor: AnyEventEditor,
+) -> Result<Card<BroadcastListTemplate>, AppError> {
+    let broadcasts = sqlx::query_as!(
+        BroadcastRow,
+        r#"SELECT b.id, b.subject, e.name as event_name, b.audience as "audience: Audience",
+            b.date, b.sent_at IS NOT NULL as "sent!", COUNT(em.id) as "recipients!"
+        FROM broadcast as b
+        INNER JOIN event as e ON e.id = b.event_id
+        LEFT JOIN email as em ON em.broadcast_id = b.id
+        WHERE $1::uuid IS NULL
+            OR b.event_id IN (SELECT event_id FROM event_coordinator WHERE user_id = $1)
+        GROUP BY b.id, e.name
+        ORDER BY b.sent_at IS NOT NULL, b.created_at DESC"#,
+        editor.coordinator_id()
+    )
+    .fetch_all(config().pool())
+    .await?;
+    Ok(Card {
+        class: None,
+        title: "Broadcasts".to_owned(),
+        child: BroadcastListTemplate { broadcasts },
+        show_x: false,
+    })
+}
+
+#[derive(Deserialize)]
+pub struct ComposeQuery {
+    event_id: Option<Uuid>,
+}
+
+pub async fn compose_form(
+    editor: AnyEventEditor,
+    Query(ComposeQuery { event_id }): Query<ComposeQuery>,
+) -> Result<Card<ComposeTemplate>, AppError> {
+    if let Some(event_id) = event_id {
+        editor.check(event_id).await?;
+    }
+    Ok(Card {
+        class: None,
+        title: "Compose".to_owned(),
+        child: ComposeTemplate::new(&editor, None, event_id).await?,
+        show_x: false,
+    })
+}
+
+#[derive(Deserialize)]
+pub struct ComposeForm {
+    event_id: Uuid,
+    audience: Audience,
+    date: Option<NaiveDate>,
+    subject: String,
+    body: String,
+}
+
+impl ComposeForm {
+    /// Checks the form, returning the day the broadcast is for, if any
+    async fn validate(&self) -> Result<Option<NaiveDate>, AppError> {
+        if self.subject.trim().is_empty() || self.body.trim().is_empty() {
+            return Err(AppError::inline(
+                StatusCode::BAD_REQUEST,
+                "A subject and message are required",
+            ));
+        }
+        if self.audience != Audience::Day {
+            return Ok(None);
+        }
+        let Some(date) = self.date else {
+            return Err(AppError::inline(StatusCode::BAD_REQUEST, "Pick a day"));
+        };
+        let exists = sqlx::query_scalar!(
+            r#"SELECT EXISTS (SELECT 1 FROM day WHERE event_id = $1 AND date = $2) as "exists!""#,
+            self.event_id,
+            date
+        )
+        .fetch_one(config().pool())
+        .await?;
+        if !exists {
+            return Err(AppError::inline(
+                StatusCode::BAD_REQUEST,
+                "That day isn't part of the event",
+            ));
+        }
+        Ok(Some(date))
+    }
+}
+
+/// Saves a new broadcast, with a draft for each recipient
+pub async fn create(
+    editor: AnyEventEditor,
+    Form(form): Form<ComposeForm>,
+) -> Result<Redirect, AppError> {
+    editor.check(form.event_id).await?;
+    let date = form.validate().await?;
+    let mut tran = config().pool().begin().await?;
+    let broadcast = sqlx::query_as!(
+        Broadcast,
+        r#"INSERT INTO broadcast (event_id, audience, date, subject, body)
+        VALUES ($1, $2, $3, $4, $5)
+        RETURNING id, created_at, sent_at, event_id, audience as "audience: Audience",
+            date, subject, body"#,
+        form.event_id,
+        form.audience as Audience,
+        date,
+        form.subject.trim(),
+        form.body
+    )
+    .fetch_one(&mut *tran)
+    .await?;
+    draft(&broadcast, &mut tran).await?;
+    tran.commit().await?;
+    Ok(Redirect::to(format!("/email/broadcast/{}", broadcast.id)))
+}
+
+/// Loads the broadcast if the user can change its event
+async fn load(id: Uuid, editor: &AnyEventEditor) -> Result<Broadcast, AppError> {
+    let broadcast = sqlx::query_as!(
+        Broadcast,
+        r#"SELECT id, created_at, sent_at, event_id, audience as "audience: Audience",
+            date, subject, body
+        FROM broadcast WHERE id = $1"#,
+        id
+    )
+    .fetch_optional(config().pool())
+    .await?
+    .ok_or(NOT_FOUND)?;
+    editor.check(broadcast.event_id).await?;
+    Ok(broadcast)
+}
+
+pub async fn view(
+    editor: AnyEventEditor,
+    Path(id): Path<Uuid>,
+) -> Result<Card<BroadcastTemplate>, AppError> {
+    let broadcast = load(id, &editor).await?;
+    let event_name =
+        sqlx::query_scalar!("SELECT name FROM event WHERE id = $1", broadcast.event_id)
+            .fetch_one(config().pool())
+            .await?;
+    let recipients = sqlx::query_as!(
+        Recipient,
+        r#"SELECT em.id as email_id, w.name_first, w.name_last, em.address,
+            em.status as "status: EmailStatus"
+        FROM email as em
+        INNER JOIN worker as w ON w.id = em.recipient
+        WHERE em.broadcast_id = $1
+        ORDER BY w.name_last, w.name_first"#,
+        id
+    )
+    .fetch_all(config().pool())
+    .await?;
+    let compose =
+        ComposeTemplate::new(&editor, Some(broadcast.clone()), Some(broadcast.event_id)).await?;
+    Ok(Card {
+        class: None,
+        title: broadcast.subject.clone(),
+        child: BroadcastTemplate {
+            broadcast,
+            event_name,
+            recipients,
+            compose,
+        },
+        show_x: false,
+    })
+}
+
+/// Changes a draft, writing its emails again for whoever is in the audience now
+pub async fn update(
+    editor: AnyEventEditor,
+    Path(id): Path<Uuid>,
+    Form(form): Form<ComposeForm>,
+) -> Result<Redirect, AppError> {
+    load(id, &editor).await?;
+    editor.check(form.event_id).await?;
+    let date = form.validate().await?;
+    let mut tran = config().pool().begin().await?;
+    let broadcast = sqlx::query_as!(
+        Broadcast,
+        r#"UPDATE broadcast SET event_id = $2, audience = $3, date = $4, subject = $5, body = $6
+        WHERE id = $1 AND sent_at IS NULL
+        RETURNING id, created_at, sent_at, event_id, audience as "audience: Audience",
+            date, subject, body"#,
+        id,
+        form.event_id,
+        form.audience as Audience,
+        date,
+        form.subject.trim(),
+        form.body
+    )
+    .fetch_optional(&mut *tran)
+    .await?
+    .ok_or(AppError::inline(
+        StatusCode::BAD_REQUEST,
+        "This broadcast has already been sent",
+    ))?;
+    draft(&broadcast, &mut tran).await?;
+    tran.commit().await?;
+    Ok(Redirect::to(format!("/email/broadcast/{id}")))
+}
+
+/// Queues the drafts to go out on the next run of the sender
+pub async fn send(editor: AnyEventEditor, Path(id): Path<Uuid>) -> Result<Redirect, AppError> {
+    load(id, &editor).await?;
+    let mut tran = config().pool().begin().await?;
+    let marked = sqlx::query!(
+        "UPDATE broadcast SET sent_at = now() WHERE id = $1 AND sent_at IS NULL",
+        id
+    )
+    .execute(&mut *tran)
+    .await?;
+    if marked.rows_affected() == 0 {
+        return Err(AppError::inline(
+            StatusCode::BAD_REQUEST,
+            "This broadcast has already been sent",
+        ));
+    }
+    let queued = sqlx::query!(
+        "UPDATE email SET status = 'pending' WHERE broadcast_id = $1 AND status = 'draft'",
+        id
+    )
+    .execute(&mut *tran)
+    .await?;
+    if queued.rows_affected() == 0 {
+        return Err(AppError::inline(
+            StatusCode::BAD_REQUEST,
+            "Nobody is in this broadcast's audience",
+        ));
+    }
+    tran.commit().await?;
+    info!(%id, emails = queued.rows_affected(), "Sending broadcast");
+    Ok(Redirect::to(format!("/email/broadcast/{id}")))
+}
+
+/// Throws away a draft. Sent broadcasts are kept along with their emails.
+pub async fn delete(editor: AnyEventEditor, Path(id): Path<Uuid>) -> Result<Redirect, AppError> {
+    load(id, &editor).await?;
+    let mut tran = config().pool().begin().await?;
+    sqlx::query!(
+        "DELETE FROM email WHERE broadcast_id = $1 AND status = 'draft'",
+        id
+    )
+    .execute(&mut *tran)
+    .await?;
+    let deleted = sqlx::query!(
+        "DELETE FROM broadcast WHERE id = $1 AND sent_at IS NULL",
+        id
+    )
+    .execute(&mut *tran)
+    .await?;
+    if deleted.rows_affected() == 0 {
+        return Err(AppError::inline(
+            StatusCode::BAD_REQUEST,
+            "Sent broadcasts can't be deleted",
+        ));
+    }
+    tran.commit().await?;
+    Ok(Redirect::to("/email/broadcasts".to_owned()))
+}
+
+/// Workers the broadcast goes to
+async fn audience(
+    broadcast: &Broadcast,
+    conn: &mut PgConnection,
+) -> Result<Vec<Worker>, sqlx::Error> {
+    match broadcast.audience {
+        Audience::Event | Audience::Day => {
+            sqlx::query_as!(
+                Worker,
+                "SELECT w.* FROM worker as w
+                INNER JOIN shift_assignment as sa ON sa.worker_id = w.id
+                INNER JOIN shift as s ON s.id = sa.shift_id
+                WHERE s.event_id = $1 AND ($2::date IS NULL OR s.date = $2)
+                GROUP BY w.id
+                ORDER BY w.name_last, w.name_first",
+                broadcast.event_id,
+                broadcast.date
+            )
+            .fetch_all(conn)
+            .await
+        }
+        // Not in the event yet, and either said they can help on a day that
+        // still has open spots, or have worked another event
+        Audience::Prospects => {
+            sqlx::query_as!(
+                Worker,
+                "WITH open_day AS (
+                    SELECT DISTINCT s.date FROM shift as s
+                    WHERE s.event_id = $1
+                    AND s.capacity > (SELECT COUNT(*) FROM shift_assignment WHERE shift_id = s.id)
+                )
+                SELECT w.* FROM worker as w
+                WHERE EXISTS (SELECT 1 FROM open_day)
+                AND NOT EXISTS (
+                    SELECT 1 FROM shift_assignment as sa
+                    INNER JOIN shift as s ON s.id = sa.shift_id
+                    WHERE sa.worker_id = w.id AND s.event_id = $1
+                )
+                AND (
+                    EXISTS (
+                        SELECT 1 FROM worker_availability as a
+                        INNER JOIN open_day as o ON o.date = a.date
+                        WHERE a.worker_id = w.id AND a.event_id = $1
+                    )
+                    OR EXISTS (
+                        SELECT 1 FROM shift_assignment as sa
+                        INNER JOIN shift as s ON s.id = sa.shift_id
+                        WHERE sa.worker_id = w.id AND s.event_id != $1
+                    )
+                )
+                ORDER BY w.name_last, w.name_first",
+                broadcast.event_id
+            )
+            .fetch_all(conn)
+            .await
+        }
+    }
+}
+
+/// Writes a draft email for each worker in the broadcast's audience, replacing
+/// any it already had. Access links are only issued when the message uses
+/// them, since every edit issues them again.
+async fn draft(broadcast: &Broadcast, conn: &mut PgConnection) -> Result<(), AppError> {
+    sqlx::query!(
+        "DELETE FROM email WHERE broadcast_id = $1 AND status = 'draft'",
+        broadcast.id
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    let needs_link = broadcast.body.contains("{link}");
+    for worker in audience(broadcast, &mut *conn).await? {
+        let shifts: Vec<Shift> =
+            assignment::shifts(worker.id, Some(broadcast.event_id), &mut *conn)
+                .await?
+                .into_iter()
+                .filter(|s| broadcast.date.is_none_or(|d| d == s.date))
+                .collect();
+        let link = if needs_link {
+            worker::access_url(worker::issue_token(worker.id, &mut *conn).await?)
+        } else {
+            String::new()
+        };
+        let variables = Variables::default()
+            .worker(&worker)
+            .shifts(&shifts)
+            .set("link", link);
+        let message = BroadcastEmail {
+            body: variables.fill(&broadcast.body),
+            admin: &config().admin,
+        }
+        .render()?;
+
+        sqlx::query!(
+            "INSERT INTO email
+                (status, kind, recipient, address, subject, message, event_id, broadcast_id)
+            VALUES ('draft', 'html', $1, $2, $3, $4, $5, $6)",
+            worker.id,
+            worker.email,
+            variables.fill(&broadcast.subject),
+            message,
+            broadcast.event_id,
+            broadcast.id
+        )
+        .execute(&mut *conn)
+        .await?;
+    }
+    Ok(())
+}
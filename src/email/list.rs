@@ -2,23 +2,32 @@ use std::{borrow::Borrow, fmt};
 
 use askama::Template;
 use askama_axum::IntoResponse;
-use axum::extract::Query;
+use axum::{
+    extract::{Path, Query},
+    http::StatusCode,
+    response::{Html, Response},
+};
 use cafe_website::{
+    error::{ISE, NOT_FOUND},
+    export::{self, ExportQuery, ExportRow},
     filters,
     pagination::{OrderDirection, PaginationControls},
     templates::Card,
     AppError, PaginatedQuery,
 };
-use chrono::{NaiveDate, TimeZone};
+use chrono::{DateTime, NaiveDate, TimeZone, Utc};
 use chrono_tz::{OffsetName, Tz};
 use serde::{Deserialize, Serialize};
-use sqlx::QueryBuilder;
+use sqlx::{Postgres, QueryBuilder};
 use tracing::info;
 use uuid::Uuid;
 
 use crate::config;
 
-use super::{Email, EmailKind, EmailStatus};
+use super::{
+    render::{render, Parts},
+    Email, EmailKind, EmailStatus,
+};
 
 const DEFAULT_TAKE: i64 = 6;
 
@@ -60,6 +69,20 @@ pub struct EmailQuery {
     event_id: Option<Uuid>,
 }
 
+impl EmailQuery {
+    fn filter(&self, builder: &mut QueryBuilder<'_, Postgres>) {
+        if let Some(recip) = self.recipient {
+            builder.push(" AND recipient = ").push_bind(recip);
+        }
+        if let Some(status) = self.status {
+            builder.push(" AND status = ").push_bind(status);
+        }
+        if let Some(eid) = self.event_id {
+            builder.push(" AND event_id = ").push_bind(eid);
+        }
+    }
+}
+
 impl fmt::Display for EmailQuery {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let s = serde_urlencoded::to_string(self).unwrap_or_default();
@@ -71,22 +94,11 @@ pub async fn email_list(
     Query(pagination): Query<PaginatedQuery<EmailOrderBy, DEFAULT_TAKE, false>>,
     Query(query): Query<EmailQuery>,
 ) -> Result<impl IntoResponse, AppError> {
-    let mut builder = QueryBuilder::new("SELECT * FROM email WHERE recipient IS NOT NULL");
-    let mut count_builder =
-        QueryBuilder::new("SELECT Count(*) FROM email WHERE recipient IS NOT NULL");
+    let mut builder = QueryBuilder::new("SELECT * FROM email WHERE TRUE");
+    let mut count_builder = QueryBuilder::new("SELECT Count(*) FROM email WHERE TRUE");
 
-    if let Some(recip) = query.recipient {
-        builder.push(" AND recipient = ").push_bind(recip);
-        count_builder.push(" AND recipient = ").push_bind(recip);
-    }
-    if let Some(status) = query.status {
-        builder.push(" AND status = ").push_bind(status);
-        count_builder.push(" AND status = ").push_bind(status);
-    }
-    if let Some(eid) = query.event_id {
-        builder.push(" AND event_id = ").push_bind(eid);
-        count_builder.push(" AND event_id = ").push_bind(eid);
-    }
+    query.filter(&mut builder);
+    query.filter(&mut count_builder);
     builder.push(" ").push(pagination.sql());
 
     let (emails, count) = tokio::try_join!(
@@ -113,3 +125,85 @@ pub async fn email_list(
         show_x: false,
     })
 }
+
+impl ExportRow for Email {
+    const HEADERS: &'static [&'static str] = &["Created", "Sent", "Status", "Address", "Subject"];
+
+    fn record(&self) -> Vec<String> {
+        let tz = config().timezone();
+        let time = |t: DateTime<Utc>| t.with_timezone(&tz).format("%Y-%m-%d %H:%M").to_string();
+        vec![
+            time(self.created_at),
+            self.sent_at.map(time).unwrap_or_default(),
+            self.status.to_string(),
+            self.address.clone(),
+            self.subject.clone(),
+        ]
+    }
+}
+
+pub async fn export(
+    Query(ExportQuery { format }): Query<ExportQuery>,
+    Query(query): Query<EmailQuery>,
+) -> Result<Response, AppError> {
+    let mut builder = QueryBuilder::new("SELECT * FROM email WHERE TRUE");
+    query.filter(&mut builder);
+    builder.push(" ORDER BY created_at DESC");
+    export::export::<Email>("emails", format, builder, config().pool().clone()).await
+}
+
+/// Queues a failed or dead email to be sent on the next run, whatever its
+/// backoff, with a fresh set of attempts
+pub async fn retry(Path(id): Path<Uuid>) -> Result<Html<&'static str>, AppError> {
+    let status = sqlx::query_scalar!(
+        r#"SELECT status as "status: EmailStatus" FROM email WHERE id = $1"#,
+        id
+    )
+    .fetch_optional(config().pool())
+    .await?
+    .ok_or(NOT_FOUND)?;
+    if !matches!(status, EmailStatus::Failed | EmailStatus::Dead) {
+        return Err(AppError::inline(
+            StatusCode::BAD_REQUEST,
+            "Only emails that failed to send can be retried",
+        ));
+    }
+    sqlx::query!(
+        "UPDATE email SET status = 'pending', attempts = 0, next_attempt_at = now()
+        WHERE id = $1",
+        id
+    )
+    .execute(config().pool())
+    .await?;
+    info!(%id, "Retrying email");
+    Ok(Html("pending"))
+}
+
+#[derive(Template)]
+#[template(path = "email/preview.html")]
+pub struct PreviewTemplate {
+    email: Email,
+    parts: Parts,
+}
+
+/// Both parts of an email, as they're sent
+pub async fn preview(Path(id): Path<Uuid>) -> Result<Card<PreviewTemplate>, AppError> {
+    let email = sqlx::query_as!(
+        Email,
+        r#"SELECT id, created_at, sent_at, status as "status: _", kind as "kind: _",
+            recipient, address, subject, message, event_id, attempts, last_error, next_attempt_at
+        FROM email WHERE id = $1"#,
+        id
+    )
+    .fetch_optional(config().pool())
+    .await?
+    .ok_or(NOT_FOUND)?;
+    let parts = render(email.kind, &email.message).map_err(|e| {
+        tracing::error!("Couldn't render email {id}: {e}");
+        ISE
+    })?;
+    Ok(Card::modal(
+        "Preview".to_owned(),
+        PreviewTemplate { email, parts },
+    ))
+}
@@ -1,18 +1,33 @@
 use askama::Template;
-use axum::{routing::get, Router};
+use axum::{
+    middleware::from_extractor,
+    routing::{get, post},
+    Router,
+};
 use cafe_website::{filters, AppError};
+use sqlx::PgConnection;
 use uuid::Uuid;
 
+use crate::accounts::Scheduler;
+use crate::calendar::{self, Calendar, CalendarEvent};
 use crate::config::Admin;
-use crate::worker::Worker;
+use crate::worker::{self, Worker};
 use crate::{config, models::Shift};
 
+mod broadcast;
 mod list;
 mod model;
+mod outbox;
+mod render;
 mod sender;
+mod template;
+mod template_edit;
+mod transport;
 
-pub use model::{Email, EmailKind, EmailStatus};
+pub use model::{Audience, Broadcast, Email, EmailKind, EmailStatus};
 pub use sender::send_all;
+pub use template::{find_template, TemplateKind, Variables};
+pub use transport::{FileTransport, MemoryTransport, Transport};
 
 // Verify emails? anything else?
 pub fn public_router() -> Router {
@@ -20,7 +35,36 @@ pub fn public_router() -> Router {
 }
 
 pub fn protected_router() -> Router {
-    Router::new().route("/list", get(list::email_list))
+    Router::new()
+        .route("/list", get(list::email_list))
+        .route("/export", get(list::export))
+        .route("/:id/retry", post(list::retry))
+        .route("/:id/preview", get(list::preview))
+        .route("/outbox", get(outbox::outbox))
+        .route("/templates", get(template_edit::template_list))
+        .route(
+            "/template/:event_id/:kind",
+            get(template_edit::edit_form)
+                .put(template_edit::save)
+                .delete(template_edit::reset),
+        )
+        .route(
+            "/template/:event_id/:kind/preview",
+            post(template_edit::preview),
+        )
+        // Messages include workers' access links
+        .route_layer(from_extractor::<Scheduler>())
+        // Coordinators can broadcast to their own events, checked per broadcast
+        .route("/broadcasts", get(broadcast::broadcast_list))
+        .route("/compose", get(broadcast::compose_form))
+        .route("/broadcast", post(broadcast::create))
+        .route(
+            "/broadcast/:id",
+            get(broadcast::view)
+                .put(broadcast::update)
+                .delete(broadcast::delete),
+        )
+        .route("/broadcast/:id/send", post(broadcast::send))
 }
 
 #[derive(Template)]
@@ -28,22 +72,90 @@ pub fn protected_router() -> Router {
 pub struct SignupEmail {
     worker: Worker,
     shift: Shift,
-    domain: String,
+    link: String,
     admin: &'static Admin,
 }
 
-pub async fn send_signup<'c>(
+pub async fn send_signup(
     worker: Worker,
     shift: Shift,
-    executor: impl sqlx::Executor<'c, Database = sqlx::Postgres>,
+    conn: &mut PgConnection,
 ) -> Result<Uuid, AppError> {
     let (recipient, event_id, address) = (worker.id, shift.event_id, worker.email.clone());
     let subject = format!("Thanks {}!", worker.name_first); // injection?
+    let invite = Calendar::new(shift.title.clone(), vec![CalendarEvent::from(&shift)]).render();
+
+    let token = worker::issue_token(worker.id, &mut *conn).await?;
+    let link = worker::access_url(token);
+    let custom = find_template(event_id, TemplateKind::Signup, &mut *conn).await?;
+    let (subject, message) = match custom {
+        Some(custom) => {
+            let variables = Variables::for_event(event_id, &mut *conn)
+                .await?
+                .worker(&worker)
+                .shifts(std::slice::from_ref(&shift))
+                .set("link", link);
+            custom.render(&variables)?
+        }
+        None => {
+            let message = SignupEmail {
+                worker,
+                shift,
+                link,
+                admin: &config().admin,
+            }
+            .render()?;
+            (subject, message)
+        }
+    };
 
-    let message = SignupEmail {
+    let id = sqlx::query_scalar!(
+        "INSERT INTO email (status, kind, recipient, address, subject, message, event_id)
+        VALUES ('pending', 'html', $1, $2, $3, $4, $5) RETURNING id",
+        recipient,
+        address,
+        subject,
+        message,
+        event_id
+    )
+    .fetch_one(&mut *conn)
+    .await?;
+    sqlx::query!(
+        "INSERT INTO email_attachment (email_id, filename, content_type, content)
+        VALUES ($1, 'shift.ics', $2, $3)",
+        id,
+        calendar::CONTENT_TYPE,
+        invite.as_bytes()
+    )
+    .execute(conn)
+    .await?;
+    Ok(id)
+}
+
+#[derive(Template)]
+#[template(path = "email/messages/promotion.html")]
+pub struct PromotionEmail {
+    worker: Worker,
+    shift: Shift,
+    link: String,
+    admin: &'static Admin,
+}
+
+/// Queues the email letting a waitlisted worker know they've been moved into
+/// a shift that opened up
+pub async fn send_promotion(
+    worker: Worker,
+    shift: Shift,
+    conn: &mut PgConnection,
+) -> Result<Uuid, AppError> {
+    let (recipient, event_id, address) = (worker.id, shift.event_id, worker.email.clone());
+    let subject = format!("A spot opened up for you, {}!", worker.name_first);
+
+    let token = worker::issue_token(worker.id, &mut *conn).await?;
+    let message = PromotionEmail {
         worker,
         shift,
-        domain: config().url(),
+        link: worker::access_url(token),
         admin: &config().admin,
     }
     .render()?;
@@ -57,7 +169,53 @@ pub async fn send_signup<'c>(
         message,
         event_id
     )
-    .fetch_one(executor)
+    .fetch_one(conn)
+    .await?;
+    Ok(id)
+}
+
+#[derive(Template)]
+#[template(path = "email/messages/cancellation.html")]
+pub struct CancellationEmail {
+    worker: Worker,
+    shift: Shift,
+    reason: String,
+    link: String,
+}
+
+/// Queues an email letting the admin know a worker canceled one of their
+/// shifts, and why
+pub async fn send_cancellation(
+    worker_id: Uuid,
+    shift: Shift,
+    reason: String,
+    conn: &mut PgConnection,
+) -> Result<Uuid, AppError> {
+    let worker = sqlx::query_as!(Worker, "SELECT * FROM worker WHERE id = $1", worker_id)
+        .fetch_one(&mut *conn)
+        .await?;
+    let event_id = shift.event_id;
+    let subject = format!(
+        "{} {} canceled their {} shift",
+        worker.name_first, worker.name_last, shift.title
+    );
+    let message = CancellationEmail {
+        link: format!("{}/worker/{}", config().url(), worker.id),
+        worker,
+        shift,
+        reason,
+    }
+    .render()?;
+
+    let id = sqlx::query_scalar!(
+        "INSERT INTO email (status, kind, address, subject, message, event_id)
+        VALUES ('pending', 'html', $1, $2, $3, $4) RETURNING id",
+        config().admin.email,
+        subject,
+        message,
+        event_id
+    )
+    .fetch_one(conn)
     .await?;
     Ok(id)
 }
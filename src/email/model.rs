@@ -1,6 +1,6 @@
 use std::fmt;
 
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, NaiveDate, Utc};
 use serde::{Deserialize, Serialize};
 use sqlx::FromRow;
 use uuid::Uuid;
@@ -26,7 +26,10 @@ pub enum EmailStatus {
     Draft,
     Pending,
     Sent,
+    /// Will be tried again at `next_attempt_at`
     Failed,
+    /// Gave up on sending
+    Dead,
 }
 
 impl fmt::Display for EmailStatus {
@@ -36,6 +39,7 @@ impl fmt::Display for EmailStatus {
             Self::Pending => "pending",
             Self::Sent => "sent",
             Self::Failed => "failed",
+            Self::Dead => "dead",
         };
         write!(f, "{}", s)
     }
@@ -75,5 +79,56 @@ pub struct Email {
     pub address: String,
     pub subject: String,
     pub message: String,
+    pub event_id: Option<Uuid>,
+    pub attempts: i32,
+    pub last_error: Option<String>,
+    pub next_attempt_at: Option<DateTime<Utc>>,
+}
+
+/// Who a broadcast goes to
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, sqlx::Type, Debug)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(type_name = "broadcast_audience", rename_all = "lowercase")]
+pub enum Audience {
+    /// Everyone working a shift in the event
+    Event,
+    /// Everyone working a shift on one day of the event
+    Day,
+    /// Workers who aren't in the event yet but might fill its open shifts
+    Prospects,
+}
+
+impl Audience {
+    pub const ALL: [Audience; 3] = [Audience::Event, Audience::Day, Audience::Prospects];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Self::Event => "Everyone working the event",
+            Self::Day => "Everyone working one day",
+            Self::Prospects => "Prospects for open shifts",
+        }
+    }
+}
+
+impl fmt::Display for Audience {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            Self::Event => "event",
+            Self::Day => "day",
+            Self::Prospects => "prospects",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Broadcast {
+    pub id: Uuid,
+    pub created_at: DateTime<Utc>,
+    pub sent_at: Option<DateTime<Utc>>,
     pub event_id: Uuid,
+    pub audience: Audience,
+    pub date: Option<NaiveDate>,
+    pub subject: String,
+    pub body: String,
 }
@@ -0,0 +1,45 @@
+use askama::Template;
+use cafe_website::{error::NOT_FOUND, templates::Card, AppError};
+
+use crate::config;
+
+pub struct OutboxEmail {
+    pub to: String,
+    pub subject: String,
+    /// Exactly what would have gone to the mail server
+    pub raw: String,
+}
+
+#[derive(Template)]
+#[template(path = "email/outbox.html")]
+pub struct OutboxTemplate {
+    emails: Vec<OutboxEmail>,
+}
+
+/// Emails "sent" through the memory transport, newest first
+pub async fn outbox() -> Result<Card<OutboxTemplate>, AppError> {
+    let outbox = config().outbox().ok_or(NOT_FOUND)?;
+    let header = |message: &lettre::Message, name: &str| {
+        message
+            .headers()
+            .get_raw(name)
+            .unwrap_or_default()
+            .to_owned()
+    };
+    let emails = outbox
+        .messages()
+        .iter()
+        .rev()
+        .map(|message| OutboxEmail {
+            to: header(message, "To"),
+            subject: header(message, "Subject"),
+            raw: String::from_utf8_lossy(&message.formatted()).into_owned(),
+        })
+        .collect();
+    Ok(Card {
+        class: None,
+        title: "Outbox".to_owned(),
+        child: OutboxTemplate { emails },
+        show_x: false,
+    })
+}
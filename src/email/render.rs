@@ -0,0 +1,154 @@
+use std::{collections::HashMap, sync::LazyLock};
+
+use css_inline::CSSInliner;
+
+use crate::style::STYLESHEET;
+
+use super::EmailKind;
+
+/// Line width of the generated plain text
+const TEXT_WIDTH: usize = 78;
+
+/// The stylesheet as mail clients can use it. Tailwind puts every rule in a
+/// cascade layer and most values in custom properties, neither of which
+/// inlining or most clients understand, so layers are flattened and theme
+/// variables swapped for their values.
+static EMAIL_CSS: LazyLock<String> = LazyLock::new(|| {
+    let flat = flatten_layers(STYLESHEET);
+    let vars = theme_variables(&flat);
+    resolve_variables(&flat, &vars, 0)
+});
+
+/// What actually goes out for an email
+pub struct Parts {
+    /// With styles inlined. `None` for plain text emails.
+    pub html: Option<String>,
+    pub text: String,
+}
+
+pub fn render(kind: EmailKind, message: &str) -> Result<Parts, String> {
+    match kind {
+        EmailKind::Text => Ok(Parts {
+            html: None,
+            text: message.to_owned(),
+        }),
+        EmailKind::Html => {
+            let inliner = CSSInliner::options()
+                .extra_css(Some(EMAIL_CSS.as_str().into()))
+                .build();
+            let html = inliner.inline(message).map_err(|e| e.to_string())?;
+            let text =
+                html2text::from_read(message.as_bytes(), TEXT_WIDTH).map_err(|e| e.to_string())?;
+            Ok(Parts {
+                html: Some(html),
+                text,
+            })
+        }
+    }
+}
+
+/// Index of the brace closing the block opened just before `start`
+fn block_end(css: &str, start: usize) -> usize {
+    let mut depth = 1;
+    for (i, c) in css[start..].char_indices() {
+        match c {
+            '{' => depth += 1,
+            '}' => {
+                depth -= 1;
+                if depth == 0 {
+                    return start + i;
+                }
+            }
+            _ => {}
+        }
+    }
+    css.len()
+}
+
+/// Replaces each `@layer name { ... }` with its contents, and drops layer
+/// order statements
+fn flatten_layers(css: &str) -> String {
+    let mut out = String::with_capacity(css.len());
+    let mut rest = css;
+    while let Some(at) = rest.find("@layer") {
+        out.push_str(&rest[..at]);
+        rest = &rest[at..];
+        let statement_end = rest.find(';').unwrap_or(rest.len());
+        match rest.find('{') {
+            Some(open) if open < statement_end => {
+                let close = block_end(rest, open + 1);
+                out.push_str(&flatten_layers(&rest[open + 1..close]));
+                rest = rest.get(close + 1..).unwrap_or_default();
+            }
+            _ => rest = rest.get(statement_end + 1..).unwrap_or_default(),
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Custom properties set on `:root`, which is where Tailwind puts its theme
+fn theme_variables(css: &str) -> HashMap<String, String> {
+    let mut vars = HashMap::new();
+    let mut rest = css;
+    while let Some(open) = rest.find('{') {
+        let selector = rest[..open].rsplit(['}', ';']).next().unwrap_or_default();
+        let close = block_end(rest, open + 1);
+        if selector.contains(":root") {
+            for declaration in rest[open + 1..close].split(';') {
+                if let Some((name, value)) = declaration.split_once(':') {
+                    let name = name.trim();
+                    if name.starts_with("--") {
+                        vars.insert(name.to_owned(), value.trim().to_owned());
+                    }
+                }
+            }
+            rest = rest.get(close + 1..).unwrap_or_default();
+        } else {
+            rest = &rest[open + 1..];
+        }
+    }
+    vars
+}
+
+/// Swaps `var(--name)` for the variable's value, or its fallback if it isn't
+/// a theme variable. Anything else is left for clients that support it.
+fn resolve_variables(css: &str, vars: &HashMap<String, String>, depth: u8) -> String {
+    // Values can refer to other variables, but shouldn't go round in circles
+    if depth > 8 {
+        return css.to_owned();
+    }
+    let mut out = String::with_capacity(css.len());
+    let mut rest = css;
+    while let Some(at) = rest.find("var(") {
+        out.push_str(&rest[..at]);
+        let inner_start = at + "var(".len();
+        let mut depth = 1;
+        let mut close = rest.len();
+        for (i, c) in rest[inner_start..].char_indices() {
+            match c {
+                '(' => depth += 1,
+                ')' => {
+                    depth -= 1;
+                    if depth == 0 {
+                        close = inner_start + i;
+                        break;
+                    }
+                }
+                _ => {}
+            }
+        }
+        let inner = &rest[inner_start..close.min(rest.len())];
+        let (name, fallback) = match inner.split_once(',') {
+            Some((name, fallback)) => (name.trim(), Some(fallback.trim())),
+            None => (inner.trim(), None),
+        };
+        match vars.get(name).map(String::as_str).or(fallback) {
+            Some(value) => out.push_str(&resolve_variables(value, vars, depth + 1)),
+            None => out.push_str(&rest[at..(close + 1).min(rest.len())]),
+        }
+        rest = rest.get(close + 1..).unwrap_or_default();
+    }
+    out.push_str(rest);
+    out
+}
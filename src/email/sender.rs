@@ -2,11 +2,16 @@ use std::{error::Error, fmt::Display};
 
 use crate::config::config;
 
-use super::EmailKind;
+use super::{
+    render::{render, Parts},
+    transport::{Transport, TransportError},
+    EmailKind, EmailStatus,
+};
+use chrono::{Duration, Utc};
 use lettre::{
     address::AddressError,
-    message::{Mailbox, SinglePart},
-    Address, AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor,
+    message::{header::ContentType, Attachment, Mailbox, MultiPart, SinglePart},
+    Address, Message,
 };
 use sqlx::FromRow;
 use tracing::info;
@@ -19,12 +24,34 @@ struct EmailToSend {
     subject: String,
     message: String,
     to: String,
+    attempts: i32,
+}
+
+#[derive(FromRow, Clone)]
+struct AttachmentToSend {
+    filename: String,
+    content_type: String,
+    content: Vec<u8>,
 }
 
 enum EmailError {
     Address(AddressError),
     Email(lettre::error::Error),
-    Smtp(lettre::transport::smtp::Error),
+    Transport(TransportError),
+    ContentType(lettre::message::header::ContentTypeErr),
+    Database(sqlx::Error),
+    Render(String),
+}
+
+impl EmailError {
+    /// Whether sending again would fail the same way
+    fn is_permanent(&self) -> bool {
+        match self {
+            Self::Address(_) | Self::Email(_) | Self::ContentType(_) | Self::Render(_) => true,
+            Self::Transport(t) => t.is_permanent(),
+            Self::Database(_) => false,
+        }
+    }
 }
 
 impl Display for EmailError {
@@ -32,7 +59,10 @@ impl Display for EmailError {
         let s = match self {
             Self::Address(a) => a.to_string(),
             Self::Email(e) => e.to_string(),
-            Self::Smtp(s) => s.to_string(),
+            Self::Transport(t) => t.to_string(),
+            Self::ContentType(c) => c.to_string(),
+            Self::Database(d) => d.to_string(),
+            Self::Render(r) => format!("Couldn't render email: {}", r),
         };
         write!(f, "{}", s)
     }
@@ -48,12 +78,34 @@ impl From<lettre::error::Error> for EmailError {
         Self::Email(value)
     }
 }
-impl From<lettre::transport::smtp::Error> for EmailError {
-    fn from(value: lettre::transport::smtp::Error) -> Self {
-        Self::Smtp(value)
+impl From<TransportError> for EmailError {
+    fn from(value: TransportError) -> Self {
+        Self::Transport(value)
+    }
+}
+impl From<sqlx::Error> for EmailError {
+    fn from(value: sqlx::Error) -> Self {
+        Self::Database(value)
+    }
+}
+impl From<lettre::message::header::ContentTypeErr> for EmailError {
+    fn from(value: lettre::message::header::ContentTypeErr) -> Self {
+        Self::ContentType(value)
     }
 }
 
+/// Times an email is tried before it's marked dead
+const MAX_ATTEMPTS: i32 = 6;
+/// How long to wait before the first retry, doubled after each one after that
+const FIRST_RETRY_MINUTES: i64 = 5;
+
+fn backoff(attempts: i32) -> Duration {
+    Duration::minutes(FIRST_RETRY_MINUTES << (attempts - 1).clamp(0, 16))
+}
+
+/// Sends pending emails, and failed ones that are due to be retried. An email
+/// that can't be sent is retried with backoff until it runs out of attempts,
+/// or straight away marked dead if trying again won't help.
 pub async fn send_all() -> Result<(), Box<dyn Error>> {
     let emails = sqlx::query_as!(
         EmailToSend,
@@ -62,9 +114,10 @@ pub async fn send_all() -> Result<(), Box<dyn Error>> {
             kind AS "kind: _", 
             subject, 
             message, 
-            address as to
+            address as to,
+            attempts
         FROM email
-        WHERE status = 'pending'"#
+        WHERE status = 'pending' OR (status = 'failed' AND next_attempt_at <= now())"#
     )
     .fetch_all(config().pool())
     .await?;
@@ -77,56 +130,94 @@ pub async fn send_all() -> Result<(), Box<dyn Error>> {
     let address = config().mailing_address().expect("Emailing not set up");
     let mailbox = Mailbox::new(None, address.clone());
     for email in emails {
-        let this_id = email.id;
-        let res = try_build(email, mailbox.clone());
-        if let Ok(msg) = res {
-            if let Err(e) = try_send(msg, transport).await {
-                // Mark email as failed
-                tracing::error!("Failed to send email: {}", e);
-                sqlx::query!(
-                    "UPDATE email SET status = 'failed', sent_at = now() WHERE id = $1",
-                    this_id
-                )
-                .execute(config().pool())
-                .await?; // Should probably not exit out at this point?
-            } else {
-                // Mark email as sent
-                sqlx::query!(
-                    "UPDATE email SET status = 'sent', sent_at = now() WHERE id = $1",
-                    this_id
-                )
-                .execute(config().pool())
-                .await?; // Should probably not exit out at this point?
-            };
-        } else {
-            // Mark email as failed
-            sqlx::query!(
-                "UPDATE email SET status = 'failed', sent_at = now() WHERE id = $1",
-                this_id
+        let (this_id, attempts) = (email.id, email.attempts + 1);
+        // One email going wrong shouldn't hold up the rest
+        let recorded = match try_send(email, mailbox.clone(), transport).await {
+            Ok(()) => sqlx::query!(
+                "UPDATE email SET status = 'sent', sent_at = now(), attempts = $2,
+                    next_attempt_at = NULL
+                WHERE id = $1",
+                this_id,
+                attempts
             )
             .execute(config().pool())
-            .await?; // Should probably not exit out at this point?
+            .await
+            .map(|_| ()),
+            Err(e) => record_failure(this_id, attempts, e).await,
+        };
+        if let Err(e) = recorded {
+            tracing::error!("Couldn't record the result of sending email {this_id}: {e}");
         }
     }
     Ok(())
 }
 
-fn try_build(email: EmailToSend, mbox: Mailbox) -> Result<Message, EmailError> {
-    let body = match email.kind {
-        EmailKind::Html => SinglePart::html(email.message),
-        EmailKind::Text => SinglePart::plain(email.message),
+async fn record_failure(id: Uuid, attempts: i32, error: EmailError) -> Result<(), sqlx::Error> {
+    let dead = error.is_permanent() || attempts >= MAX_ATTEMPTS;
+    let (status, next_attempt_at) = if dead {
+        tracing::error!("Giving up on email {id} after {attempts} attempts: {error}");
+        (EmailStatus::Dead, None)
+    } else {
+        tracing::warn!("Failed to send email {id}, will retry: {error}");
+        (EmailStatus::Failed, Some(Utc::now() + backoff(attempts)))
     };
-    Ok(Message::builder()
-        .subject(email.subject)
-        .from(mbox)
-        .to(Mailbox::new(None, Address::try_from(email.to)?))
-        .singlepart(body)?)
+    sqlx::query!(
+        "UPDATE email SET status = $2, attempts = $3, last_error = $4, next_attempt_at = $5
+        WHERE id = $1",
+        id,
+        status as EmailStatus,
+        attempts,
+        error.to_string(),
+        next_attempt_at
+    )
+    .execute(config().pool())
+    .await?;
+    Ok(())
 }
 
 async fn try_send(
-    msg: Message,
-    transport: &AsyncSmtpTransport<Tokio1Executor>,
+    email: EmailToSend,
+    mbox: Mailbox,
+    transport: &dyn Transport,
 ) -> Result<(), EmailError> {
+    let attachments = sqlx::query_as!(
+        AttachmentToSend,
+        "SELECT filename, content_type, content FROM email_attachment WHERE email_id = $1",
+        email.id
+    )
+    .fetch_all(config().pool())
+    .await?;
+    let msg = try_build(email, attachments, mbox)?;
     transport.send(msg).await?;
     Ok(())
 }
+
+fn try_build(
+    email: EmailToSend,
+    attachments: Vec<AttachmentToSend>,
+    mbox: Mailbox,
+) -> Result<Message, EmailError> {
+    let Parts { html, text } = render(email.kind, &email.message).map_err(EmailError::Render)?;
+    let builder = Message::builder()
+        .subject(email.subject)
+        .from(mbox)
+        .to(Mailbox::new(None, Address::try_from(email.to)?));
+    if attachments.is_empty() {
+        return Ok(match html {
+            Some(html) => builder.multipart(MultiPart::alternative_plain_html(text, html))?,
+            None => builder.singlepart(SinglePart::plain(text))?,
+        });
+    }
+
+    let mut parts = match html {
+        Some(html) => MultiPart::mixed().multipart(MultiPart::alternative_plain_html(text, html)),
+        None => MultiPart::mixed().singlepart(SinglePart::plain(text)),
+    };
+    for attachment in attachments {
+        let content_type = ContentType::parse(&attachment.content_type)?;
+        parts = parts.singlepart(
+            Attachment::new(attachment.filename).body(attachment.content, content_type),
+        );
+    }
+    Ok(builder.multipart(parts)?)
+}
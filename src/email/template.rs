@@ -0,0 +1,245 @@
+use std::{fmt, sync::LazyLock};
+
+use askama::Template;
+use cafe_website::filters;
+use chrono::{DateTime, Utc};
+use regex::{Captures, Regex};
+use serde::{Deserialize, Serialize};
+use sqlx::{Executor, Postgres};
+use uuid::Uuid;
+
+use crate::{config, models::Shift, worker::Worker};
+
+static VARIABLE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\{([a-z_]+)\}").expect("Variable regex should be valid"));
+
+/// Emails an event can write its own version of
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, sqlx::Type, Debug)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(type_name = "email_template_kind", rename_all = "lowercase")]
+pub enum TemplateKind {
+    Signup,
+    Reminder,
+    /// Only for coordinators, since other roles aren't invited to an event
+    Invite,
+}
+
+const WORKER_VARIABLES: &[(&str, &str)] = &[
+    ("first_name", "The worker's first name"),
+    ("last_name", "The worker's last name"),
+    ("shifts", "A list of the shifts the email is about"),
+    (
+        "link",
+        "A link to the worker's page, where they can manage their shifts",
+    ),
+    ("event_name", "The name of the event"),
+    ("admin_name", "Your name, from the website's settings"),
+    ("admin_email", "Your email address"),
+    ("admin_phone", "Your phone number"),
+];
+
+const INVITE_VARIABLES: &[(&str, &str)] = &[
+    ("inviter", "The name of whoever sent the invite"),
+    ("link", "The link to create their account"),
+    ("event_name", "The name of the event they'll coordinate"),
+    ("admin_name", "Your name, from the website's settings"),
+    ("admin_email", "Your email address"),
+    ("admin_phone", "Your phone number"),
+];
+
+impl TemplateKind {
+    pub const ALL: [TemplateKind; 3] = [
+        TemplateKind::Signup,
+        TemplateKind::Reminder,
+        TemplateKind::Invite,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Self::Signup => "Signup confirmation",
+            Self::Reminder => "Shift reminder",
+            Self::Invite => "Coordinator invite",
+        }
+    }
+
+    /// The variables the email can use, and what they're filled in with
+    pub fn variables(&self) -> &'static [(&'static str, &'static str)] {
+        match self {
+            Self::Signup | Self::Reminder => WORKER_VARIABLES,
+            Self::Invite => INVITE_VARIABLES,
+        }
+    }
+
+    /// A starting point for an event that hasn't written its own yet
+    pub fn starter_subject(&self) -> &'static str {
+        match self {
+            Self::Signup => "Thanks {first_name}!",
+            Self::Reminder => "Your shifts at {event_name}",
+            Self::Invite => "{inviter} has invited you to help coordinate {event_name}",
+        }
+    }
+
+    pub fn starter_body(&self) -> &'static str {
+        match self {
+            Self::Signup => {
+                "Dear {first_name},\n\n\
+                Thank you for volunteering at {event_name}! We will count on you for \
+                the following shift:\n\n\
+                {shifts}\n\n\
+                [Click here]({link}) to view and manage the shifts you are signed up for.\n\n\
+                Thank you,  \n{admin_name}  \n{admin_phone}  \n{admin_email}\n"
+            }
+            Self::Reminder => {
+                "Dear {first_name} {last_name},\n\n\
+                Thank you for volunteering at {event_name}. We will count on you for \
+                the following shifts:\n\n\
+                {shifts}\n\n\
+                [Click here]({link}) to view the shifts you have signed up for.\n\n\
+                Thank you,  \n{admin_name}  \n{admin_phone}  \n{admin_email}\n"
+            }
+            Self::Invite => {
+                "{inviter} has invited you to help coordinate {event_name}! \
+                [Click here]({link}) or copy and paste the link below into a browser \
+                to create your account.\n\n\
+                {link}\n"
+            }
+        }
+    }
+}
+
+impl fmt::Display for TemplateKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            Self::Signup => "signup",
+            Self::Reminder => "reminder",
+            Self::Invite => "invite",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+/// An event's own subject and body for one kind of email
+pub struct EmailTemplate {
+    pub subject: String,
+    /// Markdown
+    pub body: String,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Template)]
+#[template(path = "email/messages/custom.html")]
+struct CustomMessage {
+    body: String,
+}
+
+impl EmailTemplate {
+    /// The subject and message, with the variables filled in
+    pub fn render(&self, variables: &Variables) -> Result<(String, String), askama::Error> {
+        let message = CustomMessage {
+            body: variables.fill(&self.body),
+        }
+        .render()?;
+        Ok((variables.fill(&self.subject), message))
+    }
+}
+
+/// The event's own version of the email, if it has one. Without one, the
+/// built-in message is sent.
+pub async fn find_template<'c>(
+    event_id: Uuid,
+    kind: TemplateKind,
+    executor: impl Executor<'c, Database = Postgres>,
+) -> Result<Option<EmailTemplate>, sqlx::Error> {
+    sqlx::query_as!(
+        EmailTemplate,
+        "SELECT subject, body, updated_at FROM email_template WHERE event_id = $1 AND kind = $2",
+        event_id,
+        kind as TemplateKind
+    )
+    .fetch_optional(executor)
+    .await
+}
+
+/// Values for the variables in one email
+#[derive(Clone, Default)]
+pub struct Variables(Vec<(&'static str, String)>);
+
+impl Variables {
+    /// The event's name and the admin's contact details, which every template
+    /// can use
+    pub async fn for_event<'c>(
+        event_id: Uuid,
+        executor: impl Executor<'c, Database = Postgres>,
+    ) -> Result<Variables, sqlx::Error> {
+        let event_name = sqlx::query_scalar!("SELECT name FROM event WHERE id = $1", event_id)
+            .fetch_one(executor)
+            .await?;
+        let admin = &config().admin;
+        Ok(Variables(vec![
+            ("event_name", event_name),
+            ("admin_name", admin.name.clone()),
+            ("admin_email", admin.email.clone()),
+            ("admin_phone", admin.phone.clone()),
+        ]))
+    }
+
+    pub fn set(mut self, name: &'static str, value: impl Into<String>) -> Variables {
+        self.0.retain(|(n, _)| *n != name);
+        self.0.push((name, value.into()));
+        self
+    }
+
+    pub fn worker(self, worker: &Worker) -> Variables {
+        self.set("first_name", worker.name_first.clone())
+            .set("last_name", worker.name_last.clone())
+    }
+
+    pub fn shifts(self, shifts: &[Shift]) -> Variables {
+        self.set("shifts", shift_list(shifts))
+    }
+
+    /// Replaces each `{name}` with its value. Ones without a value are left
+    /// as they are.
+    pub fn fill(&self, text: &str) -> String {
+        VARIABLE
+            .replace_all(text, |caps: &Captures| {
+                self.0
+                    .iter()
+                    .find(|(name, _)| *name == &caps[1])
+                    .map_or_else(|| caps[0].to_owned(), |(_, value)| value.clone())
+            })
+            .into_owned()
+    }
+}
+
+/// Variables in the text that the kind of email doesn't have
+pub fn unknown_variables(kind: TemplateKind, text: &str) -> Vec<String> {
+    let mut unknown: Vec<String> = VARIABLE
+        .captures_iter(text)
+        .map(|caps| caps[1].to_owned())
+        .filter(|name| !kind.variables().iter().any(|(known, _)| known == name))
+        .collect();
+    unknown.sort();
+    unknown.dedup();
+    unknown
+}
+
+/// The shifts as a markdown list
+pub fn shift_list(shifts: &[Shift]) -> String {
+    if shifts.is_empty() {
+        return "_No shifts yet_".to_owned();
+    }
+    shifts
+        .iter()
+        .map(|s| {
+            format!(
+                "- **{}**, {} from {} to {}",
+                s.title,
+                filters::date_long(&s.date).unwrap_or_default(),
+                filters::time_short(&s.start_time).unwrap_or_default(),
+                filters::time_short(&s.end_time).unwrap_or_default()
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
@@ -0,0 +1,208 @@
+use askama::Template;
+use axum::{extract::Path, http::StatusCode, response::Html};
+use axum_extra::extract::Form;
+use cafe_website::{error::ISE, filters, templates::Card, AppError, Redirect};
+use serde::Deserialize;
+use uuid::Uuid;
+
+use crate::{
+    config,
+    models::{Event, Shift},
+};
+
+use super::{
+    render::{render, Parts},
+    template::{find_template, unknown_variables, EmailTemplate, TemplateKind, Variables},
+    EmailKind,
+};
+
+#[derive(Template)]
+#[template(path = "email/template_list.html")]
+pub struct TemplateListTemplate {
+    events: Vec<Event>,
+    custom: Vec<(Uuid, TemplateKind)>,
+}
+
+impl TemplateListTemplate {
+    fn is_custom(&self, event_id: &Uuid, kind: &TemplateKind) -> bool {
+        self.custom.contains(&(*event_id, *kind))
+    }
+}
+
+pub async fn template_list() -> Result<Card<TemplateListTemplate>, AppError> {
+    let events = sqlx::query_as!(Event, "SELECT * FROM event ORDER BY name")
+        .fetch_all(config().pool())
+        .await?;
+    let custom =
+        sqlx::query!(r#"SELECT event_id, kind as "kind: TemplateKind" FROM email_template"#)
+            .fetch_all(config().pool())
+            .await?
+            .into_iter()
+            .map(|r| (r.event_id, r.kind))
+            .collect();
+    Ok(Card {
+        class: None,
+        title: "Email Templates".to_owned(),
+        child: TemplateListTemplate { events, custom },
+        show_x: false,
+    })
+}
+
+#[derive(Template)]
+#[template(path = "email/template_edit.html")]
+pub struct TemplateEditTemplate {
+    event_id: Uuid,
+    kind: TemplateKind,
+    template: Option<EmailTemplate>,
+}
+
+impl TemplateEditTemplate {
+    fn subject(&self) -> &str {
+        self.template
+            .as_ref()
+            .map_or(self.kind.starter_subject(), |t| &t.subject)
+    }
+
+    fn body(&self) -> &str {
+        self.template
+            .as_ref()
+            .map_or(self.kind.starter_body(), |t| &t.body)
+    }
+}
+
+pub async fn edit_form(
+    Path((event_id, kind)): Path<(Uuid, TemplateKind)>,
+) -> Result<Card<TemplateEditTemplate>, AppError> {
+    let event_name = sqlx::query_scalar!("SELECT name FROM event WHERE id = $1", event_id)
+        .fetch_one(config().pool())
+        .await?;
+    let template = find_template(event_id, kind, config().pool()).await?;
+    Ok(Card {
+        class: None,
+        title: format!("{}: {}", event_name, kind.label()),
+        child: TemplateEditTemplate {
+            event_id,
+            kind,
+            template,
+        },
+        show_x: false,
+    })
+}
+
+#[derive(Deserialize)]
+pub struct TemplateForm {
+    #[serde(default)]
+    subject: String,
+    #[serde(default)]
+    body: String,
+}
+
+pub async fn save(
+    Path((event_id, kind)): Path<(Uuid, TemplateKind)>,
+    Form(form): Form<TemplateForm>,
+) -> Result<Html<&'static str>, AppError> {
+    if form.subject.trim().is_empty() {
+        return Err(AppError::inline(
+            StatusCode::BAD_REQUEST,
+            "The subject can't be empty",
+        ));
+    }
+    if form.body.trim().is_empty() {
+        return Err(AppError::inline(
+            StatusCode::BAD_REQUEST,
+            "The message can't be empty. Reset it to use the built-in one.",
+        ));
+    }
+    if !unknown_variables(kind, &form.subject).is_empty()
+        || !unknown_variables(kind, &form.body).is_empty()
+    {
+        return Err(AppError::inline(
+            StatusCode::BAD_REQUEST,
+            "This uses a variable the email doesn't have. Preview it to see which.",
+        ));
+    }
+
+    sqlx::query!(
+        "INSERT INTO email_template (event_id, kind, subject, body) VALUES ($1, $2, $3, $4)
+        ON CONFLICT (event_id, kind) DO UPDATE
+        SET subject = EXCLUDED.subject, body = EXCLUDED.body, updated_at = now()",
+        event_id,
+        kind as TemplateKind,
+        form.subject.trim(),
+        form.body
+    )
+    .execute(config().pool())
+    .await?;
+    Ok(Html("Saved"))
+}
+
+/// Goes back to the built-in message
+pub async fn reset(
+    Path((event_id, kind)): Path<(Uuid, TemplateKind)>,
+) -> Result<Redirect, AppError> {
+    sqlx::query!(
+        "DELETE FROM email_template WHERE event_id = $1 AND kind = $2",
+        event_id,
+        kind as TemplateKind
+    )
+    .execute(config().pool())
+    .await?;
+    Ok(Redirect::to(format!(
+        "/email/template/{}/{}",
+        event_id, kind
+    )))
+}
+
+#[derive(Template)]
+#[template(path = "email/template_preview.html")]
+pub struct TemplatePreview {
+    subject: String,
+    parts: Parts,
+    unknown: Vec<String>,
+}
+
+/// Renders the form as it is, without saving it, for a made up worker and the
+/// event's first shifts
+pub async fn preview(
+    Path((event_id, kind)): Path<(Uuid, TemplateKind)>,
+    Form(form): Form<TemplateForm>,
+) -> Result<TemplatePreview, AppError> {
+    let mut unknown = unknown_variables(kind, &form.subject);
+    unknown.extend(unknown_variables(kind, &form.body));
+    unknown.sort();
+    unknown.dedup();
+
+    let mut shifts = sqlx::query_as!(
+        Shift,
+        "SELECT * FROM shift WHERE event_id = $1 ORDER BY date, start_time LIMIT 3",
+        event_id
+    )
+    .fetch_all(config().pool())
+    .await?;
+    if kind == TemplateKind::Signup {
+        shifts.truncate(1);
+    }
+    let variables = Variables::for_event(event_id, config().pool())
+        .await?
+        .set("first_name", "Jane")
+        .set("last_name", "Doe")
+        .set("inviter", config().admin.name.clone())
+        .shifts(&shifts)
+        .set("link", format!("{}/worker/example", config().url()));
+
+    let template = EmailTemplate {
+        subject: form.subject,
+        body: form.body,
+        updated_at: chrono::Utc::now(),
+    };
+    let (subject, message) = template.render(&variables)?;
+    let parts = render(EmailKind::Html, &message).map_err(|e| {
+        tracing::error!("Couldn't render template preview: {e}");
+        ISE
+    })?;
+    Ok(TemplatePreview {
+        subject,
+        parts,
+        unknown,
+    })
+}
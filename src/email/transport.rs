@@ -0,0 +1,120 @@
+use std::{fmt, io, path::PathBuf, sync::Mutex};
+
+use futures_util::future::BoxFuture;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+use tracing::info;
+use uuid::Uuid;
+
+/// Where built emails are delivered, picked by `transport` in the `[email]`
+/// section of the config
+pub trait Transport: std::any::Any + Send + Sync {
+    fn send(&self, message: Message) -> BoxFuture<'_, Result<(), TransportError>>;
+}
+
+pub enum TransportError {
+    Smtp(lettre::transport::smtp::Error),
+    Io(io::Error),
+}
+
+impl TransportError {
+    /// Whether sending again would fail the same way
+    pub fn is_permanent(&self) -> bool {
+        match self {
+            Self::Smtp(s) => s.is_permanent(),
+            Self::Io(_) => false,
+        }
+    }
+}
+
+impl fmt::Display for TransportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Smtp(s) => write!(f, "{}", s),
+            Self::Io(i) => write!(f, "Couldn't write email: {}", i),
+        }
+    }
+}
+
+impl From<lettre::transport::smtp::Error> for TransportError {
+    fn from(value: lettre::transport::smtp::Error) -> Self {
+        Self::Smtp(value)
+    }
+}
+impl From<io::Error> for TransportError {
+    fn from(value: io::Error) -> Self {
+        Self::Io(value)
+    }
+}
+
+impl Transport for AsyncSmtpTransport<Tokio1Executor> {
+    fn send(&self, message: Message) -> BoxFuture<'_, Result<(), TransportError>> {
+        Box::pin(async move {
+            AsyncTransport::send(self, message).await?;
+            Ok(())
+        })
+    }
+}
+
+/// Writes each email to a file instead of sending it, for trying things out
+/// locally
+pub struct FileTransport {
+    dir: PathBuf,
+    /// Lay the directory out as a maildir so mail clients can open it,
+    /// rather than dropping `.eml` files straight into it
+    maildir: bool,
+}
+
+impl FileTransport {
+    pub fn new(dir: PathBuf, maildir: bool) -> FileTransport {
+        FileTransport { dir, maildir }
+    }
+}
+
+impl Transport for FileTransport {
+    fn send(&self, message: Message) -> BoxFuture<'_, Result<(), TransportError>> {
+        Box::pin(async move {
+            let name = format!("{}.eml", Uuid::now_v7());
+            let contents = message.formatted();
+            let path = if self.maildir {
+                // Written to tmp first so readers never see half a message
+                let (tmp, new) = (self.dir.join("tmp"), self.dir.join("new"));
+                tokio::fs::create_dir_all(&tmp).await?;
+                tokio::fs::create_dir_all(&new).await?;
+                tokio::fs::write(tmp.join(&name), contents).await?;
+                tokio::fs::rename(tmp.join(&name), new.join(&name)).await?;
+                new.join(name)
+            } else {
+                tokio::fs::create_dir_all(&self.dir).await?;
+                let path = self.dir.join(name);
+                tokio::fs::write(&path, contents).await?;
+                path
+            };
+            info!(path = %path.display(), "Wrote email");
+            Ok(())
+        })
+    }
+}
+
+/// Keeps every email it's given, so they can be looked at without sending
+/// anything
+#[derive(Default)]
+pub struct MemoryTransport {
+    sent: Mutex<Vec<Message>>,
+}
+
+impl MemoryTransport {
+    /// Everything sent so far, oldest first
+    pub fn messages(&self) -> Vec<Message> {
+        self.sent.lock().expect("Outbox lock poisoned").clone()
+    }
+}
+
+impl Transport for MemoryTransport {
+    fn send(&self, message: Message) -> BoxFuture<'_, Result<(), TransportError>> {
+        self.sent
+            .lock()
+            .expect("Outbox lock poisoned")
+            .push(message);
+        Box::pin(async { Ok(()) })
+    }
+}
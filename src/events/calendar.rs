@@ -0,0 +1,173 @@
+use std::collections::HashMap;
+
+use askama::Template;
+use axum::{
+    extract::{Path, Query},
+    http::StatusCode,
+};
+use cafe_website::{templates::Card, AppError};
+use serde::Deserialize;
+use uuid::Uuid;
+
+use crate::{
+    accounts::EventEditor,
+    calendar::{Calendar, CalendarEvent},
+    config,
+    models::{Event, Shift},
+};
+
+#[derive(Deserialize)]
+pub struct FeedQuery {
+    token: Option<Uuid>,
+}
+
+/// The event's feed token, unless it's been turned off
+async fn current_token(event_id: Uuid) -> Result<Option<Uuid>, sqlx::Error> {
+    sqlx::query_scalar!(
+        "SELECT id FROM event_feed WHERE event_id = $1 AND revoked_at IS NULL
+        ORDER BY created_at DESC LIMIT 1",
+        event_id
+    )
+    .fetch_optional(config().pool())
+    .await
+}
+
+/// The url calendar apps subscribe to. They don't share the browser's session,
+/// so it needs the token.
+fn feed_url(event_id: Uuid, token: Uuid) -> String {
+    format!(
+        "{}/event/{}/schedule.ics?token={}",
+        config().url(),
+        event_id,
+        token
+    )
+}
+
+/// Every shift in the event, with who's working it, as a calendar. Open to
+/// users who can edit the event, or anyone with the event's current feed token.
+pub async fn schedule_feed(
+    editor: Option<EventEditor>,
+    Path(event_id): Path<Uuid>,
+    Query(FeedQuery { token }): Query<FeedQuery>,
+) -> Result<Calendar, AppError> {
+    let allowed = editor.is_some()
+        || match token {
+            Some(token) => current_token(event_id).await? == Some(token),
+            None => false,
+        };
+    if !allowed {
+        return Err(AppError::inline(
+            StatusCode::UNAUTHORIZED,
+            "This calendar link has been turned off",
+        ));
+    }
+
+    let event = sqlx::query_as!(Event, "SELECT * FROM event WHERE id = $1", event_id)
+        .fetch_one(config().pool())
+        .await?;
+    let shifts = sqlx::query_as!(
+        Shift,
+        "SELECT * FROM shift WHERE event_id = $1 ORDER BY date, start_time",
+        event_id
+    )
+    .fetch_all(config().pool())
+    .await?;
+    let assigned = sqlx::query!(
+        "SELECT sa.shift_id, w.name_first, w.name_last FROM shift_assignment as sa
+        INNER JOIN worker as w ON w.id = sa.worker_id
+        INNER JOIN shift as s ON s.id = sa.shift_id
+        WHERE s.event_id = $1
+        ORDER BY sa.created_at",
+        event_id
+    )
+    .fetch_all(config().pool())
+    .await?;
+
+    let mut names: HashMap<Uuid, Vec<String>> = HashMap::new();
+    for row in assigned {
+        names
+            .entry(row.shift_id)
+            .or_default()
+            .push(format!("{} {}", row.name_first, row.name_last));
+    }
+
+    let events = shifts
+        .iter()
+        .map(|shift| {
+            let mut event = CalendarEvent::from(shift);
+            let workers = names.remove(&shift.id).unwrap_or_default();
+            let staffing = format!(
+                "{} of {} filled: {}",
+                workers.len(),
+                shift.capacity,
+                workers.join(", ")
+            );
+            event.description = Some(match event.description {
+                Some(description) => format!("{staffing}\n\n{description}"),
+                None => staffing,
+            });
+            event
+        })
+        .collect();
+
+    Ok(Calendar::new(event.name, events))
+}
+
+#[derive(Template)]
+#[template(path = "events/feed.html")]
+pub struct FeedTemplate {
+    event_id: Uuid,
+    url: Option<String>,
+}
+
+impl FeedTemplate {
+    async fn load(event_id: Uuid) -> Result<FeedTemplate, AppError> {
+        let url = current_token(event_id)
+            .await?
+            .map(|token| feed_url(event_id, token));
+        Ok(FeedTemplate { event_id, url })
+    }
+}
+
+pub async fn feed_form(Path(event_id): Path<Uuid>) -> Result<Card<FeedTemplate>, AppError> {
+    Ok(Card::modal(
+        "Calendar Feed".to_owned(),
+        FeedTemplate::load(event_id).await?,
+    ))
+}
+
+/// Replaces the event's feed link, so the old one stops working
+pub async fn new_feed(Path(event_id): Path<Uuid>) -> Result<FeedTemplate, AppError> {
+    let mut tran = config().pool().begin().await?;
+    sqlx::query!(
+        "UPDATE event_feed SET revoked_at = now() WHERE event_id = $1 AND revoked_at IS NULL",
+        event_id
+    )
+    .execute(&mut *tran)
+    .await?;
+    let token = sqlx::query_scalar!(
+        "INSERT INTO event_feed (event_id) VALUES ($1) RETURNING id",
+        event_id
+    )
+    .fetch_one(&mut *tran)
+    .await?;
+    tran.commit().await?;
+    Ok(FeedTemplate {
+        event_id,
+        url: Some(feed_url(event_id, token)),
+    })
+}
+
+/// Turns the feed off until a new link is made
+pub async fn revoke_feed(Path(event_id): Path<Uuid>) -> Result<FeedTemplate, AppError> {
+    sqlx::query!(
+        "UPDATE event_feed SET revoked_at = now() WHERE event_id = $1 AND revoked_at IS NULL",
+        event_id
+    )
+    .execute(config().pool())
+    .await?;
+    Ok(FeedTemplate {
+        event_id,
+        url: None,
+    })
+}
@@ -0,0 +1,363 @@
+use std::collections::{HashMap, HashSet};
+
+use askama::Template;
+use axum::{
+    extract::{Multipart, Path},
+    http::StatusCode,
+    Form,
+};
+use cafe_website::{filters, templates::Card, AppError, Redirect};
+use chrono::{NaiveDate, NaiveTime};
+use serde::Deserialize;
+use uuid::Uuid;
+
+use crate::{config, models::Shift, shift::assignment, worker::Worker};
+
+#[derive(Template)]
+#[template(path = "events/import.html")]
+pub struct ImportForm {
+    event_id: Uuid,
+}
+
+pub async fn import_form(Path(event_id): Path<Uuid>) -> Card<ImportForm> {
+    Card::modal("Import Schedule".to_owned(), ImportForm { event_id })
+}
+
+/// A row of the uploaded spreadsheet, as written
+#[derive(Deserialize)]
+struct CsvRow {
+    date: String,
+    start: String,
+    end: String,
+    title: String,
+    description: Option<String>,
+    public: Option<String>,
+    capacity: Option<String>,
+    worker_email: Option<String>,
+}
+
+/// A row after validation. Only imported if there are no errors in the file.
+struct ImportRow {
+    line: u64,
+    date: Option<NaiveDate>,
+    start_time: Option<NaiveTime>,
+    end_time: Option<NaiveTime>,
+    title: String,
+    description: Option<String>,
+    public_signup: bool,
+    capacity: i32,
+    worker: Option<Worker>,
+    errors: Vec<String>,
+}
+
+impl ImportRow {
+    fn date_text(&self) -> String {
+        self.date
+            .and_then(|d| filters::date_short(&d).ok())
+            .unwrap_or_default()
+    }
+
+    fn time_text(&self) -> String {
+        let time = |t: Option<NaiveTime>| t.and_then(|t| filters::time_short(&t).ok());
+        format!(
+            "{} - {}",
+            time(self.start_time).unwrap_or_default(),
+            time(self.end_time).unwrap_or_default()
+        )
+    }
+}
+
+#[derive(Template)]
+#[template(path = "events/import_preview.html")]
+pub struct ImportPreview {
+    event_id: Uuid,
+    csv: String,
+    rows: Vec<ImportRow>,
+    /// Problems with the file as a whole
+    error: Option<String>,
+}
+
+impl ImportPreview {
+    fn is_valid(&self) -> bool {
+        self.error.is_none()
+            && !self.rows.is_empty()
+            && self.rows.iter().all(|r| r.errors.is_empty())
+    }
+}
+
+fn parse_date(s: &str) -> Option<NaiveDate> {
+    ["%Y-%m-%d", "%m/%d/%Y", "%m/%d/%y"]
+        .iter()
+        .find_map(|f| NaiveDate::parse_from_str(s, f).ok())
+}
+
+fn parse_time(s: &str) -> Option<NaiveTime> {
+    ["%H:%M", "%H:%M:%S", "%I:%M %p", "%I:%M%p", "%I %p"]
+        .iter()
+        .find_map(|f| NaiveTime::parse_from_str(&s.to_uppercase(), f).ok())
+}
+
+fn parse_bool(s: &str) -> Option<bool> {
+    match s.to_lowercase().as_str() {
+        "" | "true" | "yes" | "y" | "1" => Some(true),
+        "false" | "no" | "n" | "0" => Some(false),
+        _ => None,
+    }
+}
+
+/// Parses and checks the file against the event's days and workers without
+/// changing anything
+async fn validate(event_id: Uuid, csv: String) -> Result<ImportPreview, AppError> {
+    let days: HashSet<NaiveDate> =
+        sqlx::query_scalar!("SELECT date FROM day WHERE event_id = $1", event_id)
+            .fetch_all(config().pool())
+            .await?
+            .into_iter()
+            .collect();
+
+    let mut reader = csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .flexible(true)
+        .from_reader(csv.as_bytes());
+    let mut rows = vec![];
+    // Spreadsheets tend to capitalize their headers
+    let headers: csv::StringRecord = match reader.headers() {
+        Ok(h) => h
+            .iter()
+            .map(|h| h.to_lowercase().replace(' ', "_"))
+            .collect(),
+        Err(e) => {
+            return Ok(ImportPreview {
+                event_id,
+                csv,
+                rows,
+                error: Some(format!("Couldn't read the file: {e}")),
+            })
+        }
+    };
+    let mut workers: HashMap<String, Option<Worker>> = HashMap::new();
+    for record in reader.records() {
+        let record = match record {
+            Ok(r) => r,
+            Err(e) => {
+                return Ok(ImportPreview {
+                    event_id,
+                    csv,
+                    rows,
+                    error: Some(format!("Couldn't read the file: {e}")),
+                })
+            }
+        };
+        let line = record.position().map_or(0, |p| p.line());
+        let raw: CsvRow = match record.deserialize(Some(&headers)) {
+            Ok(r) => r,
+            Err(e) => {
+                return Ok(ImportPreview {
+                    event_id,
+                    csv,
+                    rows,
+                    error: Some(format!(
+                        "Line {line}: {e}. The columns should be date, start, end, title, \
+                        description, public, capacity and worker_email"
+                    )),
+                })
+            }
+        };
+
+        let mut errors = vec![];
+        let date = parse_date(&raw.date);
+        match date {
+            None => errors.push(format!("\"{}\" isn't a date", raw.date)),
+            Some(d) if !days.contains(&d) => errors.push(format!("{d} isn't a day of this event")),
+            _ => {}
+        }
+        let start_time = parse_time(&raw.start);
+        if start_time.is_none() {
+            errors.push(format!("\"{}\" isn't a start time", raw.start));
+        }
+        let end_time = parse_time(&raw.end);
+        if end_time.is_none() {
+            errors.push(format!("\"{}\" isn't an end time", raw.end));
+        }
+        if let (Some(start), Some(end)) = (start_time, end_time) {
+            if end <= start {
+                errors.push("Ends before it starts".to_owned());
+            }
+        }
+        if raw.title.is_empty() {
+            errors.push("Missing a title".to_owned());
+        }
+        let public_signup = parse_bool(raw.public.as_deref().unwrap_or("")).unwrap_or_else(|| {
+            errors.push("Public should be yes or no".to_owned());
+            true
+        });
+        let capacity = match raw.capacity.as_deref().unwrap_or("") {
+            "" => 1,
+            c => c.parse().ok().filter(|&c| c > 0).unwrap_or_else(|| {
+                errors.push(format!("\"{c}\" isn't a number of spots"));
+                1
+            }),
+        };
+
+        let worker = match raw
+            .worker_email
+            .map(|e| e.to_lowercase())
+            .filter(|e| !e.is_empty())
+        {
+            Some(email) => {
+                if !workers.contains_key(&email) {
+                    let found =
+                        sqlx::query_as!(Worker, "SELECT * FROM worker WHERE email = $1", email)
+                            .fetch_optional(config().pool())
+                            .await?;
+                    workers.insert(email.clone(), found);
+                }
+                let found = workers[&email].clone();
+                if found.is_none() {
+                    errors.push(format!("No worker with the email {email}"));
+                }
+                found
+            }
+            None => None,
+        };
+
+        if let (Some(worker), Some(date), Some(start_time), Some(end_time)) =
+            (&worker, date, start_time, end_time)
+        {
+            let shift = Shift {
+                id: Uuid::nil(),
+                event_id,
+                date,
+                start_time,
+                end_time,
+                title: raw.title.clone(),
+                description: None,
+                public_signup,
+                capacity,
+                adults_only: false,
+            };
+            if assignment::overlaps(&shift, worker.id, config().pool()).await? {
+                errors.push("This worker is already on an overlapping shift".to_owned());
+            }
+        }
+
+        rows.push(ImportRow {
+            line,
+            date,
+            start_time,
+            end_time,
+            title: raw.title,
+            description: raw.description.filter(|d| !d.is_empty()),
+            public_signup,
+            capacity,
+            worker,
+            errors,
+        });
+    }
+
+    // Workers can't be given overlapping shifts in the same file
+    for i in 0..rows.len() {
+        let Some(worker_id) = rows[i].worker.as_ref().map(|w| w.id) else {
+            continue;
+        };
+        let clash = rows[..i].iter().any(|other| {
+            other.worker.as_ref().is_some_and(|w| w.id == worker_id)
+                && other.date == rows[i].date
+                && other.start_time < rows[i].end_time
+                && other.end_time > rows[i].start_time
+        });
+        if clash {
+            rows[i]
+                .errors
+                .push("This worker is already on an overlapping shift in the file".to_owned());
+        }
+    }
+
+    let error = rows
+        .is_empty()
+        .then(|| "The file has no shifts in it".to_owned());
+    Ok(ImportPreview {
+        event_id,
+        csv,
+        rows,
+        error,
+    })
+}
+
+pub async fn preview(
+    Path(event_id): Path<Uuid>,
+    mut multipart: Multipart,
+) -> Result<ImportPreview, AppError> {
+    let mut csv = None;
+    while let Some(field) = multipart
+        .next_field()
+        .await
+        .map_err(|_| AppError::inline(StatusCode::BAD_REQUEST, "Couldn't read the upload"))?
+    {
+        if field.name() == Some("file") {
+            csv = Some(field.text().await.map_err(|_| {
+                AppError::inline(StatusCode::BAD_REQUEST, "The file should be a CSV")
+            })?);
+        }
+    }
+    let csv = csv.ok_or(AppError::inline(
+        StatusCode::BAD_REQUEST,
+        "Choose a file to import",
+    ))?;
+    validate(event_id, csv).await
+}
+
+#[derive(Deserialize)]
+pub struct ImportBody {
+    csv: String,
+}
+
+pub async fn import(
+    Path(event_id): Path<Uuid>,
+    Form(ImportBody { csv }): Form<ImportBody>,
+) -> Result<Redirect, AppError> {
+    // Checked again in case the schedule changed since the preview
+    let preview = validate(event_id, csv).await?;
+    if !preview.is_valid() {
+        return Err(AppError::inline(
+            StatusCode::BAD_REQUEST,
+            "The file has errors. Upload it again to see them",
+        ));
+    }
+
+    let mut tran = config().pool().begin().await?;
+    for row in preview.rows {
+        let (Some(date), Some(start_time), Some(end_time)) =
+            (row.date, row.start_time, row.end_time)
+        else {
+            return Err(cafe_website::error::ISE);
+        };
+        let shift = sqlx::query_as!(
+            Shift,
+            "INSERT INTO shift (event_id, date, start_time, end_time, title, description, public_signup, capacity)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8) RETURNING *",
+            event_id,
+            date,
+            start_time,
+            end_time,
+            row.title,
+            row.description,
+            row.public_signup,
+            row.capacity,
+        )
+        .fetch_one(&mut *tran)
+        .await?;
+        if let Some(worker) = row.worker {
+            if assignment::overlaps(&shift, worker.id, &mut *tran).await? {
+                return Err(AppError::inline(
+                    StatusCode::BAD_REQUEST,
+                    "A worker in the file is already working an overlapping shift",
+                ));
+            }
+            assignment::assign(shift.id, worker.id, &mut tran).await?;
+        }
+    }
+    tran.commit().await?;
+
+    Ok(Redirect::to(format!("/event/{event_id}")))
+}
@@ -5,23 +5,12 @@ use cafe_website::{
     templates::Card,
     AppError, PaginatedQuery,
 };
-use chrono::NaiveDate;
-use sqlx::{FromRow, Postgres};
-use uuid::Uuid;
+use sqlx::Postgres;
 
 use crate::{config, models::Event};
 
 use super::pagination::EventOrderBy;
 
-#[derive(FromRow)]
-pub struct EventWithDates {
-    pub id: Uuid,
-    pub name: String,
-    pub start_date: Option<NaiveDate>,
-    pub end_date: Option<NaiveDate>,
-    pub allow_signups: bool,
-}
-
 #[derive(Template)]
 #[template(path = "events/list.html")]
 pub struct EventListTemplate {
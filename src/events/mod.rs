@@ -1,14 +1,18 @@
 use axum::{
     extract::Query,
+    middleware::from_extractor,
     response::Html,
-    routing::{get, patch},
+    routing::{get, patch, post},
     Router,
 };
 
+mod calendar;
 mod crud;
+mod import;
 mod list;
 mod list_row;
 mod pagination;
+mod rules;
 mod view;
 
 use cafe_website::AppError;
@@ -18,7 +22,12 @@ use list_row::*;
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
-use crate::{config, models::Event, schedule};
+use crate::{
+    accounts::{EventEditor, Scheduler},
+    config,
+    models::Event,
+    schedule,
+};
 
 use self::view::view;
 
@@ -55,20 +64,61 @@ pub async fn event_option_list(
 
 pub fn protected_router() -> Router {
     Router::new()
-        .route("/:id", patch(patch_event).delete(delete_event))
-        .route("/:id/send_reminders", get(send_reminders))
+        .route(
+            "/:id",
+            patch(patch_event)
+                .delete(delete_event)
+                .route_layer(from_extractor::<EventEditor>()),
+        )
+        .route(
+            "/:id/send_reminders",
+            get(send_reminders).route_layer(from_extractor::<EventEditor>()),
+        )
         .route("/:id/print_reminders", get(print_reminders))
-        .route("/create", get(create_event_form).post(create_event))
+        .route(
+            "/:id/feed",
+            get(calendar::feed_form)
+                .post(calendar::new_feed)
+                .delete(calendar::revoke_feed)
+                .route_layer(from_extractor::<EventEditor>()),
+        )
+        .route(
+            "/:id/import",
+            get(import::import_form)
+                .post(import::preview)
+                .route_layer(from_extractor::<EventEditor>()),
+        )
+        .route(
+            "/:id/import/confirm",
+            post(import::import).route_layer(from_extractor::<EventEditor>()),
+        )
+        .route(
+            "/:id/rules",
+            get(rules::rules_form)
+                .put(rules::save_rules)
+                .route_layer(from_extractor::<EventEditor>()),
+        )
+        .route(
+            "/create",
+            get(create_event_form)
+                .post(create_event)
+                .route_layer(from_extractor::<Scheduler>()),
+        )
         .route("/option_list", get(event_option_list))
         .route("/day/option_list", get(schedule::option_list))
         .route("/list", get(event_list))
         .route("/list/row/:id", get(event_table_row))
-        .route("/list/row/:id/edit", get(edit_event_table_row))
+        .route(
+            "/list/row/:id/edit",
+            get(edit_event_table_row).route_layer(from_extractor::<EventEditor>()),
+        )
         .nest("/:id", schedule::protected_router())
 }
 
 pub fn public_router() -> Router {
     Router::new()
         .route("/:id", get(view))
+        // Checks the token itself, since calendar apps can't log in
+        .route("/:id/schedule.ics", get(calendar::schedule_feed))
         .nest("/:id", schedule::public_router())
 }
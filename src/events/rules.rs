@@ -0,0 +1,95 @@
+use askama::Template;
+use axum::{extract::Path, http::StatusCode, response::Html, Form};
+use cafe_website::{templates::Card, AppError};
+use serde::Deserialize;
+use uuid::Uuid;
+
+use crate::{config, models::Event};
+
+#[derive(Template)]
+#[template(path = "events/rules.html")]
+pub struct EventRulesTemplate {
+    event: Event,
+}
+
+impl EventRulesTemplate {
+    /// The daily limit in hours, as shown in the form
+    fn max_daily_hours(&self) -> String {
+        self.event
+            .max_daily_minutes
+            .map(|m| (m as f64 / 60.0).to_string())
+            .unwrap_or_default()
+    }
+}
+
+pub async fn rules_form(Path(id): Path<Uuid>) -> Result<Card<EventRulesTemplate>, AppError> {
+    let event = sqlx::query_as!(Event, "SELECT * FROM event WHERE id = $1", id)
+        .fetch_one(config().pool())
+        .await?;
+    Ok(Card::modal(
+        "Shift Rules".to_owned(),
+        EventRulesTemplate { event },
+    ))
+}
+
+#[derive(Deserialize)]
+pub struct RulesForm {
+    max_daily_hours: String,
+    max_shifts: String,
+    min_break_minutes: String,
+}
+
+/// An optional positive number from the form, where blank means no limit
+fn limit<T: std::str::FromStr + PartialOrd + Default>(
+    value: &str,
+    message: &'static str,
+) -> Result<Option<T>, AppError> {
+    match value.trim() {
+        "" => Ok(None),
+        v => v
+            .parse()
+            .ok()
+            .filter(|n| *n > T::default())
+            .map(Some)
+            .ok_or(AppError::inline(StatusCode::BAD_REQUEST, message)),
+    }
+}
+
+pub async fn save_rules(
+    Path(id): Path<Uuid>,
+    Form(form): Form<RulesForm>,
+) -> Result<Html<&'static str>, AppError> {
+    let max_daily_hours: Option<f64> =
+        limit(&form.max_daily_hours, "Hours per day has to be more than 0")?;
+    let max_shifts: Option<i32> =
+        limit(&form.max_shifts, "Shifts has to be a whole number above 0")?;
+    let min_break_minutes: Option<i32> = limit(
+        &form.min_break_minutes,
+        "The break has to be a whole number of minutes",
+    )?;
+    let max_daily_minutes = max_daily_hours.map(|h| (h * 60.0).round() as i32);
+    if max_daily_minutes.is_some_and(|m| m < 1) {
+        return Err(AppError::inline(
+            StatusCode::BAD_REQUEST,
+            "Hours per day has to be at least a minute",
+        ));
+    }
+    if max_daily_minutes.is_some_and(|m| m > 24 * 60) {
+        return Err(AppError::inline(
+            StatusCode::BAD_REQUEST,
+            "There are only 24 hours in a day",
+        ));
+    }
+
+    sqlx::query!(
+        "UPDATE event SET max_daily_minutes = $2, max_shifts = $3, min_break_minutes = $4
+        WHERE id = $1",
+        id,
+        max_daily_minutes,
+        max_shifts,
+        min_break_minutes
+    )
+    .execute(config().pool())
+    .await?;
+    Ok(Html("Saved"))
+}
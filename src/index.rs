@@ -5,11 +5,13 @@ use axum::response::Html;
 #[template(path = "index.html")]
 struct Index {
     content: String,
+    csrf_token: String,
 }
 
-pub async fn index() -> Html<String> {
+pub async fn index(csrf_token: String) -> Html<String> {
     let body = Index {
         content: "Content".to_owned(),
+        csrf_token,
     }
     .render()
     .expect("Valid template");
@@ -0,0 +1,205 @@
+use std::{borrow::Cow, io};
+
+use axum::{
+    body::Body,
+    http::header,
+    response::{IntoResponse, Response},
+};
+use futures_util::{stream, StreamExt};
+use rust_xlsxwriter::{Format, Workbook};
+use serde::Deserialize;
+use sqlx::{postgres::PgRow, FromRow, Pool, Postgres, QueryBuilder};
+use tokio::sync::mpsc;
+
+use crate::{error::ISE, AppError};
+
+/// A row that can be written out as a line of a spreadsheet
+pub trait ExportRow {
+    const HEADERS: &'static [&'static str];
+
+    fn record(&self) -> Vec<String>;
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Deserialize, Default, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    #[default]
+    Csv,
+    Xlsx,
+}
+
+#[derive(Deserialize, Default, Debug)]
+pub struct ExportQuery {
+    #[serde(default)]
+    pub format: ExportFormat,
+}
+
+/// Runs the query and responds with the rows as a file download. CSVs are
+/// streamed as rows come back from the database. XLSX files are zipped, so
+/// they have to be built in full first.
+pub async fn export<T>(
+    name: &str,
+    format: ExportFormat,
+    query: QueryBuilder<'static, Postgres>,
+    pool: Pool<Postgres>,
+) -> Result<Response, AppError>
+where
+    T: ExportRow + for<'r> FromRow<'r, PgRow> + Send + Unpin + 'static,
+{
+    match format {
+        ExportFormat::Csv => Ok((
+            [
+                (header::CONTENT_TYPE, content_type(format).to_owned()),
+                (
+                    header::CONTENT_DISPOSITION,
+                    format!("attachment; filename=\"{name}.csv\""),
+                ),
+            ],
+            stream_csv::<T>(query, pool),
+        )
+            .into_response()),
+        ExportFormat::Xlsx => Ok((
+            [
+                (header::CONTENT_TYPE, content_type(format).to_owned()),
+                (
+                    header::CONTENT_DISPOSITION,
+                    format!("attachment; filename=\"{name}.xlsx\""),
+                ),
+            ],
+            build_xlsx::<T>(query, pool).await?,
+        )
+            .into_response()),
+    }
+}
+
+/// Responds with rows that were already loaded as a file download
+pub fn export_rows<T: ExportRow>(
+    name: &str,
+    format: ExportFormat,
+    rows: Vec<T>,
+) -> Result<Response, AppError> {
+    let body = match format {
+        ExportFormat::Csv => std::iter::once(csv_line(T::HEADERS))
+            .chain(rows.iter().map(|row| csv_line(&row.record())))
+            .collect::<Result<Vec<Vec<u8>>, io::Error>>()
+            .map_err(|e| {
+                tracing::error!("Export failed: {e}");
+                ISE
+            })?
+            .concat(),
+        ExportFormat::Xlsx => write_xlsx(&rows)?,
+    };
+    Ok((
+        [
+            (header::CONTENT_TYPE, content_type(format).to_owned()),
+            (
+                header::CONTENT_DISPOSITION,
+                format!("attachment; filename=\"{name}.{}\"", extension(format)),
+            ),
+        ],
+        body,
+    )
+        .into_response())
+}
+
+fn content_type(format: ExportFormat) -> &'static str {
+    match format {
+        ExportFormat::Csv => "text/csv; charset=utf-8",
+        ExportFormat::Xlsx => "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet",
+    }
+}
+
+fn extension(format: ExportFormat) -> &'static str {
+    match format {
+        ExportFormat::Csv => "csv",
+        ExportFormat::Xlsx => "xlsx",
+    }
+}
+
+/// Fields can hold anything the public typed in, and spreadsheet apps run
+/// cells starting with these as formulas
+const FORMULA_PREFIXES: &[char] = &['=', '+', '-', '@', '\t', '\r'];
+
+/// The field with a leading `'` if a spreadsheet app would treat it as a
+/// formula
+fn escape_field(field: &str) -> Cow<'_, str> {
+    if field.starts_with(FORMULA_PREFIXES) {
+        Cow::Owned(format!("'{field}"))
+    } else {
+        Cow::Borrowed(field)
+    }
+}
+
+fn csv_line<S: AsRef<str>>(fields: &[S]) -> Result<Vec<u8>, io::Error> {
+    let mut writer = csv::Writer::from_writer(vec![]);
+    writer.write_record(fields.iter().map(|f| escape_field(f.as_ref()).into_owned()))?;
+    writer.into_inner().map_err(|e| e.into_error())
+}
+
+fn stream_csv<T>(mut query: QueryBuilder<'static, Postgres>, pool: Pool<Postgres>) -> Body
+where
+    T: ExportRow + for<'r> FromRow<'r, PgRow> + Send + Unpin + 'static,
+{
+    let (tx, rx) = mpsc::channel::<Result<Vec<u8>, io::Error>>(16);
+    tokio::spawn(async move {
+        if tx.send(csv_line(T::HEADERS)).await.is_err() {
+            return;
+        }
+        let mut rows = query.build_query_as::<T>().fetch(&pool);
+        while let Some(row) = rows.next().await {
+            let line = match row {
+                Ok(row) => csv_line(&row.record()),
+                Err(e) => {
+                    tracing::error!("Export failed: {e}");
+                    Err(io::Error::other(e))
+                }
+            };
+            let failed = line.is_err();
+            // Stop if the download was canceled
+            if tx.send(line).await.is_err() || failed {
+                return;
+            }
+        }
+    });
+    Body::from_stream(stream::unfold(rx, |mut rx| async move {
+        rx.recv().await.map(|line| (line, rx))
+    }))
+}
+
+async fn build_xlsx<T>(
+    mut query: QueryBuilder<'static, Postgres>,
+    pool: Pool<Postgres>,
+) -> Result<Vec<u8>, AppError>
+where
+    T: ExportRow + for<'r> FromRow<'r, PgRow> + Send + Unpin + 'static,
+{
+    let rows = query.build_query_as::<T>().fetch_all(&pool).await?;
+    write_xlsx(&rows)
+}
+
+fn write_xlsx<T: ExportRow>(rows: &[T]) -> Result<Vec<u8>, AppError> {
+    let mut workbook = Workbook::new();
+    let sheet = workbook.add_worksheet();
+    let bold = Format::new().set_bold();
+    let written = sheet
+        .write_row_with_format(0, 0, T::HEADERS.iter().copied(), &bold)
+        .and_then(|sheet| {
+            // Always strings, so nothing in a row can become a formula
+            rows.iter().enumerate().try_for_each(|(i, row)| {
+                row.record().iter().enumerate().try_for_each(|(j, field)| {
+                    sheet
+                        .write_string(i as u32 + 1, j as u16, field)
+                        .map(|_| ())
+                })
+            })
+        });
+    if let Err(e) = written {
+        tracing::error!("Couldn't write spreadsheet: {e}");
+        return Err(ISE);
+    }
+    sheet.autofit();
+    workbook.save_to_buffer().map_err(|e| {
+        tracing::error!("Couldn't save spreadsheet: {e}");
+        ISE
+    })
+}
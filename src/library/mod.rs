@@ -12,3 +12,5 @@ pub mod templates;
 pub mod filters;
 
 pub mod print;
+
+pub mod export;
@@ -19,6 +19,7 @@ pub use config::config;
 use tracing::{debug, info_span};
 
 mod accounts;
+mod calendar;
 mod config;
 mod email;
 mod events;
@@ -26,6 +27,7 @@ mod home;
 mod index;
 pub mod models;
 mod navigation;
+mod notify;
 mod otel;
 mod remind;
 mod schedule;
@@ -60,6 +62,44 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         }
     });
 
+    // Scheduled reminders
+    tokio::spawn(async move {
+        let Some(reminders) = config::config().reminders.as_ref() else {
+            return;
+        };
+        loop {
+            if let Err(e) = remind::send_due_reminders(reminders.lead_time()).await {
+                tracing::error!("Reminder error: {}", e);
+            }
+            tokio::time::sleep(Duration::from_secs(reminders.check_minutes * 60)).await;
+        }
+    });
+
+    // Signup and cancellation digests
+    tokio::spawn(async move {
+        let Some(notifications) = config::config().notifications.as_ref() else {
+            return;
+        };
+        loop {
+            if let Err(e) = notify::send_digest(notifications).await {
+                tracing::error!("Digest error: {}", e);
+            }
+            tokio::time::sleep(Duration::from_secs(notifications.check_minutes * 60)).await;
+        }
+    });
+
+    // Clearing out old sessions
+    tokio::spawn(async move {
+        let minutes = config::config().sessions.sweep_minutes;
+        loop {
+            match session::sweep().await {
+                Ok(swept) => debug!("Deleted {} old sessions", swept),
+                Err(e) => tracing::error!("Session sweep error: {}", e),
+            }
+            tokio::time::sleep(Duration::from_secs(minutes * 60)).await;
+        }
+    });
+
     // Routes
     let auth_routes = Router::new()
         .nest("/event", events::protected_router())
@@ -73,6 +113,10 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         .route("/", get(home::view))
         .route("/nav", get(navigation::navigation))
         .route("/login", get(accounts::login_form).post(accounts::login))
+        .route(
+            "/login/verify",
+            get(accounts::verify_form).post(accounts::verify),
+        )
         .route("/logout", get(accounts::logout))
         .route("/style.css", get(style::style))
         .nest("/event", events::public_router())
@@ -85,6 +129,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let mid = ServiceBuilder::new()
         .layer(middleware::from_fn(html_wrapper))
         .layer(middleware::from_fn(session::session_provider))
+        .layer(middleware::from_fn(session::csrf_layer))
         .layer(
             TraceLayer::new_for_http().make_span_with(|request: &Request<_>| {
                 // debug!(?request);
@@ -127,12 +172,12 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     match config().tls_config().cloned() {
         Some(cfg) => {
             axum_server::bind_rustls(addr, cfg)
-                .serve(app.into_make_service())
+                .serve(app.into_make_service_with_connect_info::<SocketAddr>())
                 .await
                 .unwrap();
         }
         None => axum_server::bind(addr)
-            .serve(app.into_make_service())
+            .serve(app.into_make_service_with_connect_info::<SocketAddr>())
             .await
             .unwrap(),
     };
@@ -145,11 +190,18 @@ async fn auth_layer(
     next: Next,
 ) -> Result<Response<Body>, AppError> {
     if !session.is_authenticated() {
+        // Users who still need to enter a code go back to finish logging in
+        let page = if session.pending_user_id().is_some() {
+            "/login/verify"
+        } else {
+            "/login"
+        };
         return Err(AppError::redirect(
             StatusCode::UNAUTHORIZED,
             "Restricted",
             format!(
-                "/login?from={}",
+                "{}?from={}",
+                page,
                 request.uri().path_and_query().map_or("", |p| p.as_str())
             ),
         ));
@@ -166,7 +218,14 @@ async fn html_wrapper(request: Request<Body>, next: Next) -> impl IntoResponse {
     let from_htmx = request.headers().contains_key("HX-Request");
     let response = next.run(request).await;
 
-    let Html(wrapper) = index::index().await;
+    // The token for pages to send back, set by the csrf layer
+    let csrf_token = response
+        .headers()
+        .get(session::CSRF_HEADER)
+        .and_then(|t| t.to_str().ok())
+        .unwrap_or_default()
+        .to_owned();
+    let Html(wrapper) = index::index(csrf_token).await;
 
     let (mut parts, body) = response.into_parts();
     parts.headers.remove("content-length");
@@ -1,13 +1,163 @@
+use std::fmt;
+
 use chrono::{DateTime, NaiveDate, NaiveTime, Utc};
 use serde::{Deserialize, Serialize};
 use sqlx::{types::Uuid, FromRow};
 
+/// What an admin user is allowed to do. Coordinators can only edit the
+/// events they've been given in `event_coordinator`.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, Default, sqlx::Type, Debug)]
+#[serde(rename_all = "snake_case")]
+#[sqlx(type_name = "user_role", rename_all = "lowercase")]
+pub enum Role {
+    Owner,
+    Scheduler,
+    #[default]
+    Viewer,
+    Coordinator,
+}
+
+impl Role {
+    pub const ALL: [Role; 4] = [
+        Role::Owner,
+        Role::Scheduler,
+        Role::Viewer,
+        Role::Coordinator,
+    ];
+
+    /// Can change the schedule of every event
+    pub fn can_schedule(&self) -> bool {
+        matches!(self, Role::Owner | Role::Scheduler)
+    }
+}
+
+impl fmt::Display for Role {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            Self::Owner => "owner",
+            Self::Scheduler => "scheduler",
+            Self::Viewer => "viewer",
+            Self::Coordinator => "coordinator",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+/// Training or certification some shifts need
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, sqlx::Type, Debug)]
+#[serde(rename_all = "snake_case")]
+#[sqlx(type_name = "skill", rename_all = "snake_case")]
+pub enum Skill {
+    FoodHandler,
+    Cashier,
+    Grill,
+}
+
+impl Skill {
+    pub const ALL: [Skill; 3] = [Skill::FoodHandler, Skill::Cashier, Skill::Grill];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Self::FoodHandler => "Food handler card",
+            Self::Cashier => "Cashier",
+            Self::Grill => "Grill",
+        }
+    }
+}
+
+impl fmt::Display for Skill {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            Self::FoodHandler => "food_handler",
+            Self::Cashier => "cashier",
+            Self::Grill => "grill",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, sqlx::Type, Debug)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(type_name = "shirt_size", rename_all = "lowercase")]
+pub enum ShirtSize {
+    Xs,
+    S,
+    M,
+    L,
+    Xl,
+    Xxl,
+}
+
+impl ShirtSize {
+    pub const ALL: [ShirtSize; 6] = [
+        ShirtSize::Xs,
+        ShirtSize::S,
+        ShirtSize::M,
+        ShirtSize::L,
+        ShirtSize::Xl,
+        ShirtSize::Xxl,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Self::Xs => "XS",
+            Self::S => "S",
+            Self::M => "M",
+            Self::L => "L",
+            Self::Xl => "XL",
+            Self::Xxl => "XXL",
+        }
+    }
+}
+
+impl fmt::Display for ShirtSize {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.label().to_lowercase())
+    }
+}
+
+/// Kept for minors, who can't work some positions
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, sqlx::Type, Debug)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(type_name = "age_bracket", rename_all = "lowercase")]
+pub enum AgeBracket {
+    Under16,
+    Teen,
+    Adult,
+}
+
+impl AgeBracket {
+    pub const ALL: [AgeBracket; 3] = [AgeBracket::Under16, AgeBracket::Teen, AgeBracket::Adult];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Self::Under16 => "Under 16",
+            Self::Teen => "16-17",
+            Self::Adult => "18+",
+        }
+    }
+}
+
+impl fmt::Display for AgeBracket {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            Self::Under16 => "under16",
+            Self::Teen => "teen",
+            Self::Adult => "adult",
+        };
+        write!(f, "{}", s)
+    }
+}
+
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct User {
     pub id: Uuid,
     pub email: String,
     pub password: String,
     pub name: String,
+    pub role: Role,
+    /// Base32 secret for two factor codes, if the user has turned it on
+    pub totp_secret: Option<String>,
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
@@ -16,6 +166,8 @@ pub struct AdminInvite {
     pub created_at: DateTime<Utc>,
     pub accepted_at: Option<DateTime<Utc>>,
     pub email: String,
+    pub role: Role,
+    pub event_id: Option<Uuid>,
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
@@ -29,6 +181,12 @@ pub struct Event {
     pub id: Uuid,
     pub name: String,
     pub allow_signups: bool,
+    /// Most a worker can work in a day, if limited
+    pub max_daily_minutes: Option<i32>,
+    /// Most shifts a worker can take in the event, if limited
+    pub max_shifts: Option<i32>,
+    /// Least time a worker needs between shifts, if any
+    pub min_break_minutes: Option<i32>,
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
@@ -43,10 +201,12 @@ pub struct Shift {
     pub id: Uuid,
     pub event_id: Uuid,
     pub date: NaiveDate,
-    pub worker_id: Option<Uuid>,
     pub start_time: NaiveTime,
     pub end_time: NaiveTime,
     pub title: String,
     pub description: Option<String>,
     pub public_signup: bool,
+    pub capacity: i32,
+    /// Only workers 18 or older can take it
+    pub adults_only: bool,
 }
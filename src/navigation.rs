@@ -1,6 +1,6 @@
 use askama::Template;
 
-use crate::session::Session;
+use crate::{accounts::current_role, models::Role, session::Session};
 
 #[derive(Template)]
 #[template(path = "navigation.html")]
@@ -10,18 +10,29 @@ pub struct Nav {
 }
 
 pub async fn navigation(session: Session) -> Nav {
-    let (left, right) = if !session.is_authenticated() {
-        (vec![], vec![("Log In", "/login")])
-    } else {
-        (
-            vec![
-                ("Events", "/event/list"),
-                ("Workers", "/worker/list"),
-                ("Emails", "/email/list"),
-                ("Users", "/account/manage"),
-            ],
-            vec![("Log Out", "/logout")],
-        )
+    // Only link to the pages the user's role can use
+    let (left, right) = match current_role(&session).await.ok().flatten() {
+        None => (vec![], vec![("Log In", "/login")]),
+        Some(role) => {
+            let mut left = vec![("Events", "/event/list"), ("Workers", "/worker/list")];
+            if role.can_schedule() {
+                left.push(("Emails", "/email/list"));
+            } else if role == Role::Coordinator {
+                left.push(("Broadcasts", "/email/broadcasts"));
+            }
+            if role == Role::Owner {
+                left.push(("Users", "/account/manage"));
+            }
+            (
+                left,
+                vec![
+                    ("Password", "/account/password"),
+                    ("Two Factor", "/account/two_factor"),
+                    ("Sessions", "/account/sessions"),
+                    ("Log Out", "/logout"),
+                ],
+            )
+        }
     };
     Nav { left, right }
 }
@@ -0,0 +1,227 @@
+use std::collections::BTreeMap;
+
+use askama::Template;
+use cafe_website::{filters, AppError};
+use chrono::{DateTime, Utc};
+use chrono_tz::Tz;
+use sqlx::PgConnection;
+use tracing::info;
+use uuid::Uuid;
+
+use crate::{
+    config,
+    config::{DigestMode, Notifications},
+    models::Shift,
+};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, sqlx::Type)]
+#[sqlx(type_name = "notification_kind", rename_all = "lowercase")]
+pub enum NotificationKind {
+    Signup,
+    Cancellation,
+}
+
+/// Records a signup or cancellation for the next digest. Does nothing when
+/// digests aren't set up.
+pub async fn record(
+    kind: NotificationKind,
+    shift_id: Uuid,
+    worker_id: Uuid,
+    reason: Option<&str>,
+    conn: &mut PgConnection,
+) -> Result<(), sqlx::Error> {
+    if config().notifications.is_none() {
+        return Ok(());
+    }
+    sqlx::query!(
+        "INSERT INTO notification (kind, shift_id, worker_id, reason) VALUES ($1, $2, $3, $4)",
+        kind as NotificationKind,
+        shift_id,
+        worker_id,
+        reason
+    )
+    .execute(conn)
+    .await?;
+    Ok(())
+}
+
+pub struct Activity {
+    at: DateTime<Tz>,
+    worker: String,
+    shift: Shift,
+    reason: Option<String>,
+}
+
+pub struct Unfilled {
+    shift: Shift,
+    filled: i64,
+}
+
+#[derive(Default)]
+pub struct EventDigest {
+    name: String,
+    signups: Vec<Activity>,
+    cancellations: Vec<Activity>,
+    unfilled: Vec<Unfilled>,
+}
+
+#[derive(Template)]
+#[template(path = "email/messages/digest.html")]
+struct DigestMessage {
+    events: Vec<EventDigest>,
+}
+
+/// Whether it's time for the next digest. Daily digests go out at the first
+/// check after the configured time, once a day.
+async fn digest_due(notifications: &Notifications) -> Result<bool, sqlx::Error> {
+    if notifications.mode == DigestMode::Immediate {
+        return Ok(true);
+    }
+    let tz = config().timezone();
+    let now = Utc::now().with_timezone(&tz);
+    let Some(due) = now
+        .date_naive()
+        .and_time(notifications.send_at)
+        .and_local_timezone(tz)
+        .earliest()
+    else {
+        return Ok(false);
+    };
+    if now < due {
+        return Ok(false);
+    }
+    let last = sqlx::query_scalar!("SELECT max(sent_at) FROM notification_digest")
+        .fetch_one(config().pool())
+        .await?;
+    Ok(last.is_none_or(|last| last < due))
+}
+
+/// Emails the admin a summary of the signups and cancellations since the last
+/// digest, along with the shifts those events still need filled
+pub async fn send_digest(notifications: &Notifications) -> Result<(), AppError> {
+    if !digest_due(notifications).await? {
+        return Ok(());
+    }
+    let tz = config().timezone();
+    let mut tran = config().pool().begin().await?;
+    let rows = sqlx::query!(
+        r#"SELECT n.id, n.created_at, n.kind as "kind: NotificationKind", n.reason,
+            w.name_first, w.name_last, e.name as event_name,
+            s.id as shift_id, s.event_id, s.date, s.start_time, s.end_time, s.title,
+            s.description, s.public_signup, s.capacity, s.adults_only
+        FROM notification as n
+        INNER JOIN worker as w ON w.id = n.worker_id
+        INNER JOIN shift as s ON s.id = n.shift_id
+        INNER JOIN event as e ON e.id = s.event_id
+        WHERE n.sent_at IS NULL
+        ORDER BY n.created_at
+        FOR UPDATE OF n"#
+    )
+    .fetch_all(&mut *tran)
+    .await?;
+    if rows.is_empty() && notifications.mode == DigestMode::Immediate {
+        return Ok(());
+    }
+
+    let ids: Vec<Uuid> = rows.iter().map(|row| row.id).collect();
+    let mut events: BTreeMap<(String, Uuid), EventDigest> = BTreeMap::new();
+    for row in rows {
+        let digest = events
+            .entry((row.event_name.clone(), row.event_id))
+            .or_default();
+        digest.name = row.event_name;
+        let activity = Activity {
+            at: row.created_at.with_timezone(&tz),
+            worker: format!("{} {}", row.name_first, row.name_last),
+            shift: Shift {
+                id: row.shift_id,
+                event_id: row.event_id,
+                date: row.date,
+                start_time: row.start_time,
+                end_time: row.end_time,
+                title: row.title,
+                description: row.description,
+                public_signup: row.public_signup,
+                capacity: row.capacity,
+                adults_only: row.adults_only,
+            },
+            reason: row.reason,
+        };
+        match row.kind {
+            NotificationKind::Signup => digest.signups.push(activity),
+            NotificationKind::Cancellation => digest.cancellations.push(activity),
+        }
+    }
+
+    if !events.is_empty() {
+        let event_ids: Vec<Uuid> = events.keys().map(|(_, id)| *id).collect();
+        let today = Utc::now().with_timezone(&tz).date_naive();
+        let unfilled = sqlx::query!(
+            r#"SELECT s.*, count(sa.worker_id) as "filled!" FROM shift as s
+            LEFT JOIN shift_assignment as sa ON sa.shift_id = s.id
+            WHERE s.event_id = ANY($1) AND s.date >= $2
+            GROUP BY s.id
+            HAVING count(sa.worker_id) < s.capacity
+            ORDER BY s.date, s.start_time, s.title"#,
+            &event_ids,
+            today
+        )
+        .fetch_all(&mut *tran)
+        .await?;
+        for row in unfilled {
+            let Some(digest) = events
+                .iter_mut()
+                .find(|((_, id), _)| *id == row.event_id)
+                .map(|(_, digest)| digest)
+            else {
+                continue;
+            };
+            digest.unfilled.push(Unfilled {
+                filled: row.filled,
+                shift: Shift {
+                    id: row.id,
+                    event_id: row.event_id,
+                    date: row.date,
+                    start_time: row.start_time,
+                    end_time: row.end_time,
+                    title: row.title,
+                    description: row.description,
+                    public_signup: row.public_signup,
+                    capacity: row.capacity,
+                    adults_only: row.adults_only,
+                },
+            });
+        }
+
+        let (signups, cancellations) = events.values().fold((0, 0), |(s, c), e| {
+            (s + e.signups.len(), c + e.cancellations.len())
+        });
+        let message = DigestMessage {
+            events: events.into_values().collect(),
+        }
+        .render()?;
+        sqlx::query!(
+            "INSERT INTO email (status, kind, address, subject, message)
+            VALUES ('pending', 'html', $1, $2, $3)",
+            config().admin.email,
+            format!("Volunteer update: {signups} signups, {cancellations} cancellations"),
+            message,
+        )
+        .execute(&mut *tran)
+        .await?;
+        sqlx::query!(
+            "UPDATE notification SET sent_at = now() WHERE id = ANY($1)",
+            &ids
+        )
+        .execute(&mut *tran)
+        .await?;
+        info!("Queued a digest of {} notifications", ids.len());
+    }
+
+    // Daily digests are only sent once a day, even when there's nothing in them
+    sqlx::query!("INSERT INTO notification_digest DEFAULT VALUES")
+        .execute(&mut *tran)
+        .await?;
+    tran.commit().await?;
+    Ok(())
+}
@@ -1,10 +1,18 @@
 use askama::Template;
 use cafe_website::{filters, AppError};
-use sqlx::QueryBuilder;
-use tracing::debug;
+use chrono::{NaiveDate, Utc};
+use sqlx::PgConnection;
+use tracing::{debug, info};
 use uuid::Uuid;
 
-use crate::{config, config::Admin, models::Shift, worker::Worker};
+use crate::{
+    config,
+    config::Admin,
+    email::{find_template, TemplateKind, Variables},
+    models::Shift,
+    shift::assignment,
+    worker::{self, Worker},
+};
 
 #[derive(Debug, Clone, Template)]
 #[template(path = "email/messages/reminder.html")]
@@ -12,29 +20,43 @@ pub struct Reminder {
     worker: Worker,
     shifts: Vec<Shift>,
     admin: &'static Admin,
-    domain: String,
+    link: String,
     locked: bool,
 }
 
+/// Used when the event hasn't written its own reminder
+const SUBJECT: &str = "Your shifts at the Cornerstone Cafe";
+
+/// The subject and message to send, from the event's own template if it has
+/// one
+async fn render_email(
+    reminder: &Reminder,
+    event_id: Uuid,
+    conn: &mut PgConnection,
+) -> Result<(String, String), AppError> {
+    let Some(custom) = find_template(event_id, TemplateKind::Reminder, &mut *conn).await? else {
+        return Ok((SUBJECT.to_owned(), reminder.render()?));
+    };
+    let variables = Variables::for_event(event_id, &mut *conn)
+        .await?
+        .worker(&reminder.worker)
+        .shifts(&reminder.shifts)
+        .set("link", reminder.link.clone());
+    Ok(custom.render(&variables)?)
+}
+
 pub async fn remind_one(
     event_id: Uuid,
     worker: Worker,
     locked: bool,
 ) -> Result<Reminder, AppError> {
-    let shifts = sqlx::query_as!(
-        Shift,
-        "SELECT * FROM shift WHERE event_id = $1 AND worker_id = $2 ORDER BY date, start_time",
-        event_id,
-        worker.id
-    )
-    .fetch_all(config().pool())
-    .await?;
+    let shifts = assignment::shifts(worker.id, Some(event_id), config().pool()).await?;
 
     Ok(Reminder {
+        link: format!("{}/worker/{}", config().url(), worker.id),
         worker,
         shifts,
         admin: &config().admin,
-        domain: config().url(),
         locked,
     })
 }
@@ -43,9 +65,9 @@ pub async fn remind_all(event_id: Uuid, locked: bool) -> Result<Vec<Reminder>, A
     let workers = sqlx::query_as!(
         Worker,
         "SELECT w.* FROM worker as w 
-        INNER JOIN shift as s ON s.worker_id = w.id 
-        INNER JOIN event as e ON s.event_id = e.id 
-        WHERE e.id = $1
+        INNER JOIN shift_assignment as sa ON sa.worker_id = w.id 
+        INNER JOIN shift as s ON s.id = sa.shift_id 
+        WHERE s.event_id = $1
         GROUP BY w.id",
         event_id
     )
@@ -54,21 +76,7 @@ pub async fn remind_all(event_id: Uuid, locked: bool) -> Result<Vec<Reminder>, A
     debug!("Creating reminders for {} workers", workers.len());
     let mut res = vec![];
     for worker in workers {
-        let shifts = sqlx::query_as!(
-            Shift,
-            "SELECT * FROM shift WHERE event_id = $1 AND worker_id = $2 ORDER BY date, start_time",
-            event_id,
-            worker.id
-        )
-        .fetch_all(config().pool())
-        .await?;
-        res.push(Reminder {
-            worker,
-            shifts,
-            admin: &config().admin,
-            domain: config().url(),
-            locked,
-        })
+        res.push(remind_one(event_id, worker, locked).await?);
     }
 
     Ok(res)
@@ -77,32 +85,99 @@ pub async fn remind_all(event_id: Uuid, locked: bool) -> Result<Vec<Reminder>, A
 pub async fn send_all_reminders(event_id: Uuid) -> Result<(), AppError> {
     // If reminders can be sent without the event being hidden to the public,
     // this will need to change
-    let reminders: Vec<(Reminder, String)> = remind_all(event_id, true)
-        .await?
-        .into_iter()
-        .map(|reminder| -> Result<_, AppError> { Ok((reminder.clone(), reminder.render()?)) })
-        .collect::<Result<Vec<_>, AppError>>()?;
+    let mut tran = config().pool().begin().await?;
+    for mut reminder in remind_all(event_id, true).await? {
+        let token = worker::issue_token(reminder.worker.id, &mut tran).await?;
+        reminder.link = worker::access_url(token);
+        let mut dates: Vec<NaiveDate> = reminder.shifts.iter().map(|s| s.date).collect();
+        dates.dedup();
+        queue(&reminder, event_id, &dates, &mut tran).await?;
+    }
+    tran.commit().await?;
+    Ok(())
+}
 
-    if reminders.is_empty() {
+/// Queues the reminder's email, and records the days it covers so the
+/// scheduled reminders don't send them again
+async fn queue(
+    reminder: &Reminder,
+    event_id: Uuid,
+    dates: &[NaiveDate],
+    conn: &mut PgConnection,
+) -> Result<(), AppError> {
+    let (subject, message) = render_email(reminder, event_id, &mut *conn).await?;
+    let email_id = sqlx::query_scalar!(
+        "INSERT INTO email (status, kind, recipient, address, subject, message, event_id)
+        VALUES ('pending', 'html', $1, $2, $3, $4, $5)
+        RETURNING id",
+        reminder.worker.id,
+        reminder.worker.email,
+        subject,
+        message,
+        event_id
+    )
+    .fetch_one(&mut *conn)
+    .await?;
+    for date in dates {
+        sqlx::query!(
+            "INSERT INTO reminder (worker_id, event_id, date, email_id) VALUES ($1, $2, $3, $4)
+            ON CONFLICT DO NOTHING",
+            reminder.worker.id,
+            event_id,
+            date,
+            email_id
+        )
+        .execute(&mut *conn)
+        .await?;
+    }
+    Ok(())
+}
+
+/// Queues a reminder for each worker whose first shift of a day starts within
+/// the configured lead time. Each worker is reminded once per event day, so
+/// this can be run as often as needed.
+pub async fn send_due_reminders(lead_time: chrono::Duration) -> Result<(), AppError> {
+    let now = Utc::now().with_timezone(&config().timezone()).naive_local();
+    let mut tran = config().pool().begin().await?;
+    let due = sqlx::query!(
+        "SELECT sa.worker_id, s.event_id, s.date, e.allow_signups FROM shift_assignment as sa
+        INNER JOIN shift as s ON s.id = sa.shift_id
+        INNER JOIN event as e ON e.id = s.event_id
+        WHERE NOT EXISTS (
+            SELECT 1 FROM reminder as r
+            WHERE r.worker_id = sa.worker_id AND r.event_id = s.event_id AND r.date = s.date
+        )
+        GROUP BY sa.worker_id, s.event_id, s.date, e.allow_signups
+        HAVING MIN(s.date + s.start_time) > $1 AND MIN(s.date + s.start_time) <= $2",
+        now,
+        now + lead_time
+    )
+    .fetch_all(&mut *tran)
+    .await?;
+    if due.is_empty() {
         return Ok(());
     }
-    let mut email_query = QueryBuilder::new(
-        "INSERT INTO email (status, kind, recipient, address, subject, message, event_id) ",
-    );
-    email_query.push_values(reminders, |mut b, (reminder, body)| {
-        b.push("'pending'")
-            .push("'html'")
-            .push_bind(reminder.worker.id)
-            .push_bind(reminder.worker.email)
-            .push("'Your shifts at the Cornerstone Cafe'")
-            .push_bind(body)
-            .push_bind(event_id);
-    });
 
-    email_query
-        .build()
-        .persistent(false)
-        .execute(config().pool())
-        .await?;
+    for row in &due {
+        let worker = sqlx::query_as!(Worker, "SELECT * FROM worker WHERE id = $1", row.worker_id)
+            .fetch_one(&mut *tran)
+            .await?;
+        let shifts = assignment::shifts(worker.id, Some(row.event_id), &mut *tran)
+            .await?
+            .into_iter()
+            .filter(|s| s.date == row.date)
+            .collect();
+        let token = worker::issue_token(worker.id, &mut tran).await?;
+        let reminder = Reminder {
+            worker,
+            shifts,
+            admin: &config().admin,
+            link: worker::access_url(token),
+            locked: !row.allow_signups,
+        };
+        queue(&reminder, row.event_id, &[row.date], &mut tran).await?;
+    }
+    tran.commit().await?;
+    info!("Queued {} scheduled reminders", due.len());
     Ok(())
 }
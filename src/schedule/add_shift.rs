@@ -1,11 +1,12 @@
 use askama::Template;
-use axum::{extract::Path, Form};
+use axum::{extract::Path, http::StatusCode};
+use axum_extra::extract::Form;
 use cafe_website::{templates::Card, AppError, Redirect};
 use chrono::{NaiveDate, NaiveTime};
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
-use crate::config;
+use crate::{config, models::Skill, shift::requirement};
 
 #[derive(Template)]
 #[template(path = "schedule/add_shift.html")]
@@ -32,6 +33,10 @@ pub struct CreateShiftInput {
     end_time: NaiveTime,
     description: Option<String>,
     public_signup: Option<String>,
+    capacity: i32,
+    adults_only: Option<String>,
+    #[serde(default)]
+    required_skill: Vec<Skill>,
 }
 
 pub async fn add_shift(
@@ -44,18 +49,39 @@ pub async fn add_shift(
         end_time,
         description,
         public_signup,
+        capacity,
+        adults_only,
+        required_skill,
     } = shift_input;
 
-    sqlx::query!(
-        "INSERT INTO shift (date, event_id, title, start_time, end_time, description, public_signup) VALUES ($1, $2, $3, $4, $5, $6, $7) RETURNING *",
+    if end_time <= start_time {
+        return Err(AppError::inline(
+            StatusCode::BAD_REQUEST,
+            "A shift has to end after it starts",
+        ));
+    }
+    if capacity < 1 {
+        return Err(AppError::inline(
+            StatusCode::BAD_REQUEST,
+            "A shift needs at least one spot",
+        ));
+    }
+
+    let mut tran = config().pool().begin().await?;
+    let id = sqlx::query_scalar!(
+        "INSERT INTO shift (date, event_id, title, start_time, end_time, description, public_signup, capacity, adults_only) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9) RETURNING id",
         date,
         event_id,
         title,
         start_time,
         end_time,
         description,
-        public_signup.is_some_and(|s| s == "on")
-    ).fetch_one(config().pool()).await?;
+        public_signup.is_some_and(|s| s == "on"),
+        capacity,
+        adults_only.is_some_and(|s| s == "on")
+    ).fetch_one(&mut *tran).await?;
+    requirement::save_skills(id, &required_skill, &mut tran).await?;
+    tran.commit().await?;
 
     Ok(Redirect::to(format!("/event/{event_id}")))
 }
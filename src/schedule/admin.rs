@@ -6,11 +6,19 @@ use uuid::Uuid;
 
 use crate::config;
 use crate::models::Shift;
+use crate::shift::assignment;
 use crate::worker::Worker;
 
 struct ShiftWorker {
     shift: Shift,
-    worker: Option<Worker>,
+    workers: Vec<Worker>,
+}
+
+impl ShiftWorker {
+    /// Number of unfilled spots
+    fn open(&self) -> usize {
+        (self.shift.capacity as usize).saturating_sub(self.workers.len())
+    }
 }
 
 #[derive(Template)]
@@ -40,29 +48,18 @@ pub async fn schedule_admin(
     )
     .fetch_all(config().pool())
     .await?;
-    let w_handles: Vec<tokio::task::JoinHandle<Result<Option<Worker>, sqlx::Error>>> = shifts
+    let w_handles: Vec<tokio::task::JoinHandle<Result<Vec<Worker>, sqlx::Error>>> = shifts
         .iter()
         .map(|s| {
             let state = config().clone();
-            let w_id = s.worker_id;
-            tokio::spawn(async move {
-                match w_id {
-                    Some(id) => {
-                        let worker =
-                            sqlx::query_as!(Worker, "SELECT * FROM worker WHERE id = $1", id)
-                                .fetch_one(state.pool())
-                                .await?;
-                        Ok(Some(worker))
-                    }
-                    None => Ok(None),
-                }
-            })
+            let shift_id = s.id;
+            tokio::spawn(async move { assignment::workers(shift_id, state.pool()).await })
         })
         .collect();
     let mut all = Vec::new();
-    for (shift, handle) in shifts.into_iter().zip(w_handles.into_iter()) {
-        let worker = handle.await??;
-        all.push(ShiftWorker { shift, worker });
+    for (shift, handle) in shifts.into_iter().zip(w_handles) {
+        let workers = handle.await??;
+        all.push(ShiftWorker { shift, workers });
     }
     let mut current = Vec::new();
     let mut up_next = Vec::new();
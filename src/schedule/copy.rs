@@ -55,8 +55,8 @@ pub async fn copy(
     .execute(&mut *tran)
     .await?;
     for shift in shifts {
-        sqlx::query!(
-            "INSERT INTO shift (event_id, date, start_time, end_time, title, description, public_signup) VALUES ($1, $2, $3, $4, $5, $6, $7)",
+        let id = sqlx::query_scalar!(
+            "INSERT INTO shift (event_id, date, start_time, end_time, title, description, public_signup, capacity, adults_only) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9) RETURNING id",
             event_to,
             date_to,
             shift.start_time,
@@ -64,6 +64,13 @@ pub async fn copy(
             shift.title,
             shift.description,
             shift.public_signup,
+            shift.capacity,
+            shift.adults_only,
+        ).fetch_one(&mut *tran).await?;
+        sqlx::query!(
+            "INSERT INTO shift_requirement (shift_id, skill) SELECT $2, skill FROM shift_requirement WHERE shift_id = $1",
+            shift.id,
+            id
         ).execute(&mut *tran).await?;
     }
     tran.commit().await?;
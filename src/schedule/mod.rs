@@ -4,12 +4,12 @@ mod copy;
 mod view;
 
 pub use add_shift::*;
-use axum::{extract::Query, response::Html, routing::get, Router};
+use axum::{extract::Query, middleware::from_extractor, response::Html, routing::get, Router};
 use cafe_website::AppError;
 use serde::Deserialize;
 use uuid::Uuid;
 
-use crate::{config, models::Day};
+use crate::{accounts::EventEditor, config, models::Day};
 
 #[derive(Deserialize)]
 pub struct OptionListParams {
@@ -38,9 +38,20 @@ pub async fn option_list(Query(params): Query<OptionListParams>) -> Result<Html<
 
 pub fn protected_router() -> Router {
     Router::new()
-        .route("/:date/add_shift", get(add_shift_form).post(add_shift))
-        .route("/:date/copy", get(copy::copy_form).post(copy::copy))
+        .route(
+            "/:date/add_shift",
+            get(add_shift_form)
+                .post(add_shift)
+                .route_layer(from_extractor::<EventEditor>()),
+        )
+        .route(
+            "/:date/copy",
+            get(copy::copy_form)
+                .post(copy::copy)
+                .route_layer(from_extractor::<EventEditor>()),
+        )
         .route("/:date/admin", get(admin::schedule_admin))
+        .route("/:date/export", get(view::export))
 }
 
 pub fn public_router() -> Router {
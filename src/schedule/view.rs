@@ -1,11 +1,23 @@
 use askama::Template;
-use axum::extract::Path;
-use cafe_website::{filters, AppError};
+use axum::{
+    extract::{Path, Query},
+    response::Response,
+};
+use cafe_website::{
+    export::{self, ExportQuery, ExportRow},
+    filters, AppError,
+};
 use chrono::{Duration, NaiveDate, NaiveTime, Timelike};
-use sqlx::QueryBuilder;
+use sqlx::{Postgres, QueryBuilder};
 use uuid::Uuid;
 
-use crate::{config, session::Session};
+use crate::{
+    config,
+    models::Skill,
+    session::Session,
+    shift::requirement::{self, Requirements},
+    worker::token_worker,
+};
 
 #[derive(Template)]
 #[template(path = "schedule/view.html")]
@@ -27,9 +39,41 @@ pub struct ShiftWorker {
     start_time: NaiveTime,
     end_time: NaiveTime,
     title: String,
-    // Worker name
-    name_first: Option<String>,
-    name_last: Option<String>,
+    capacity: i32,
+    filled: i64,
+    // Worker names, only for admins
+    names: Option<Vec<String>>,
+    required: Vec<Skill>,
+    adults_only: bool,
+}
+
+impl ShiftWorker {
+    fn is_full(&self) -> bool {
+        self.filled >= self.capacity as i64
+    }
+
+    fn requirements(&self) -> Requirements {
+        Requirements {
+            skills: self.required.clone(),
+            adults_only: self.adults_only,
+        }
+    }
+}
+
+impl ExportRow for ShiftWorker {
+    const HEADERS: &'static [&'static str] =
+        &["Title", "Start", "End", "Spots", "Filled", "Workers"];
+
+    fn record(&self) -> Vec<String> {
+        vec![
+            self.title.clone(),
+            filters::time_short(&self.start_time).unwrap_or_default(),
+            filters::time_short(&self.end_time).unwrap_or_default(),
+            self.capacity.to_string(),
+            self.filled.to_string(),
+            self.names.as_deref().unwrap_or_default().join(", "),
+        ]
+    }
 }
 
 #[derive(Debug)]
@@ -46,30 +90,83 @@ pub struct ScheduleItemTemplate {
     end_time: NaiveTime,
 }
 
-pub async fn schedule(
-    session: Session,
-    Path((event_id, date)): Path<(Uuid, NaiveDate)>,
-) -> Result<ScheduleTemplate, AppError> {
-    let mut query = QueryBuilder::new(
-        "SELECT s.id, s.title, s.start_time, s.end_time, w.name_first, w.name_last 
-        FROM shift as s LEFT OUTER JOIN worker as w ON s.worker_id = w.id ",
-    );
+/// Shifts on a day with how full they are. Workers' names are only included
+/// for admins.
+fn shift_query(
+    event_id: Uuid,
+    date: NaiveDate,
+    authenticated: bool,
+) -> QueryBuilder<'static, Postgres> {
+    let mut query = if authenticated {
+        QueryBuilder::new(
+            "SELECT s.id, s.title, s.start_time, s.end_time, s.capacity, COUNT(sa.worker_id) as filled,
+                array_agg(w.name_first || ' ' || w.name_last ORDER BY sa.created_at)
+                    FILTER (WHERE w.id IS NOT NULL) as names,
+                ARRAY(SELECT skill FROM shift_requirement WHERE shift_id = s.id ORDER BY skill) as required,
+                s.adults_only
+            FROM shift as s
+            LEFT OUTER JOIN shift_assignment as sa ON sa.shift_id = s.id
+            LEFT OUTER JOIN worker as w ON sa.worker_id = w.id ",
+        )
+    } else {
+        // Filled shifts are still listed so people can join the waitlist, but
+        // without the worker's name
+        QueryBuilder::new(
+            "SELECT s.id, s.title, s.start_time, s.end_time, s.capacity, COUNT(sa.worker_id) as filled,
+                NULL::text[] as names,
+                ARRAY(SELECT skill FROM shift_requirement WHERE shift_id = s.id ORDER BY skill) as required,
+                s.adults_only
+            FROM shift as s
+            LEFT OUTER JOIN shift_assignment as sa ON sa.shift_id = s.id ",
+        )
+    };
     query
         .push("WHERE s.date = ")
         .push_bind(date)
         .push(" AND s.event_id = ")
         .push_bind(event_id);
 
-    if !session.is_authenticated() {
-        query.push(" AND s.public_signup = TRUE AND w IS NULL");
+    if !authenticated {
+        query.push(" AND s.public_signup = TRUE");
     }
 
-    query.push(" ORDER BY s.start_time, s.title ASC");
-    let shifts = query
+    query.push(" GROUP BY s.id ORDER BY s.start_time, s.title ASC");
+    query
+}
+
+pub async fn export(
+    Path((event_id, date)): Path<(Uuid, NaiveDate)>,
+    Query(ExportQuery { format }): Query<ExportQuery>,
+) -> Result<Response, AppError> {
+    export::export::<ShiftWorker>(
+        &format!("schedule-{date}"),
+        format,
+        shift_query(event_id, date, true),
+        config().pool().clone(),
+    )
+    .await
+}
+
+pub async fn schedule(
+    session: Session,
+    Path((event_id, date)): Path<(Uuid, NaiveDate)>,
+) -> Result<ScheduleTemplate, AppError> {
+    let mut query = shift_query(event_id, date, session.is_authenticated());
+    let mut shifts = query
         .build_query_as::<ShiftWorker>()
         .fetch_all(config().pool())
         .await?;
 
+    // Workers who opened the site from their link only see shifts they can take
+    let worker_id = match session.worker_token_id() {
+        Some(token_id) if !session.is_authenticated() => token_worker(token_id).await?,
+        _ => None,
+    };
+    if let Some(worker_id) = worker_id {
+        let qualifications = requirement::qualifications(worker_id, config().pool()).await?;
+        shifts.retain(|shift| !shift.requirements().check(&qualifications).is_blocked());
+    }
+
     let start_time = shifts
         .first()
         .map(|sh| sh.start_time - Duration::minutes(sh.start_time.minute().into()))
@@ -4,10 +4,13 @@ use crate::{config::config, models::User};
 use axum::{async_trait, extract::FromRequestParts, http::request::Parts, Extension};
 use cafe_website::AppError;
 use chrono::NaiveDateTime;
+use sqlx::PgConnection;
 use tracing::error;
 use uuid::Uuid;
 
+mod csrf;
 mod provider;
+pub use csrf::{csrf_layer, CSRF_HEADER};
 pub use provider::session_provider;
 
 /// Struct representing the current user session When used as an extractor, the
@@ -23,6 +26,11 @@ struct DbSession {
     created_at: NaiveDateTime,
     expires_at: Option<NaiveDateTime>,
     user_id: Option<Uuid>,
+    worker_token_id: Option<Uuid>,
+    pending_user_id: Option<Uuid>,
+    last_seen_at: NaiveDateTime,
+    ip: Option<String>,
+    user_agent: Option<String>,
 }
 
 impl Session {
@@ -51,16 +59,28 @@ impl Session {
         self.0.lock().unwrap().user_id
     }
 
-    /// Set the currently authenticated user
-    pub async fn set_auth_user(&self, user: User) -> Result<(), sqlx::Error> {
+    /// Returns the id of the user who has entered their password but still
+    /// needs to enter a code from their authenticator app
+    pub fn pending_user_id(&self) -> Option<Uuid> {
+        self.0.lock().unwrap().pending_user_id
+    }
+
+    /// Returns the id of the worker access token this session was opened with,
+    /// if any. The token still needs to be checked before trusting it.
+    pub fn worker_token_id(&self) -> Option<Uuid> {
+        self.0.lock().unwrap().worker_token_id
+    }
+
+    /// Set the worker access token used to open this session
+    pub async fn set_worker_token(&self, token_id: Uuid) -> Result<(), sqlx::Error> {
         let session_id = self.0.lock().unwrap().id;
 
         let session = sqlx::query_as!(
             DbSession,
-            "UPDATE session SET user_id = $1 
+            "UPDATE session SET worker_token_id = $1
             WHERE id = $2
             RETURNING *",
-            user.id,
+            token_id,
             session_id,
         )
         .fetch_one(config().pool())
@@ -70,27 +90,106 @@ impl Session {
         Ok(())
     }
 
+    /// Set the user who needs to finish logging in with a second factor. The
+    /// session isn't authenticated until `set_auth_user` is called.
+    pub async fn set_pending_user(&self, user_id: Uuid) -> Result<(), sqlx::Error> {
+        let session_id = self.0.lock().unwrap().id;
+
+        let session = sqlx::query_as!(
+            DbSession,
+            "UPDATE session SET pending_user_id = $1
+            WHERE id = $2
+            RETURNING *",
+            user_id,
+            session_id,
+        )
+        .fetch_one(config().pool())
+        .await?;
+
+        *self.0.lock().unwrap() = session;
+        Ok(())
+    }
+
+    /// Set the currently authenticated user. The session gets a new id, so an
+    /// id someone else planted or saw before logging in is no use to them.
+    pub async fn set_auth_user(&self, user: User) -> Result<(), sqlx::Error> {
+        self.rotate(Some(user.id)).await
+    }
+
     /// Creates a new session and invalidates the existing one
     pub async fn remove_auth_user(&self) -> Result<(), sqlx::Error> {
+        self.rotate(None).await
+    }
+
+    /// Replaces the session with a new one for the user, ending the old one
+    async fn rotate(&self, user_id: Option<Uuid>) -> Result<(), sqlx::Error> {
         let session_id = self.0.lock().unwrap().id;
         let mut tran = config().pool().begin().await?;
-        sqlx::query_as!(
-            DbSession,
+        sqlx::query!(
             "UPDATE session SET expires_at = now() WHERE id = $1",
             session_id
         )
         .execute(&mut *tran)
         .await?;
-        let new_session =
-            sqlx::query_as!(DbSession, "INSERT INTO session DEFAULT VALUES RETURNING *")
-                .fetch_one(&mut *tran)
-                .await?;
+        // Carries over where the session is used from, which the new one
+        // won't see again until the next request
+        let new_session = sqlx::query_as!(
+            DbSession,
+            "INSERT INTO session (user_id, expires_at, ip, user_agent)
+            SELECT $2, now() + make_interval(days => $3), ip, user_agent
+            FROM session WHERE id = $1
+            RETURNING *",
+            session_id,
+            user_id,
+            config().sessions.absolute_days as i32,
+        )
+        .fetch_one(&mut *tran)
+        .await?;
         tran.commit().await?;
         *self.0.lock().unwrap() = new_session;
         Ok(())
     }
 }
 
+/// Logs the user out of all of their sessions, except `keep` if given
+pub async fn end_user_sessions(
+    user_id: Uuid,
+    keep: Option<Uuid>,
+    conn: &mut PgConnection,
+) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query!(
+        "UPDATE session SET expires_at = now()
+        WHERE user_id = $1
+            AND id IS DISTINCT FROM $2
+            AND (expires_at IS NULL OR expires_at > now())",
+        user_id,
+        keep
+    )
+    .execute(conn)
+    .await?;
+    Ok(result.rows_affected())
+}
+
+/// Deletes sessions that have expired or gone unused for too long. Sessions
+/// nobody has logged in to are dropped after an hour, since every visitor gets
+/// one.
+pub async fn sweep() -> Result<u64, sqlx::Error> {
+    let result = sqlx::query!(
+        "DELETE FROM session
+        WHERE expires_at IS NULL
+            OR expires_at <= now()
+            OR last_seen_at < now() - make_interval(hours => $1)
+            OR (
+                user_id IS NULL AND pending_user_id IS NULL AND worker_token_id IS NULL
+                AND last_seen_at < now() - interval '1 hour'
+            )",
+        config().sessions.idle_hours as i32,
+    )
+    .execute(config().pool())
+    .await?;
+    Ok(result.rows_affected())
+}
+
 #[async_trait]
 impl<S> FromRequestParts<S> for Session
 where
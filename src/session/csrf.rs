@@ -0,0 +1,91 @@
+use axum::{
+    body::Body,
+    extract::FromRef,
+    http::{HeaderValue, Method, Request, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use axum_extra::extract::cookie::Key;
+use cafe_website::AppError;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use uuid::Uuid;
+
+use crate::config;
+
+use super::Session;
+
+/// The header HTMX sends the token in, and responses carry the current one in
+pub const CSRF_HEADER: &str = "X-CSRF-Token";
+
+fn mac(session_id: Uuid) -> Hmac<Sha256> {
+    let key = Key::from_ref(config());
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(key.signing()).expect("HMAC takes keys of any size");
+    mac.update(session_id.as_bytes());
+    mac
+}
+
+/// The token for the session. It's derived from the session id, so it changes
+/// whenever the user logs in or out and nothing needs storing.
+pub fn csrf_token(session: &Session) -> String {
+    mac(session.id())
+        .finalize()
+        .into_bytes()
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect()
+}
+
+fn token_matches(session_id: Uuid, token: &str) -> bool {
+    if !token.len().is_multiple_of(2) || !token.is_ascii() {
+        return false;
+    }
+    let bytes: Option<Vec<u8>> = (0..token.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&token[i..i + 2], 16).ok())
+        .collect();
+    bytes.is_some_and(|bytes| mac(session_id).verify_slice(&bytes).is_ok())
+}
+
+fn check(request: &Request<Body>, session: &Session) -> Result<(), AppError> {
+    let safe = matches!(
+        *request.method(),
+        Method::GET | Method::HEAD | Method::OPTIONS
+    );
+    if safe {
+        return Ok(());
+    }
+    let token = request
+        .headers()
+        .get(CSRF_HEADER)
+        .and_then(|t| t.to_str().ok())
+        .ok_or(AppError::inline(
+            StatusCode::FORBIDDEN,
+            "This request is missing its security token. Reload the page and try again",
+        ))?;
+    if !token_matches(session.id(), token) {
+        tracing::warn!("Rejected a request with an invalid csrf token");
+        return Err(AppError::inline(
+            StatusCode::FORBIDDEN,
+            "Your session has changed. Reload the page and try again",
+        ));
+    }
+    Ok(())
+}
+
+/// Rejects requests that change anything unless they carry the session's
+/// token, so other sites can't make them on a user's behalf. Every response
+/// carries the current token, which the page picks up for later requests.
+pub async fn csrf_layer(session: Session, request: Request<Body>, next: Next) -> Response<Body> {
+    let mut response = match check(&request, &session) {
+        Ok(()) => next.run(request).await,
+        Err(e) => e.into_response(),
+    };
+    // Logging in or out changes the session, and so the token
+    response.headers_mut().insert(
+        CSRF_HEADER,
+        HeaderValue::from_str(&csrf_token(&session)).expect("Hex is a valid header value"),
+    );
+    response
+}
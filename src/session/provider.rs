@@ -1,11 +1,20 @@
-use std::sync::{Arc, Mutex};
+use std::{
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+};
 
-use axum::{extract::FromRequestParts, http::Request, middleware::Next, response::IntoResponse};
+use axum::{
+    extract::{ConnectInfo, FromRequestParts},
+    http::{header::USER_AGENT, Request},
+    middleware::Next,
+    response::IntoResponse,
+};
 use axum_extra::extract::{
     cookie::{Cookie, Key, SameSite},
     PrivateCookieJar,
 };
 use cafe_website::AppError;
+use chrono::{Duration, Utc};
 use uuid::Uuid;
 
 use crate::config;
@@ -14,6 +23,10 @@ use super::{DbSession, Session};
 
 use axum::body::Body;
 
+/// How stale a session's last seen time can get before it's updated, so every
+/// request doesn't have to write to the database
+const SEEN_INTERVAL_SECONDS: i64 = 60;
+
 /// Extracts the session from the request cookies (which creates one if it
 /// doesn't exist), and updates the response cookie store with it
 pub async fn session_provider(
@@ -35,12 +48,26 @@ pub async fn session_provider(
         .unwrap_or_else(|| Cookie::from("session"));
     let initial_id = session_cookie.value().parse::<Uuid>().ok();
 
+    let ip = request
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(addr)| addr.ip().to_string());
+    let user_agent = request
+        .headers()
+        .get(USER_AGENT)
+        .and_then(|ua| ua.to_str().ok())
+        .map(|ua| ua.chars().take(512).collect::<String>());
+
+    // Sessions stop working once they're too old or go unused for too long.
+    // The sweeper deletes them later.
     let db_session = if let Some(ref id) = initial_id {
         sqlx::query_as!(
             DbSession,
             "SELECT * FROM session 
-                WHERE id = $1 AND (expires_at IS NULL OR expires_at > now())",
-            id
+                WHERE id = $1 AND expires_at > now()
+                    AND last_seen_at > now() - make_interval(hours => $2)",
+            id,
+            config().sessions.idle_hours as i32,
         )
         .fetch_one(config().pool())
         .await
@@ -50,11 +77,37 @@ pub async fn session_provider(
     };
 
     let db_session = match db_session {
+        Some(s)
+            if s.ip != ip
+                || s.user_agent != user_agent
+                || Utc::now().naive_utc() - s.last_seen_at
+                    > Duration::seconds(SEEN_INTERVAL_SECONDS) =>
+        {
+            sqlx::query_as!(
+                DbSession,
+                "UPDATE session SET last_seen_at = now(), ip = $2, user_agent = $3
+                WHERE id = $1
+                RETURNING *",
+                s.id,
+                ip,
+                user_agent,
+            )
+            .fetch_one(config().pool())
+            .await?
+        }
         Some(s) => s,
         None => {
-            sqlx::query_as!(DbSession, "INSERT INTO session DEFAULT VALUES RETURNING *")
-                .fetch_one(config().pool())
-                .await?
+            sqlx::query_as!(
+                DbSession,
+                "INSERT INTO session (expires_at, ip, user_agent)
+                VALUES (now() + make_interval(days => $1), $2, $3)
+                RETURNING *",
+                config().sessions.absolute_days as i32,
+                ip,
+                user_agent,
+            )
+            .fetch_one(config().pool())
+            .await?
         }
     };
 
@@ -74,6 +127,7 @@ pub async fn session_provider(
             .same_site(SameSite::Strict)
             .secure(true)
             .http_only(true)
+            // Expiry is checked against the database, so the cookie can outlive it
             .permanent() // This is in place of expires bc expires uses time, I'm currently using chrono
             .build();
         Ok((jar.add(cookie), response).into_response())
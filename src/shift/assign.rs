@@ -0,0 +1,269 @@
+use askama::Template;
+use axum::{
+    extract::{Path, Query},
+    http::StatusCode,
+    response::IntoResponse,
+    Form,
+};
+use cafe_website::{filters, AppError};
+use serde::Deserialize;
+use tracing::info;
+use uuid::Uuid;
+
+use crate::{
+    config, email,
+    models::{AgeBracket, Event, Shift, Skill},
+    notify::{self, NotificationKind},
+    worker::Worker,
+};
+
+use super::{
+    assignment,
+    requirement::{Qualifications, Requirements},
+    rules,
+    signup::{valid_email, valid_phone, PHONE_REGEX},
+    view::ShiftTemplate,
+    waitlist,
+};
+
+/// The most workers shown for a search
+const RESULTS: i64 = 10;
+
+pub struct Match {
+    worker: Worker,
+    assigned: bool,
+    /// Requirements of the shift the worker doesn't meet
+    unmet: String,
+}
+
+#[derive(Template)]
+#[template(path = "shift/assign.html")]
+pub enum AssignPicker {
+    Form {
+        shift: Shift,
+        full: bool,
+        required: Requirements,
+    },
+    Results {
+        workers: Vec<Match>,
+    },
+}
+
+/// Lets admins put a worker on a shift themselves, such as when someone signs
+/// up over the phone
+pub async fn assign_form(Path(id): Path<Uuid>) -> Result<AssignPicker, AppError> {
+    let shift = sqlx::query_as!(Shift, "SELECT * FROM shift WHERE id = $1", id)
+        .fetch_one(config().pool())
+        .await?;
+    let full = assignment::filled(id, config().pool()).await? >= shift.capacity as i64;
+    let required = Requirements::of(&shift, config().pool()).await?;
+    Ok(AssignPicker::Form {
+        shift,
+        full,
+        required,
+    })
+}
+
+#[derive(Deserialize)]
+pub struct SearchParams {
+    #[serde(default)]
+    q: String,
+}
+
+pub async fn search(
+    Path(id): Path<Uuid>,
+    Query(SearchParams { q }): Query<SearchParams>,
+) -> Result<AssignPicker, AppError> {
+    let q = q.trim();
+    if q.is_empty() {
+        return Ok(AssignPicker::Results { workers: vec![] });
+    }
+    let pattern = format!(
+        "%{}%",
+        q.replace('\\', "\\\\")
+            .replace('%', "\\%")
+            .replace('_', "\\_")
+    );
+    let shift = sqlx::query_as!(Shift, "SELECT * FROM shift WHERE id = $1", id)
+        .fetch_one(config().pool())
+        .await?;
+    let required = Requirements::of(&shift, config().pool()).await?;
+    let workers = sqlx::query!(
+        r#"SELECT w.id, w.email, w.phone, w.name_first, w.name_last,
+            EXISTS (
+                SELECT 1 FROM shift_assignment WHERE shift_id = $1 AND worker_id = w.id
+            ) as "assigned!",
+            ARRAY(
+                SELECT skill FROM worker_skill WHERE worker_id = w.id
+            ) as "skills!: Vec<Skill>",
+            p.age_bracket as "age_bracket?: AgeBracket"
+        FROM worker as w
+        LEFT JOIN worker_profile as p ON p.worker_id = w.id
+        WHERE w.name_first || ' ' || w.name_last ILIKE $2 OR w.email ILIKE $2
+        ORDER BY w.name_last, w.name_first
+        LIMIT $3"#,
+        id,
+        pattern,
+        RESULTS
+    )
+    .fetch_all(config().pool())
+    .await?
+    .into_iter()
+    .map(|row| Match {
+        worker: Worker {
+            id: row.id,
+            email: row.email,
+            phone: row.phone,
+            name_first: row.name_first,
+            name_last: row.name_last,
+        },
+        assigned: row.assigned,
+        unmet: required.unmet(&Qualifications {
+            skills: row.skills,
+            age_bracket: row.age_bracket,
+        }),
+    })
+    .collect();
+    Ok(AssignPicker::Results { workers })
+}
+
+#[derive(Deserialize)]
+pub struct AssignForm {
+    /// Set by the button of the worker picked from the search
+    worker_id: Option<Uuid>,
+    first_name: Option<String>,
+    last_name: Option<String>,
+    email: Option<String>,
+    phone: Option<String>,
+    send_email: Option<String>,
+}
+
+fn required(value: Option<String>, message: &'static str) -> Result<String, AppError> {
+    value
+        .map(|v| v.trim().to_owned())
+        .filter(|v| !v.is_empty())
+        .ok_or(AppError::inline(StatusCode::BAD_REQUEST, message))
+}
+
+pub async fn assign(
+    Path(id): Path<Uuid>,
+    Form(form): Form<AssignForm>,
+) -> Result<impl IntoResponse, AppError> {
+    let mut tran = config().pool().begin().await?;
+    let shift = sqlx::query_as!(Shift, "SELECT * FROM shift WHERE id = $1 FOR UPDATE", id)
+        .fetch_one(&mut *tran)
+        .await?;
+    if assignment::filled(id, &mut *tran).await? >= shift.capacity as i64 {
+        return Err(AppError::inline(
+            StatusCode::BAD_REQUEST,
+            "This shift is full. Add spots to it before assigning anyone else",
+        ));
+    }
+
+    let worker = match form.worker_id {
+        Some(worker_id) => {
+            sqlx::query_as!(Worker, "SELECT * FROM worker WHERE id = $1", worker_id)
+                .fetch_one(&mut *tran)
+                .await?
+        }
+        None => {
+            let email = required(form.email, "Enter the new worker's email")?.to_lowercase();
+            if !valid_email(&email) {
+                return Err(AppError::inline(StatusCode::BAD_REQUEST, "Invalid email"));
+            }
+            let phone = form.phone.filter(|p| !p.is_empty());
+            if !valid_phone(phone.as_deref()) {
+                return Err(AppError::inline(
+                    StatusCode::BAD_REQUEST,
+                    "Invalid phone number",
+                ));
+            }
+            let first_name = required(form.first_name, "Enter a first name")?;
+            let last_name = required(form.last_name, "Enter a last name")?;
+            let existing = sqlx::query_scalar!("SELECT id FROM worker WHERE email = $1", email)
+                .fetch_optional(&mut *tran)
+                .await?;
+            if existing.is_some() {
+                return Err(AppError::inline(
+                    StatusCode::BAD_REQUEST,
+                    "A worker with that email already exists. Search for them instead",
+                ));
+            }
+            sqlx::query_as!(
+                Worker,
+                "INSERT INTO worker (email, name_first, name_last, phone)
+                VALUES ($1, $2, $3, $4) RETURNING *",
+                email,
+                first_name,
+                last_name,
+                phone
+            )
+            .fetch_one(&mut *tran)
+            .await?
+        }
+    };
+
+    let assigned = sqlx::query_scalar!(
+        r#"SELECT EXISTS (
+            SELECT 1 FROM shift_assignment WHERE shift_id = $1 AND worker_id = $2
+        ) as "exists!""#,
+        id,
+        worker.id
+    )
+    .fetch_one(&mut *tran)
+    .await?;
+    if assigned {
+        return Err(AppError::inline(
+            StatusCode::BAD_REQUEST,
+            "They're already working this shift",
+        ));
+    }
+    if assignment::overlaps(&shift, worker.id, &mut *tran).await? {
+        return Err(AppError::inline(
+            StatusCode::BAD_REQUEST,
+            "They're already signed up for a shift during this one",
+        ));
+    }
+    let event = sqlx::query_as!(Event, "SELECT * FROM event WHERE id = $1", shift.event_id)
+        .fetch_one(&mut *tran)
+        .await?;
+    if let Some(violation) = rules::check(&shift, &event, worker.id, &mut *tran).await? {
+        return Err(AppError::inline(
+            StatusCode::BAD_REQUEST,
+            violation.for_admin(),
+        ));
+    }
+
+    assignment::assign(id, worker.id, &mut tran).await?;
+    notify::record(NotificationKind::Signup, id, worker.id, None, &mut tran).await?;
+    let send_email = form.send_email.is_some_and(|s| s == "on");
+    if send_email {
+        email::send_signup(worker.clone(), shift.clone(), &mut tran).await?;
+    }
+    tran.commit().await?;
+
+    info!(
+        "{} {} was assigned by an admin: {} {}-{} on {}{}",
+        worker.name_first,
+        worker.name_last,
+        shift.title,
+        filters::time_short(&shift.start_time).expect("Infallible"),
+        filters::time_short(&shift.end_time).expect("Infallible"),
+        filters::date_short(&shift.date).expect("Infallible"),
+        if send_email { "" } else { ", without an email" },
+    );
+
+    let workers = assignment::workers(id, config().pool()).await?;
+    let waitlist = waitlist::workers(id).await?;
+    let required = Requirements::of(&shift, config().pool()).await?;
+    Ok((
+        [("HX-Retarget", "#modal")],
+        ShiftTemplate {
+            shift,
+            workers,
+            waitlist,
+            required,
+            logged_in: true,
+        },
+    ))
+}
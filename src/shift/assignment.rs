@@ -0,0 +1,126 @@
+use sqlx::{Executor, PgConnection, Postgres};
+use uuid::Uuid;
+
+use crate::{models::Shift, worker::Worker};
+
+/// Workers assigned to the shift, in the order they signed up
+pub async fn workers<'c>(
+    shift_id: Uuid,
+    executor: impl Executor<'c, Database = Postgres>,
+) -> Result<Vec<Worker>, sqlx::Error> {
+    sqlx::query_as!(
+        Worker,
+        "SELECT w.* FROM worker as w
+        INNER JOIN shift_assignment as sa ON sa.worker_id = w.id
+        WHERE sa.shift_id = $1
+        ORDER BY sa.created_at",
+        shift_id
+    )
+    .fetch_all(executor)
+    .await
+}
+
+/// Shifts the worker is assigned to in date order, limited to one event if
+/// given
+pub async fn shifts<'c>(
+    worker_id: Uuid,
+    event_id: Option<Uuid>,
+    executor: impl Executor<'c, Database = Postgres>,
+) -> Result<Vec<Shift>, sqlx::Error> {
+    sqlx::query_as!(
+        Shift,
+        "SELECT s.* FROM shift as s
+        INNER JOIN shift_assignment as sa ON sa.shift_id = s.id
+        WHERE sa.worker_id = $1 AND ($2::uuid IS NULL OR s.event_id = $2)
+        ORDER BY s.date, s.start_time",
+        worker_id,
+        event_id
+    )
+    .fetch_all(executor)
+    .await
+}
+
+/// Number of workers assigned to the shift
+pub async fn filled<'c>(
+    shift_id: Uuid,
+    executor: impl Executor<'c, Database = Postgres>,
+) -> Result<i64, sqlx::Error> {
+    let count = sqlx::query_scalar!(
+        "SELECT COUNT(*) FROM shift_assignment WHERE shift_id = $1",
+        shift_id
+    )
+    .fetch_one(executor)
+    .await?;
+    Ok(count.unwrap_or(0))
+}
+
+/// Returns true if the worker is already assigned to a shift on the same day
+/// whose time overlaps with this one
+pub async fn overlaps<'c>(
+    shift: &Shift,
+    worker_id: Uuid,
+    executor: impl Executor<'c, Database = Postgres>,
+) -> Result<bool, sqlx::Error> {
+    let overlaps = sqlx::query_scalar!(
+        "SELECT COUNT(*) FROM shift as s
+        INNER JOIN shift_assignment as sa ON sa.shift_id = s.id
+        WHERE s.event_id = $1
+        AND s.date = $2
+        AND sa.worker_id = $3
+        AND (
+            s.start_time < $4 AND s.end_time > $5
+        )
+        ",
+        shift.event_id,
+        shift.date,
+        worker_id,
+        shift.end_time,
+        shift.start_time,
+    )
+    .fetch_one(executor)
+    .await?;
+    Ok(overlaps.is_some_and(|c| c != 0))
+}
+
+/// Assigns the worker to the shift, taking them off its waitlist if they were
+/// on it. Checking capacity and overlaps is up to the caller.
+pub async fn assign(
+    shift_id: Uuid,
+    worker_id: Uuid,
+    conn: &mut PgConnection,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "INSERT INTO shift_assignment (shift_id, worker_id) VALUES ($1, $2)",
+        shift_id,
+        worker_id
+    )
+    .execute(&mut *conn)
+    .await?;
+    sqlx::query!(
+        "DELETE FROM waitlist WHERE shift_id = $1 AND worker_id = $2",
+        shift_id,
+        worker_id
+    )
+    .execute(conn)
+    .await?;
+    Ok(())
+}
+
+/// Removes the worker from the shift, returning the shift if they were
+/// assigned to it
+pub async fn unassign(
+    shift_id: Uuid,
+    worker_id: Uuid,
+    conn: &mut PgConnection,
+) -> Result<Shift, sqlx::Error> {
+    sqlx::query!(
+        "DELETE FROM shift_assignment WHERE shift_id = $1 AND worker_id = $2 RETURNING shift_id",
+        shift_id,
+        worker_id
+    )
+    .fetch_one(&mut *conn)
+    .await?;
+    sqlx::query_as!(Shift, "SELECT * FROM shift WHERE id = $1", shift_id)
+        .fetch_one(conn)
+        .await
+}
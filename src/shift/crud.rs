@@ -1,15 +1,26 @@
 use askama_axum::IntoResponse;
-use axum::{extract::{Path, Query}, Form};
+use axum::{
+    extract::{Path, Query},
+    http::StatusCode,
+};
+use axum_extra::extract::Form;
 use cafe_website::{AppError, Redirect};
 use chrono::NaiveTime;
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
-use crate::{config, models::Shift};
-use crate::worker::Worker;
-
-use super::view::ShiftTemplate;
+use crate::{
+    config,
+    models::{Shift, Skill},
+    notify::{self, NotificationKind},
+};
 
+use super::{
+    assignment,
+    requirement::{self, Requirements},
+    view::ShiftTemplate,
+    waitlist,
+};
 
 #[derive(Serialize, Deserialize)]
 pub struct ShiftUpdate {
@@ -18,60 +29,124 @@ pub struct ShiftUpdate {
     end_time: NaiveTime,
     description: Option<String>,
     public_signup: Option<String>,
+    capacity: i32,
+    adults_only: Option<String>,
+    #[serde(default)]
+    required_skill: Vec<Skill>,
 }
 
 pub async fn update_shift(
     Path(id): Path<Uuid>,
-    Form(ShiftUpdate { title, start_time, end_time, description, public_signup }): Form<ShiftUpdate>
+    Form(ShiftUpdate {
+        title,
+        start_time,
+        end_time,
+        description,
+        public_signup,
+        capacity,
+        adults_only,
+        required_skill,
+    }): Form<ShiftUpdate>,
 ) -> Result<impl IntoResponse, AppError> {
-    let shift = sqlx::query_as!(
-        Shift,
-        "UPDATE shift SET title = $1, start_time = $2, end_time = $3, description = $4, public_signup = $5 WHERE id = $6 RETURNING *",
+    if end_time <= start_time {
+        return Err(AppError::inline(
+            StatusCode::BAD_REQUEST,
+            "A shift has to end after it starts",
+        ));
+    }
+    if capacity < 1 {
+        return Err(AppError::inline(
+            StatusCode::BAD_REQUEST,
+            "A shift needs at least one spot",
+        ));
+    }
+    let mut tran = config().pool().begin().await?;
+    let filled = assignment::filled(id, &mut *tran).await?;
+    if (capacity as i64) < filled {
+        return Err(AppError::inline(
+            StatusCode::BAD_REQUEST,
+            "Remove workers before lowering the number of spots below those filled",
+        ));
+    }
+    sqlx::query!(
+        "UPDATE shift SET title = $1, start_time = $2, end_time = $3, description = $4, public_signup = $5, capacity = $6, adults_only = $7 WHERE id = $8",
         title,
         start_time,
         end_time,
-        description, 
+        description,
         public_signup.is_some_and(|s| s == "on"),
+        capacity,
+        adults_only.is_some_and(|s| s == "on"),
         id
-    ).fetch_one(config().pool()).await?;
-    let worker = match shift.worker_id {
-        Some(id) => Some(
-            sqlx::query_as!(Worker, "SELECT * FROM worker WHERE id = $1", id)
-                .fetch_one(config().pool())
-                .await?,
-        ),
-        None => None,
-    };
+    ).execute(&mut *tran).await?;
+    requirement::save_skills(id, &required_skill, &mut tran).await?;
+    // Any new spots go to the waitlist first
+    waitlist::promote(id, &mut tran).await?;
+    let shift = sqlx::query_as!(Shift, "SELECT * FROM shift WHERE id = $1", id)
+        .fetch_one(&mut *tran)
+        .await?;
+    tran.commit().await?;
+
+    let workers = assignment::workers(id, config().pool()).await?;
+    let waitlist = waitlist::workers(id).await?;
+    let required = Requirements::of(&shift, config().pool()).await?;
 
-    Ok(([("HX-Retarget", "#modal")], ShiftTemplate { shift, worker, logged_in: true }))
+    Ok((
+        [("HX-Retarget", "#modal")],
+        ShiftTemplate {
+            shift,
+            workers,
+            waitlist,
+            required,
+            logged_in: true,
+        },
+    ))
 }
 
-pub async fn delete_shift(
-    Path(id): Path<Uuid>,
-) -> Result<Redirect, AppError> {
-    let shift = sqlx::query_as!(
-        Shift,
-        "DELETE FROM shift WHERE id = $1 RETURNING *",
-        id
-    ).fetch_one(config().pool()).await?;
+pub async fn delete_shift(Path(id): Path<Uuid>) -> Result<Redirect, AppError> {
+    let shift = sqlx::query_as!(Shift, "DELETE FROM shift WHERE id = $1 RETURNING *", id)
+        .fetch_one(config().pool())
+        .await?;
 
-    Ok(Redirect::to(format!("/event/{}?date={}", shift.event_id, shift.date)))
+    Ok(Redirect::to(format!(
+        "/event/{}?date={}",
+        shift.event_id, shift.date
+    )))
 }
 
-
 #[derive(Deserialize)]
 pub struct RmWorkerQuery {
-    id: Uuid
+    id: Uuid,
 }
 
 pub async fn remove_worker(
     Path(id): Path<Uuid>,
     Query(RmWorkerQuery { id: worker_id }): Query<RmWorkerQuery>,
 ) -> Result<impl IntoResponse, AppError> {
-    let shift = sqlx::query_as!(
-        Shift,
-        "UPDATE shift SET worker_id = NULL WHERE id = $1 AND worker_id = $2 RETURNING *",
-        id, worker_id
-    ).fetch_one(config().pool()).await?;
-    Ok(ShiftTemplate { shift, worker: None, logged_in: true })
+    let mut tran = config().pool().begin().await?;
+    assignment::unassign(id, worker_id, &mut tran).await?;
+    notify::record(
+        NotificationKind::Cancellation,
+        id,
+        worker_id,
+        None,
+        &mut tran,
+    )
+    .await?;
+    waitlist::promote(id, &mut tran).await?;
+    let shift = sqlx::query_as!(Shift, "SELECT * FROM shift WHERE id = $1", id)
+        .fetch_one(&mut *tran)
+        .await?;
+    tran.commit().await?;
+
+    let workers = assignment::workers(id, config().pool()).await?;
+    let waitlist = waitlist::workers(id).await?;
+    let required = Requirements::of(&shift, config().pool()).await?;
+    Ok(ShiftTemplate {
+        shift,
+        workers,
+        waitlist,
+        required,
+        logged_in: true,
+    })
 }
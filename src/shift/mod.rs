@@ -1,16 +1,23 @@
 use axum::{
+    middleware::from_extractor,
     routing::{get, patch, put},
     Router,
 };
 
+mod assign;
+pub mod assignment;
 mod crud;
+pub mod requirement;
+mod rules;
 mod signup;
 mod view;
+pub mod waitlist;
 
 use crud::{delete_shift, update_shift};
 use view::{edit_form, view};
 
 use self::crud::remove_worker;
+use crate::accounts::ShiftEditor;
 
 pub fn public_router() -> Router {
     Router::new().route("/:id", get(view)).route(
@@ -23,5 +30,8 @@ pub fn protected_router() -> Router {
     Router::new()
         .route("/:id/edit", get(edit_form))
         .route("/:id/remove_worker", patch(remove_worker))
+        .route("/:id/assign", get(assign::assign_form).post(assign::assign))
+        .route("/:id/assign/search", get(assign::search))
         .route("/:id", put(update_shift).delete(delete_shift))
+        .route_layer(from_extractor::<ShiftEditor>())
 }
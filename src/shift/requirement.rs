@@ -0,0 +1,171 @@
+use sqlx::{Executor, PgConnection, Postgres};
+use uuid::Uuid;
+
+use crate::models::{AgeBracket, Shift, Skill};
+
+/// What a worker needs to take a shift
+#[derive(Clone, Debug, Default)]
+pub struct Requirements {
+    pub skills: Vec<Skill>,
+    pub adults_only: bool,
+}
+
+/// What a worker has that shifts can require, from their profile
+#[derive(Clone, Debug, Default)]
+pub struct Qualifications {
+    pub skills: Vec<Skill>,
+    pub age_bracket: Option<AgeBracket>,
+}
+
+/// Whether a worker can take a shift
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Eligibility {
+    Eligible,
+    MissingSkills(Vec<Skill>),
+    Minor,
+    /// Adults only, and the worker hasn't said how old they are
+    AgeUnknown,
+}
+
+impl Eligibility {
+    /// Can't take the shift at all, rather than needing to confirm their age
+    pub fn is_blocked(&self) -> bool {
+        matches!(self, Self::MissingSkills(_) | Self::Minor)
+    }
+
+    pub fn needs_age(&self) -> bool {
+        *self == Self::AgeUnknown
+    }
+
+    pub fn message(&self) -> &'static str {
+        match self {
+            Self::Eligible => "",
+            Self::MissingSkills(_) => {
+                "This shift needs training you don't have on file. Contact an organizer if you have it"
+            }
+            Self::Minor => "This shift is only for volunteers 18 or older",
+            Self::AgeUnknown => "Confirm you're 18 or older to take this shift",
+        }
+    }
+}
+
+impl Requirements {
+    pub async fn of<'c>(
+        shift: &Shift,
+        executor: impl Executor<'c, Database = Postgres>,
+    ) -> Result<Requirements, sqlx::Error> {
+        Ok(Requirements {
+            skills: skills(shift.id, executor).await?,
+            adults_only: shift.adults_only,
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.skills.is_empty() && !self.adults_only
+    }
+
+    pub fn has_skill(&self, skill: &Skill) -> bool {
+        self.skills.contains(skill)
+    }
+
+    /// A readable list of everything required, like "Grill, 18+"
+    pub fn labels(&self) -> String {
+        let mut labels: Vec<&str> = self.skills.iter().map(Skill::label).collect();
+        if self.adults_only {
+            labels.push("18+");
+        }
+        labels.join(", ")
+    }
+
+    pub fn check(&self, worker: &Qualifications) -> Eligibility {
+        let missing: Vec<Skill> = self
+            .skills
+            .iter()
+            .filter(|skill| !worker.skills.contains(skill))
+            .copied()
+            .collect();
+        if !missing.is_empty() {
+            return Eligibility::MissingSkills(missing);
+        }
+        match (self.adults_only, worker.age_bracket) {
+            (false, _) | (true, Some(AgeBracket::Adult)) => Eligibility::Eligible,
+            (true, Some(_)) => Eligibility::Minor,
+            (true, None) => Eligibility::AgeUnknown,
+        }
+    }
+
+    /// The requirements the worker doesn't meet, for warning admins
+    pub fn unmet(&self, worker: &Qualifications) -> String {
+        let unmet = Requirements {
+            skills: self
+                .skills
+                .iter()
+                .filter(|skill| !worker.skills.contains(skill))
+                .copied()
+                .collect(),
+            adults_only: self.adults_only && worker.age_bracket != Some(AgeBracket::Adult),
+        };
+        unmet.labels()
+    }
+}
+
+/// Skills a worker needs to work the shift
+pub async fn skills<'c>(
+    shift_id: Uuid,
+    executor: impl Executor<'c, Database = Postgres>,
+) -> Result<Vec<Skill>, sqlx::Error> {
+    sqlx::query_scalar!(
+        r#"SELECT skill as "skill: Skill" FROM shift_requirement WHERE shift_id = $1 ORDER BY skill"#,
+        shift_id
+    )
+    .fetch_all(executor)
+    .await
+}
+
+/// Replaces the skills a shift requires
+pub async fn save_skills(
+    shift_id: Uuid,
+    skills: &[Skill],
+    conn: &mut PgConnection,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "DELETE FROM shift_requirement WHERE shift_id = $1",
+        shift_id
+    )
+    .execute(&mut *conn)
+    .await?;
+    for skill in skills {
+        sqlx::query!(
+            "INSERT INTO shift_requirement (shift_id, skill) VALUES ($1, $2) ON CONFLICT DO NOTHING",
+            shift_id,
+            *skill as Skill
+        )
+        .execute(&mut *conn)
+        .await?;
+    }
+    Ok(())
+}
+
+pub async fn qualifications<'c>(
+    worker_id: Uuid,
+    executor: impl Executor<'c, Database = Postgres>,
+) -> Result<Qualifications, sqlx::Error> {
+    let row = sqlx::query!(
+        r#"SELECT
+            ARRAY(SELECT skill FROM worker_skill WHERE worker_id = $1) as "skills!: Vec<Skill>",
+            (SELECT age_bracket FROM worker_profile WHERE worker_id = $1) as "age_bracket: AgeBracket""#,
+        worker_id
+    )
+    .fetch_one(executor)
+    .await?;
+    Ok(Qualifications {
+        skills: row.skills,
+        age_bracket: row.age_bracket,
+    })
+}
+
+/// A readable list of skills, like "Cashier, Grill"
+pub fn labels(skills: &[Skill]) -> String {
+    let labels: Vec<&str> = skills.iter().map(Skill::label).collect();
+    labels.join(", ")
+}
@@ -0,0 +1,103 @@
+use chrono::{Duration, NaiveTime};
+use sqlx::{Executor, Postgres};
+use uuid::Uuid;
+
+use crate::models::{Event, Shift};
+
+/// An event rule that taking a shift would break
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Violation {
+    DailyHours,
+    ShiftCount,
+    Break,
+}
+
+impl Violation {
+    /// Explains the rule to the worker signing up
+    pub fn for_worker(&self) -> &'static str {
+        match self {
+            Self::DailyHours => "This would put you over the most hours a volunteer can work in one day of this event",
+            Self::ShiftCount => "You've signed up for as many shifts as a volunteer can take in this event",
+            Self::Break => "This shift is too close to another of yours. Volunteers need a break between shifts at this event",
+        }
+    }
+
+    /// Explains the rule to an admin assigning someone
+    pub fn for_admin(&self) -> &'static str {
+        match self {
+            Self::DailyHours => "This would put them over the event's limit of hours per day",
+            Self::ShiftCount => "They already have as many shifts as the event allows",
+            Self::Break => {
+                "This shift is too close to another of theirs for the event's minimum break"
+            }
+        }
+    }
+}
+
+fn length(start: NaiveTime, end: NaiveTime) -> Duration {
+    end - start
+}
+
+/// Checks the event's limits on hours, shifts and breaks for the worker
+/// taking the shift. Overlapping shifts are checked separately.
+pub async fn check<'c>(
+    shift: &Shift,
+    event: &Event,
+    worker_id: Uuid,
+    executor: impl Executor<'c, Database = Postgres>,
+) -> Result<Option<Violation>, sqlx::Error> {
+    if event.max_daily_minutes.is_none()
+        && event.max_shifts.is_none()
+        && event.min_break_minutes.is_none()
+    {
+        return Ok(None);
+    }
+    let taken = sqlx::query!(
+        "SELECT s.date, s.start_time, s.end_time FROM shift as s
+        INNER JOIN shift_assignment as sa ON sa.shift_id = s.id
+        WHERE s.event_id = $1 AND sa.worker_id = $2 AND s.id != $3",
+        shift.event_id,
+        worker_id,
+        shift.id
+    )
+    .fetch_all(executor)
+    .await?;
+
+    if let Some(max) = event.max_shifts {
+        if taken.len() as i64 + 1 > max as i64 {
+            return Ok(Some(Violation::ShiftCount));
+        }
+    }
+    let same_day: Vec<(NaiveTime, NaiveTime)> = taken
+        .iter()
+        .filter(|s| s.date == shift.date)
+        .map(|s| (s.start_time, s.end_time))
+        .collect();
+    if let Some(max) = event.max_daily_minutes {
+        let worked = same_day.iter().fold(
+            length(shift.start_time, shift.end_time),
+            |total, (start, end)| total + length(*start, *end),
+        );
+        if worked > Duration::minutes(max as i64) {
+            return Ok(Some(Violation::DailyHours));
+        }
+    }
+    if let Some(min) = event.min_break_minutes {
+        let min = Duration::minutes(min as i64);
+        let too_close = same_day.iter().any(|(start, end)| {
+            let gap = if *end <= shift.start_time {
+                shift.start_time - *end
+            } else if *start >= shift.end_time {
+                *start - shift.end_time
+            } else {
+                // Overlaps are their own error
+                return false;
+            };
+            gap < min
+        });
+        if too_close {
+            return Ok(Some(Violation::Break));
+        }
+    }
+    Ok(None)
+}
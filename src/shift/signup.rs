@@ -10,18 +10,57 @@ use serde::Deserialize;
 use tracing::info;
 use uuid::Uuid;
 
+use crate::notify::{self, NotificationKind};
 use crate::{config, worker::Worker};
-use crate::{email, models::Shift};
+use crate::{
+    email,
+    models::{Event, Shift},
+};
+
+use super::{
+    assignment,
+    requirement::{qualifications, Eligibility, Qualifications, Requirements},
+    rules, waitlist,
+};
+
+pub(super) const PHONE_REGEX: &str = r#"^[2-9][0-9]{2}-[2-9][0-9]{2}-[0-9]{4}$"#;
+
+pub(super) fn valid_email(email: &str) -> bool {
+    let em_rx = Regex::new(r#"^[a-zA-Z0-9.!#$%&'*+/=?^_`{|}~-]+@[a-zA-Z0-9](?:[a-zA-Z0-9-]{0,61}[a-zA-Z0-9])?(?:\.[a-zA-Z0-9](?:[a-zA-Z0-9-]{0,61}[a-zA-Z0-9])?)*$"#).expect("Email regex should be valid");
+    em_rx.is_match(email)
+}
 
-const PHONE_REGEX: &str = r#"^[2-9][0-9]{2}-[2-9][0-9]{2}-[0-9]{4}$"#;
+/// Phone numbers are optional, so a missing one is valid
+pub(super) fn valid_phone(phone: Option<&str>) -> bool {
+    match phone {
+        None | Some("") => true,
+        Some(ph) => {
+            let ph_rx = Regex::new(PHONE_REGEX).expect("Phone regex should be valid");
+            ph_rx.is_match(ph)
+        }
+    }
+}
 
 #[derive(Template)]
 #[template(path = "shift/signup.html")]
 pub enum SignupForm {
-    Empty(Shift),
-    Known(Shift, Worker, Option<&'static str>),
+    Empty {
+        shift: Shift,
+        full: bool,
+    },
+    Known {
+        shift: Shift,
+        full: bool,
+        worker: Worker,
+        eligibility: Eligibility,
+        required: Requirements,
+        error: Option<&'static str>,
+    },
     Unknown {
         shift: Shift,
+        full: bool,
+        eligibility: Eligibility,
+        required: Requirements,
         email: String,
         first_name: Option<String>,
         last_name: Option<String>,
@@ -45,6 +84,8 @@ pub struct SignupBody {
     first_name: Option<String>,
     last_name: Option<String>,
     phone: Option<String>,
+    /// Checked to confirm they're old enough for an adults only shift
+    adult: Option<String>,
 }
 
 pub async fn signup_form(
@@ -61,12 +102,24 @@ pub async fn signup_form(
     )
     .fetch_optional(config().pool())
     .await?;
+    let full = assignment::filled(id, config().pool()).await? >= shift.capacity as i64;
+    let required = Requirements::of(&shift, config().pool()).await?;
 
     Ok(match (params.email, worker) {
-        (_, Some(worker)) => SignupForm::Known(shift, worker, None),
-        (None, None) => SignupForm::Empty(shift),
+        (_, Some(worker)) => SignupForm::Known {
+            eligibility: required.check(&qualifications(worker.id, config().pool()).await?),
+            required,
+            shift,
+            full,
+            worker,
+            error: None,
+        },
+        (None, None) => SignupForm::Empty { shift, full },
         (Some(email), None) => SignupForm::Unknown {
+            eligibility: required.check(&Qualifications::default()),
+            required,
             shift,
+            full,
             email,
             first_name: params.first_name,
             last_name: params.last_name,
@@ -84,14 +137,9 @@ pub async fn signup(
     let shift = sqlx::query_as!(Shift, "SELECT * FROM shift WHERE id = $1 FOR UPDATE", id)
         .fetch_one(&mut *tran)
         .await?;
-
-    // Prevent races
-    if shift.worker_id.is_some() {
-        return Err(AppError::inline(
-            StatusCode::BAD_REQUEST,
-            "This shift is already filled",
-        ));
-    }
+    let full = assignment::filled(id, &mut *tran).await? >= shift.capacity as i64;
+    let required = Requirements::of(&shift, &mut *tran).await?;
+    let adult = body.adult.as_deref() == Some("on");
 
     let worker = sqlx::query_as!(
         Worker,
@@ -100,27 +148,21 @@ pub async fn signup(
     )
     .fetch_optional(&mut *tran)
     .await?;
+    let qualifications = match &worker {
+        Some(w) => qualifications(w.id, &mut *tran).await?,
+        None => Qualifications::default(),
+    };
+    let eligibility = required.check(&qualifications);
+    if eligibility.is_blocked() || (eligibility.needs_age() && !adult) {
+        return Err(AppError::inline(
+            StatusCode::BAD_REQUEST,
+            eligibility.message(),
+        ));
+    }
+
     let worker = match worker {
         Some(w) => {
-            // Check any overlapping shifts
-            let overlaps = sqlx::query_scalar!(
-                "SELECT COUNT(*) FROM shift 
-                WHERE event_id = $1
-                AND date = $2 
-                AND worker_id = $3
-                AND (
-                    start_time < $4 AND end_time > $5
-                )
-                ",
-                shift.event_id,
-                shift.date,
-                w.id,
-                shift.end_time,
-                shift.start_time,
-            )
-            .fetch_one(&mut *tran)
-            .await?;
-            if overlaps.is_some_and(|c| c != 0) {
+            if assignment::overlaps(&shift, w.id, &mut *tran).await? {
                 return Err(AppError::inline(
                     StatusCode::BAD_REQUEST,
                     "You are already signed up for a shift during this one",
@@ -130,11 +172,12 @@ pub async fn signup(
         }
         None => {
             // Create a new worker
-            let em_rx = Regex::new(r#"^[a-zA-Z0-9.!#$%&'*+/=?^_`{|}~-]+@[a-zA-Z0-9](?:[a-zA-Z0-9-]{0,61}[a-zA-Z0-9])?(?:\.[a-zA-Z0-9](?:[a-zA-Z0-9-]{0,61}[a-zA-Z0-9])?)*$"#).expect("Email regex should be valid");
-            let email_match = em_rx.is_match(&body.email);
-            if !email_match {
+            if !valid_email(&body.email) {
                 return Ok(SignupForm::Unknown {
                     shift,
+                    full,
+                    eligibility,
+                    required,
                     email: body.email,
                     first_name: body.first_name,
                     last_name: body.last_name,
@@ -142,16 +185,12 @@ pub async fn signup(
                     error: Some("Invalid email"),
                 });
             }
-            let phone_match = match body.phone.as_deref() {
-                None | Some("") => true,
-                Some(ph) => {
-                    let ph_rx = Regex::new(PHONE_REGEX).expect("Phone regex should be valid");
-                    ph_rx.is_match(ph)
-                }
-            };
-            if !phone_match {
+            if !valid_phone(body.phone.as_deref()) {
                 return Ok(SignupForm::Unknown {
                     shift,
+                    full,
+                    eligibility,
+                    required,
                     email: body.email,
                     first_name: body.first_name,
                     last_name: body.last_name,
@@ -171,22 +210,63 @@ pub async fn signup(
         }
     };
 
+    // New workers have no other shifts, but the shift alone can still be over
+    // the event's daily limit
+    let event = sqlx::query_as!(Event, "SELECT * FROM event WHERE id = $1", shift.event_id)
+        .fetch_one(&mut *tran)
+        .await?;
+    if let Some(violation) = rules::check(&shift, &event, worker.id, &mut *tran).await? {
+        return Err(AppError::inline(
+            StatusCode::BAD_REQUEST,
+            violation.for_worker(),
+        ));
+    }
+
+    if eligibility.needs_age() {
+        sqlx::query!(
+            "INSERT INTO worker_profile (worker_id, age_bracket) VALUES ($1, 'adult')
+            ON CONFLICT (worker_id) DO UPDATE SET age_bracket = 'adult'",
+            worker.id
+        )
+        .execute(&mut *tran)
+        .await?;
+    }
+
     let (worker_name, worker_last, worker_id) = (
         worker.name_first.clone(),
         worker.name_last.clone(),
         worker.id,
     );
 
+    // Shift was filled before this worker got to it
+    if full {
+        waitlist::join(id, worker_id, &mut tran).await?;
+        tran.commit().await?;
+
+        info!(
+            "{} {} joined the waitlist: {} {}-{} on {}",
+            worker_name,
+            worker_last,
+            shift.title,
+            filters::time_short(&shift.start_time).expect("Infallible"),
+            filters::time_short(&shift.end_time).expect("Infallible"),
+            filters::date_short(&shift.date).expect("Infallible"),
+        );
+
+        return Ok(SignupForm::Message(
+            shift,
+            format!(
+                "Thanks, {}. You're on the waitlist and we'll email you if a spot opens up",
+                worker_name
+            ),
+        ));
+    }
+
     // Send email
-    let _ = email::send_signup(worker, shift.clone(), &mut *tran).await?;
+    let _ = email::send_signup(worker, shift.clone(), &mut tran).await?;
 
-    sqlx::query!(
-        "UPDATE shift SET worker_id = $1 WHERE id = $2",
-        worker_id,
-        id
-    )
-    .execute(&mut *tran)
-    .await?;
+    assignment::assign(id, worker_id, &mut tran).await?;
+    notify::record(NotificationKind::Signup, id, worker_id, None, &mut tran).await?;
 
     tran.commit().await?;
 
@@ -5,15 +5,19 @@ use cafe_website::{filters, AppError};
 use uuid::Uuid;
 
 use crate::config;
-use crate::models::Shift;
+use crate::models::{Shift, Skill};
 use crate::session::Session;
 use crate::worker::Worker;
 
+use super::{assignment, requirement::Requirements, waitlist};
+
 #[derive(Debug, Template, Clone)]
 #[template(path = "shift/view.html")]
 pub struct ShiftTemplate {
     pub(super) shift: Shift,
-    pub(super) worker: Option<Worker>,
+    pub(super) workers: Vec<Worker>,
+    pub(super) waitlist: Vec<Worker>,
+    pub(super) required: Requirements,
     pub(super) logged_in: bool,
 }
 
@@ -21,6 +25,7 @@ pub struct ShiftTemplate {
 #[template(path = "shift/edit.html")]
 pub struct ShiftEditTemplate {
     shift: Shift,
+    required: Requirements,
 }
 
 pub async fn view(Path(id): Path<Uuid>, session: Session) -> Result<impl IntoResponse, AppError> {
@@ -34,13 +39,12 @@ pub async fn view(Path(id): Path<Uuid>, session: Session) -> Result<impl IntoRes
     )
     .fetch_one(config().pool())
     .await?;
-    let worker = match shift.worker_id {
-        Some(id) => Some(
-            sqlx::query_as!(Worker, "SELECT * FROM worker WHERE id = $1", id)
-                .fetch_one(config().pool())
-                .await?,
-        ),
-        None => None,
+    let workers = assignment::workers(shift.id, config().pool()).await?;
+    let required = Requirements::of(&shift, config().pool()).await?;
+    let waitlist = if session.is_authenticated() {
+        waitlist::workers(shift.id).await?
+    } else {
+        vec![]
     };
 
     Ok((
@@ -48,7 +52,9 @@ pub async fn view(Path(id): Path<Uuid>, session: Session) -> Result<impl IntoRes
         ShiftTemplate {
             shift,
             logged_in: session.is_authenticated(),
-            worker,
+            workers,
+            waitlist,
+            required,
         },
     ))
 }
@@ -64,6 +70,10 @@ pub async fn edit_form(Path(id): Path<Uuid>) -> Result<impl IntoResponse, AppErr
     )
     .fetch_one(config().pool())
     .await?;
+    let required = Requirements::of(&shift, config().pool()).await?;
 
-    Ok(([("HX-Replace-Url", "false")], ShiftEditTemplate { shift }))
+    Ok((
+        [("HX-Replace-Url", "false")],
+        ShiftEditTemplate { shift, required },
+    ))
 }
@@ -0,0 +1,130 @@
+use cafe_website::{filters, AppError};
+use sqlx::PgConnection;
+use tracing::info;
+use uuid::Uuid;
+
+use crate::{
+    config, email,
+    models::{Event, Shift},
+    notify::{self, NotificationKind},
+    worker::Worker,
+};
+
+use super::{
+    assignment,
+    requirement::{qualifications, Eligibility, Requirements},
+    rules,
+};
+
+/// Adds the worker to the end of the shift's waitlist. Does nothing if they're
+/// already on it.
+pub async fn join(
+    shift_id: Uuid,
+    worker_id: Uuid,
+    conn: &mut PgConnection,
+) -> Result<(), AppError> {
+    sqlx::query!(
+        "INSERT INTO waitlist (shift_id, worker_id) VALUES ($1, $2)
+        ON CONFLICT DO NOTHING",
+        shift_id,
+        worker_id
+    )
+    .execute(conn)
+    .await?;
+    Ok(())
+}
+
+/// Workers waiting for the shift, in the order they joined
+pub async fn workers(shift_id: Uuid) -> Result<Vec<Worker>, AppError> {
+    let workers = sqlx::query_as!(
+        Worker,
+        "SELECT w.* FROM worker as w
+        INNER JOIN waitlist as wl ON wl.worker_id = w.id
+        WHERE wl.shift_id = $1
+        ORDER BY wl.created_at",
+        shift_id
+    )
+    .fetch_all(config().pool())
+    .await?;
+    Ok(workers)
+}
+
+/// Fills any open seats in the shift with the first waitlisted workers who
+/// aren't already working during it, still meet its requirements and stay
+/// within the event's limits, and queues an email letting them know. Should be
+/// called in the same transaction that freed the seats.
+pub async fn promote(shift_id: Uuid, conn: &mut PgConnection) -> Result<Vec<Worker>, AppError> {
+    let shift = sqlx::query_as!(
+        Shift,
+        "SELECT * FROM shift WHERE id = $1 FOR UPDATE",
+        shift_id
+    )
+    .fetch_one(&mut *conn)
+    .await?;
+    let mut open = shift.capacity as i64 - assignment::filled(shift_id, &mut *conn).await?;
+    if open <= 0 {
+        return Ok(vec![]);
+    }
+
+    let waiting = sqlx::query_as!(
+        Worker,
+        "SELECT w.* FROM worker as w
+        INNER JOIN waitlist as wl ON wl.worker_id = w.id
+        WHERE wl.shift_id = $1
+        ORDER BY wl.created_at",
+        shift_id
+    )
+    .fetch_all(&mut *conn)
+    .await?;
+
+    // Requirements, and the worker's other shifts, can change after they join
+    // the waitlist
+    let required = Requirements::of(&shift, &mut *conn).await?;
+    let event = sqlx::query_as!(Event, "SELECT * FROM event WHERE id = $1", shift.event_id)
+        .fetch_one(&mut *conn)
+        .await?;
+
+    let mut promoted = vec![];
+    for worker in waiting {
+        if open <= 0 {
+            break;
+        }
+        if assignment::overlaps(&shift, worker.id, &mut *conn).await? {
+            continue;
+        }
+        let qualifications = qualifications(worker.id, &mut *conn).await?;
+        if required.check(&qualifications) != Eligibility::Eligible {
+            continue;
+        }
+        if rules::check(&shift, &event, worker.id, &mut *conn)
+            .await?
+            .is_some()
+        {
+            continue;
+        }
+
+        assignment::assign(shift_id, worker.id, &mut *conn).await?;
+        email::send_promotion(worker.clone(), shift.clone(), &mut *conn).await?;
+        notify::record(
+            NotificationKind::Signup,
+            shift_id,
+            worker.id,
+            None,
+            &mut *conn,
+        )
+        .await?;
+        open -= 1;
+
+        info!(
+            "{} {} was promoted from the waitlist: {} {}-{} on {}",
+            worker.name_first,
+            worker.name_last,
+            shift.title,
+            filters::time_short(&shift.start_time).expect("Infallible"),
+            filters::time_short(&shift.end_time).expect("Infallible"),
+            filters::date_short(&shift.date).expect("Infallible"),
+        );
+        promoted.push(worker);
+    }
+    Ok(promoted)
+}
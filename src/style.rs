@@ -1,7 +1,9 @@
 use askama_axum::IntoResponse;
 use axum::http::StatusCode;
 
+/// The compiled Tailwind stylesheet
+pub const STYLESHEET: &str = include_str!(concat!(env!("OUT_DIR"), "/style.css"));
+
 pub async fn style() -> impl IntoResponse {
-    const STYLESHEET: &str = include_str!(concat!(env!("OUT_DIR"), "/style.css"));
     (StatusCode::OK, [("Content-Type", "text/css")], STYLESHEET)
 }
@@ -0,0 +1,235 @@
+use askama::Template;
+use axum::{
+    async_trait,
+    extract::{FromRequestParts, Path},
+    http::{request::Parts, StatusCode},
+    Form,
+};
+use cafe_website::{templates::Card, AppError};
+use chrono::{Duration, Utc};
+use serde::Deserialize;
+use sqlx::PgConnection;
+use tracing::info;
+use uuid::Uuid;
+
+use crate::{accounts::current_role, config, models::Role, session::Session, worker::Worker};
+
+use super::view::WorkerView;
+
+/// Proof that the current request may view and manage a worker's details and
+/// shifts. Granted to logged in users, or to sessions opened with a valid
+/// access link for the worker in the path.
+#[derive(Clone, Copy, Debug)]
+pub struct WorkerAccess {
+    pub worker_id: Uuid,
+    /// Owners and schedulers, and coordinators of an event the worker has a
+    /// shift in
+    pub is_admin: bool,
+    /// Other logged in users can look, but not change anything
+    pub read_only: bool,
+}
+
+#[async_trait]
+impl<S> FromRequestParts<S> for WorkerAccess
+where
+    S: Send + Sync,
+{
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let session = Session::from_request_parts(parts, state).await?;
+        let Path(worker_id) = Path::<Uuid>::from_request_parts(parts, state)
+            .await
+            .map_err(|_| cafe_website::error::NOT_FOUND)?;
+
+        if let Some(user_id) = session.user_id() {
+            let is_admin = match current_role(&session).await? {
+                Some(role) if role.can_schedule() => true,
+                Some(Role::Coordinator) => coordinates_worker(user_id, worker_id).await?,
+                _ => false,
+            };
+            return Ok(WorkerAccess {
+                worker_id,
+                is_admin,
+                read_only: !is_admin,
+            });
+        }
+
+        let valid = match session.worker_token_id() {
+            Some(token_id) => token_worker(token_id).await? == Some(worker_id),
+            None => false,
+        };
+        if !valid {
+            return Err(AppError::redirect(
+                StatusCode::UNAUTHORIZED,
+                "Your link has expired",
+                "/worker/access".to_owned(),
+            ));
+        }
+        Ok(WorkerAccess {
+            worker_id,
+            is_admin: false,
+            read_only: false,
+        })
+    }
+}
+
+impl WorkerAccess {
+    /// Stops read only users before they change anything
+    pub fn check_writable(&self) -> Result<(), AppError> {
+        if self.read_only {
+            Err(AppError::inline(
+                StatusCode::FORBIDDEN,
+                "You don't have permission to do that",
+            ))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// Whether the user coordinates an event the worker has a shift in
+async fn coordinates_worker(user_id: Uuid, worker_id: Uuid) -> Result<bool, sqlx::Error> {
+    sqlx::query_scalar!(
+        r#"SELECT EXISTS (
+            SELECT 1 FROM event_coordinator as ec
+            INNER JOIN shift as s ON s.event_id = ec.event_id
+            INNER JOIN shift_assignment as sa ON sa.shift_id = s.id
+            WHERE ec.user_id = $1 AND sa.worker_id = $2
+        ) as "exists!""#,
+        user_id,
+        worker_id
+    )
+    .fetch_one(config().pool())
+    .await
+}
+
+/// The worker a token gives access to, if it hasn't expired or been revoked
+pub async fn token_worker(token_id: Uuid) -> Result<Option<Uuid>, sqlx::Error> {
+    sqlx::query_scalar!(
+        "SELECT worker_id FROM worker_token
+        WHERE id = $1 AND revoked_at IS NULL AND expires_at > now()",
+        token_id
+    )
+    .fetch_optional(config().pool())
+    .await
+}
+
+/// Creates a new access token for the worker, valid for the configured number
+/// of days
+pub async fn issue_token(worker_id: Uuid, conn: &mut PgConnection) -> Result<Uuid, sqlx::Error> {
+    let expires_at = Utc::now() + Duration::days(config().website.worker_link_days);
+    sqlx::query_scalar!(
+        "INSERT INTO worker_token (worker_id, expires_at) VALUES ($1, $2) RETURNING id",
+        worker_id,
+        expires_at
+    )
+    .fetch_one(conn)
+    .await
+}
+
+/// The full url a worker can use to open their page with the given token
+pub fn access_url(token: Uuid) -> String {
+    format!("{}/worker/access/{}", config().url(), token)
+}
+
+pub async fn redeem(session: Session, Path(token): Path<Uuid>) -> Result<WorkerView, AppError> {
+    let worker_id = token_worker(token).await?.ok_or(AppError::redirect(
+        StatusCode::UNAUTHORIZED,
+        "Your link has expired",
+        "/worker/access".to_owned(),
+    ))?;
+
+    session.set_worker_token(token).await?;
+
+    Ok(WorkerView { id: worker_id })
+}
+
+#[derive(Template)]
+#[template(path = "worker/access.html")]
+pub enum AccessForm {
+    Empty,
+    Sent(String),
+}
+
+pub async fn request_form() -> Card<AccessForm> {
+    Card {
+        class: Some("w-fit"),
+        title: "Find My Shifts".to_owned(),
+        child: AccessForm::Empty,
+        show_x: false,
+    }
+}
+
+#[derive(Template)]
+#[template(path = "email/messages/access_link.html")]
+struct AccessLinkMessage {
+    worker: Worker,
+    url: String,
+    days: i64,
+}
+
+#[derive(Deserialize)]
+pub struct AccessRequest {
+    email: String,
+}
+
+pub async fn request_link(Form(req): Form<AccessRequest>) -> Result<AccessForm, AppError> {
+    let mut tran = config().pool().begin().await?;
+    let worker = sqlx::query_as!(
+        Worker,
+        "SELECT * FROM worker WHERE email = $1",
+        req.email.to_lowercase()
+    )
+    .fetch_optional(&mut *tran)
+    .await?;
+
+    // Respond the same way whether or not the address is known, so the form
+    // can't be used to find out who has signed up
+    if let Some(worker) = worker {
+        let token = issue_token(worker.id, &mut tran).await?;
+        let (recipient, address) = (worker.id, worker.email.clone());
+        let message = AccessLinkMessage {
+            worker,
+            url: access_url(token),
+            days: config().website.worker_link_days,
+        }
+        .render()?;
+        sqlx::query!(
+            "INSERT INTO email (status, kind, recipient, address, subject, message)
+            VALUES ('pending', 'html', $1, $2, $3, $4)",
+            recipient,
+            address,
+            "Your Cornerstone Cafe shifts",
+            message,
+        )
+        .execute(&mut *tran)
+        .await?;
+        info!("Sent an access link to worker {}", recipient);
+    }
+    tran.commit().await?;
+
+    Ok(AccessForm::Sent(req.email))
+}
+
+/// Turns off the worker's access links and calendar feed. The feed gets a new
+/// url the next time their shifts are shown.
+pub async fn revoke(Path(worker_id): Path<Uuid>) -> Result<&'static str, AppError> {
+    let mut tran = config().pool().begin().await?;
+    sqlx::query!(
+        "UPDATE worker_token SET revoked_at = now()
+        WHERE worker_id = $1 AND revoked_at IS NULL",
+        worker_id
+    )
+    .execute(&mut *tran)
+    .await?;
+    sqlx::query!(
+        "UPDATE worker_feed SET revoked_at = now()
+        WHERE worker_id = $1 AND revoked_at IS NULL",
+        worker_id
+    )
+    .execute(&mut *tran)
+    .await?;
+    tran.commit().await?;
+    Ok("Links revoked")
+}
@@ -0,0 +1,86 @@
+use axum::{
+    extract::{Path, Query},
+    http::StatusCode,
+};
+use cafe_website::AppError;
+use serde::Deserialize;
+use uuid::Uuid;
+
+use crate::{
+    calendar::{Calendar, CalendarEvent},
+    config,
+    shift::assignment,
+};
+
+#[derive(Deserialize)]
+pub struct FeedQuery {
+    token: Option<Uuid>,
+}
+
+/// The worker's feed token, made the first time it's needed. Separate from
+/// their access links so a calendar service holding the url can only read the
+/// worker's shifts.
+pub async fn feed_token(worker_id: Uuid) -> Result<Uuid, sqlx::Error> {
+    let current = sqlx::query_scalar!(
+        "SELECT id FROM worker_feed WHERE worker_id = $1 AND revoked_at IS NULL
+        ORDER BY created_at DESC LIMIT 1",
+        worker_id
+    )
+    .fetch_optional(config().pool())
+    .await?;
+    if let Some(token) = current {
+        return Ok(token);
+    }
+    sqlx::query_scalar!(
+        "INSERT INTO worker_feed (worker_id) VALUES ($1) RETURNING id",
+        worker_id
+    )
+    .fetch_one(config().pool())
+    .await
+}
+
+/// The url calendar apps subscribe to. They don't share the browser's session,
+/// so it needs the token.
+pub fn feed_url(worker_id: Uuid, token: Uuid) -> String {
+    format!(
+        "{}/worker/{}/shifts.ics?token={}",
+        config().url(),
+        worker_id,
+        token
+    )
+}
+
+/// All of the worker's shifts as a subscribable calendar. Stops working when
+/// the worker's links are revoked.
+pub async fn shift_feed(
+    Path(worker_id): Path<Uuid>,
+    Query(FeedQuery { token }): Query<FeedQuery>,
+) -> Result<Calendar, AppError> {
+    let allowed = match token {
+        Some(token) => {
+            sqlx::query_scalar!(
+                r#"SELECT EXISTS (
+                SELECT 1 FROM worker_feed
+                WHERE id = $1 AND worker_id = $2 AND revoked_at IS NULL
+            ) as "exists!""#,
+                token,
+                worker_id
+            )
+            .fetch_one(config().pool())
+            .await?
+        }
+        None => false,
+    };
+    if !allowed {
+        return Err(AppError::inline(
+            StatusCode::UNAUTHORIZED,
+            "This calendar link has been turned off",
+        ));
+    }
+
+    let shifts = assignment::shifts(worker_id, None, config().pool()).await?;
+    Ok(Calendar::new(
+        "Cornerstone Cafe Shifts".to_owned(),
+        shifts.iter().map(CalendarEvent::from).collect(),
+    ))
+}
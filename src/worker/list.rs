@@ -3,19 +3,25 @@ use std::fmt::Display;
 
 use askama::Template;
 use askama_axum::IntoResponse;
-use axum::extract::Query;
+use axum::{extract::Query, response::Response};
 use cafe_website::{
+    export::{self, ExportQuery, ExportRow},
     pagination::{OrderDirection, PaginationControls},
     AppError, PaginatedQuery,
 };
 use serde::{Deserialize, Serialize};
-use sqlx::{FromRow, QueryBuilder};
+use sqlx::{FromRow, Postgres, QueryBuilder};
 use tracing::debug;
 use uuid::Uuid;
 
-use crate::{config, models::Event};
+use crate::{
+    config,
+    models::{AgeBracket, Event, Skill},
+};
+
+use super::search;
 
-#[derive(Hash, Deserialize, Serialize, Debug, FromRow)]
+#[derive(Clone, Hash, Deserialize, Serialize, Debug, FromRow)]
 pub struct WorkerWithShiftAgg {
     pub id: Uuid,
     pub email: String,
@@ -25,10 +31,27 @@ pub struct WorkerWithShiftAgg {
     pub shifts: Option<i64>,
 }
 
+impl ExportRow for WorkerWithShiftAgg {
+    const HEADERS: &'static [&'static str] =
+        &["First Name", "Last Name", "Email", "Phone", "Shifts"];
+
+    fn record(&self) -> Vec<String> {
+        vec![
+            self.name_first.clone(),
+            self.name_last.clone(),
+            self.email.clone(),
+            self.phone.clone().unwrap_or_default(),
+            self.shifts.unwrap_or_default().to_string(),
+        ]
+    }
+}
+
 #[derive(Template)]
 #[template(path = "worker/list.html")]
 pub struct WorkerListTemplate {
     event_id: Option<Uuid>,
+    skill: Option<Skill>,
+    age_bracket: Option<AgeBracket>,
     events: Vec<Event>,
     workers: Vec<WorkerWithShiftAgg>,
     pagination: PaginatedQuery<WorkerOrderBy, 10, false>,
@@ -61,6 +84,77 @@ impl Display for WorkerOrderBy {
 #[derive(Clone, PartialEq, Eq, Hash, Deserialize, Serialize, Default)]
 pub struct WorkerQuery {
     event_id: Option<String>,
+    search: Option<String>,
+    skill: Option<String>,
+    age_bracket: Option<String>,
+}
+
+impl WorkerQuery {
+    fn search(&self) -> Option<&str> {
+        self.search
+            .as_deref()
+            .map(str::trim)
+            .filter(|s| !s.is_empty())
+    }
+
+    fn event_id(&self) -> Result<Option<Uuid>, AppError> {
+        Ok(match self.event_id.as_deref() {
+            None | Some("") => None,
+            Some(s) => Some(Uuid::try_from(s)?),
+        })
+    }
+
+    fn skill(&self) -> Option<Skill> {
+        Skill::ALL
+            .into_iter()
+            .find(|s| self.skill.as_deref() == Some(&s.to_string()))
+    }
+
+    fn age_bracket(&self) -> Option<AgeBracket> {
+        AgeBracket::ALL
+            .into_iter()
+            .find(|a| self.age_bracket.as_deref() == Some(&a.to_string()))
+    }
+
+    /// Adds the filters to a query joining worker as w and shift as s
+    fn push_filters(&self, builder: &mut QueryBuilder<'static, Postgres>) -> Result<(), AppError> {
+        builder.push(" WHERE TRUE");
+        if let Some(event_id) = self.event_id()? {
+            builder.push(" AND s.event_id = ").push_bind(event_id);
+        }
+        if let Some(skill) = self.skill() {
+            builder
+                .push(" AND EXISTS (SELECT 1 FROM worker_skill WHERE worker_id = w.id AND skill = ")
+                .push_bind(skill)
+                .push(")");
+        }
+        if let Some(age_bracket) = self.age_bracket() {
+            builder
+                .push(" AND EXISTS (SELECT 1 FROM worker_profile WHERE worker_id = w.id AND age_bracket = ")
+                .push_bind(age_bracket)
+                .push(")");
+        }
+        Ok(())
+    }
+}
+
+/// Workers with the number of shifts they have, in the event if given.
+/// Searches also turn up workers without any shifts.
+/// Still needs grouping by worker.
+fn worker_query(query: &WorkerQuery) -> Result<QueryBuilder<'static, Postgres>, AppError> {
+    let mut builder = QueryBuilder::new(if query.search().is_some() {
+        "SELECT w.*, COUNT(DISTINCT sa.shift_id) as shifts
+        FROM worker as w
+        LEFT JOIN shift_assignment as sa ON w.id = sa.worker_id
+        LEFT JOIN shift as s ON s.id = sa.shift_id"
+    } else {
+        "SELECT w.*, COUNT(*) as shifts 
+        FROM worker as w 
+        INNER JOIN shift_assignment as sa ON w.id = sa.worker_id
+        INNER JOIN shift as s ON s.id = sa.shift_id"
+    });
+    query.push_filters(&mut builder)?;
+    Ok(builder)
 }
 
 impl Display for WorkerQuery {
@@ -74,47 +168,56 @@ pub async fn worker_list(
     Query(pagination): Query<PaginatedQuery<WorkerOrderBy, 10, false>>,
     Query(query): Query<WorkerQuery>,
 ) -> Result<impl IntoResponse, AppError> {
-    let event_id = match query.event_id.as_deref() {
-        None | Some("") => None,
-        Some(s) => Some(Uuid::try_from(s)?),
-    };
+    let event_id = query.event_id()?;
 
-    let mut worker_builder = QueryBuilder::new(
-        "SELECT w.*, COUNT(*) as shifts 
-        FROM worker as w 
-        INNER JOIN shift as s ON w.id = s.worker_id",
-    );
+    let mut worker_builder = worker_query(&query)?;
     let mut count_builder = QueryBuilder::new(
         "SELECT COUNT(DISTINCT w.id) 
         FROM worker as w 
-        INNER JOIN shift as s ON w.id = s.worker_id",
+        INNER JOIN shift_assignment as sa ON w.id = sa.worker_id
+        INNER JOIN shift as s ON s.id = sa.shift_id",
     );
-    if let Some(event_id) = event_id {
-        worker_builder
-            .push(" WHERE s.event_id = ")
-            .push_bind(event_id);
-        count_builder
-            .push(" WHERE s.event_id = ")
-            .push_bind(event_id);
-    };
-    // Add other where clauses here
-    worker_builder
-        .push(" GROUP BY w.id")
-        .push(" ")
-        .push(pagination.sql());
-
-    let (workers, count, events): (Vec<WorkerWithShiftAgg>, i64, Vec<Event>) = tokio::try_join!(
-        worker_builder.build_query_as().fetch_all(config().pool()),
-        count_builder
-            .build_query_scalar()
-            .fetch_one(config().pool()),
-        sqlx::query_as!(Event, "SELECT * from event ORDER BY name").fetch_all(config().pool())
-    )?;
+    query.push_filters(&mut count_builder)?;
+    let events =
+        sqlx::query_as!(Event, "SELECT * from event ORDER BY name").fetch_all(config().pool());
+
+    // Fuzzy matches can't be done in the database, so searches are ranked and
+    // paged here, best matches first
+    let (workers, count, events): (Vec<WorkerWithShiftAgg>, i64, Vec<Event>) =
+        if let Some(search) = query.search() {
+            worker_builder.push(" GROUP BY w.id ORDER BY w.name_last, w.name_first");
+            let (all, events) = tokio::try_join!(
+                worker_builder.build_query_as().fetch_all(config().pool()),
+                events
+            )?;
+            let ranked = search::rank(all, search);
+            let count = ranked.len() as i64;
+            let workers = ranked
+                .into_iter()
+                .skip(pagination.skip as usize)
+                .take(pagination.take as usize)
+                .collect();
+            (workers, count, events)
+        } else {
+            worker_builder
+                .push(" GROUP BY w.id")
+                .push(" ")
+                .push(pagination.sql());
+            tokio::try_join!(
+                worker_builder.build_query_as().fetch_all(config().pool()),
+                count_builder
+                    .build_query_scalar()
+                    .fetch_one(config().pool()),
+                events
+            )?
+        };
 
     debug!(?count, "workers:");
 
     let list = WorkerListTemplate {
         event_id,
+        skill: query.skill(),
+        age_bracket: query.age_bracket(),
         events,
         workers,
         pagination,
@@ -123,3 +226,16 @@ pub async fn worker_list(
     };
     Ok(list)
 }
+
+pub async fn export(
+    Query(ExportQuery { format }): Query<ExportQuery>,
+    Query(query): Query<WorkerQuery>,
+) -> Result<Response, AppError> {
+    let mut builder = worker_query(&query)?;
+    builder.push(" GROUP BY w.id ORDER BY w.name_last, w.name_first");
+    if let Some(search) = query.search() {
+        let workers = builder.build_query_as().fetch_all(config().pool()).await?;
+        return export::export_rows("workers", format, search::rank(workers, search));
+    }
+    export::export::<WorkerWithShiftAgg>("workers", format, builder, config().pool().clone()).await
+}
@@ -0,0 +1,271 @@
+use askama::Template;
+use axum::{
+    extract::Path,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Form,
+};
+use cafe_website::{
+    error::{ISE, NOT_FOUND},
+    filters,
+    templates::Card,
+    AppError, Redirect,
+};
+use serde::Deserialize;
+use sqlx::PgConnection;
+use tracing::info;
+use uuid::Uuid;
+
+use crate::{config, models::Shift};
+
+use super::{list::WorkerWithShiftAgg, search};
+
+pub struct Duplicate {
+    pub a: WorkerWithShiftAgg,
+    pub b: WorkerWithShiftAgg,
+    pub reasons: Vec<&'static str>,
+}
+
+#[derive(Template)]
+#[template(path = "worker/duplicates.html")]
+pub struct DuplicatesTemplate {
+    duplicates: Vec<Duplicate>,
+}
+
+/// Pairs of workers that look like the same person signed up twice
+pub async fn duplicates() -> Result<DuplicatesTemplate, AppError> {
+    let workers = sqlx::query_as!(
+        WorkerWithShiftAgg,
+        "SELECT w.id, w.email, w.phone, w.name_first, w.name_last, COUNT(sa.shift_id) as shifts
+        FROM worker as w
+        LEFT JOIN shift_assignment as sa ON w.id = sa.worker_id
+        GROUP BY w.id
+        ORDER BY w.name_last, w.name_first"
+    )
+    .fetch_all(config().pool())
+    .await?;
+
+    // Every pair has to be compared, so keep it off the async threads
+    let duplicates = tokio::task::spawn_blocking(move || {
+        let mut duplicates = vec![];
+        for (i, a) in workers.iter().enumerate() {
+            for b in &workers[i + 1..] {
+                let reasons = search::duplicate_reasons(a, b);
+                if !reasons.is_empty() {
+                    duplicates.push(Duplicate {
+                        a: a.clone(),
+                        b: b.clone(),
+                        reasons,
+                    });
+                }
+            }
+        }
+        duplicates
+    })
+    .await
+    .map_err(|e| {
+        tracing::error!("Couldn't compare workers: {e}");
+        ISE
+    })?;
+
+    Ok(DuplicatesTemplate { duplicates })
+}
+
+#[derive(Deserialize)]
+pub struct MergeForm {
+    into: Uuid,
+}
+
+/// Moves everything belonging to the worker in the path onto the worker it's
+/// merged into, then deletes it. Rows the other worker already has, like a
+/// spot on the same shift, are dropped instead of moved.
+/// Shifts of the merged worker that overlap, for the admin to sort out
+#[derive(Template)]
+#[template(path = "worker/merged.html")]
+pub struct MergedTemplate {
+    worker_id: Uuid,
+    overlaps: Vec<(Shift, Shift)>,
+}
+
+pub async fn merge(
+    Path(worker_id): Path<Uuid>,
+    Form(MergeForm { into }): Form<MergeForm>,
+) -> Result<Response, AppError> {
+    if worker_id == into {
+        return Err(AppError::inline(
+            StatusCode::BAD_REQUEST,
+            "A worker can't be merged into itself",
+        ));
+    }
+    let mut tran = config().pool().begin().await?;
+    let exists = sqlx::query_scalar!(
+        "SELECT COUNT(*) FROM worker WHERE id = $1 OR id = $2",
+        worker_id,
+        into
+    )
+    .fetch_one(&mut *tran)
+    .await?;
+    if exists != Some(2) {
+        return Err(NOT_FOUND);
+    }
+
+    sqlx::query!(
+        "DELETE FROM shift_assignment as sa WHERE sa.worker_id = $1
+        AND EXISTS (SELECT 1 FROM shift_assignment WHERE worker_id = $2 AND shift_id = sa.shift_id)",
+        worker_id,
+        into
+    )
+    .execute(&mut *tran)
+    .await?;
+    sqlx::query!(
+        "UPDATE shift_assignment SET worker_id = $2 WHERE worker_id = $1",
+        worker_id,
+        into
+    )
+    .execute(&mut *tran)
+    .await?;
+
+    // Waiting on a shift the other worker is already on, or already waiting on
+    sqlx::query!(
+        "DELETE FROM waitlist as wl WHERE wl.worker_id = $1
+        AND (EXISTS (SELECT 1 FROM waitlist WHERE worker_id = $2 AND shift_id = wl.shift_id)
+            OR EXISTS (SELECT 1 FROM shift_assignment WHERE worker_id = $2 AND shift_id = wl.shift_id))",
+        worker_id,
+        into
+    )
+    .execute(&mut *tran)
+    .await?;
+    sqlx::query!(
+        "UPDATE waitlist SET worker_id = $2 WHERE worker_id = $1",
+        worker_id,
+        into
+    )
+    .execute(&mut *tran)
+    .await?;
+    // Including shifts the other worker was waiting on that they now have
+    sqlx::query!(
+        "DELETE FROM waitlist as wl WHERE wl.worker_id = $1
+        AND EXISTS (SELECT 1 FROM shift_assignment WHERE worker_id = $1 AND shift_id = wl.shift_id)",
+        into
+    )
+    .execute(&mut *tran)
+    .await?;
+
+    sqlx::query!(
+        "DELETE FROM reminder as r WHERE r.worker_id = $1
+        AND EXISTS (SELECT 1 FROM reminder WHERE worker_id = $2 AND event_id = r.event_id AND date = r.date)",
+        worker_id,
+        into
+    )
+    .execute(&mut *tran)
+    .await?;
+    sqlx::query!(
+        "UPDATE reminder SET worker_id = $2 WHERE worker_id = $1",
+        worker_id,
+        into
+    )
+    .execute(&mut *tran)
+    .await?;
+
+    sqlx::query!(
+        "UPDATE email SET recipient = $2 WHERE recipient = $1",
+        worker_id,
+        into
+    )
+    .execute(&mut *tran)
+    .await?;
+    sqlx::query!(
+        "UPDATE worker_token SET worker_id = $2 WHERE worker_id = $1",
+        worker_id,
+        into
+    )
+    .execute(&mut *tran)
+    .await?;
+    sqlx::query!(
+        "UPDATE notification SET worker_id = $2 WHERE worker_id = $1",
+        worker_id,
+        into
+    )
+    .execute(&mut *tran)
+    .await?;
+
+    // Profile details are only moved where the other worker has none
+    sqlx::query!(
+        "UPDATE worker_profile SET worker_id = $2 WHERE worker_id = $1
+        AND NOT EXISTS (SELECT 1 FROM worker_profile WHERE worker_id = $2)",
+        worker_id,
+        into
+    )
+    .execute(&mut *tran)
+    .await?;
+    sqlx::query!(
+        "INSERT INTO worker_skill (worker_id, skill)
+        SELECT $2, skill FROM worker_skill WHERE worker_id = $1
+        ON CONFLICT DO NOTHING",
+        worker_id,
+        into
+    )
+    .execute(&mut *tran)
+    .await?;
+    sqlx::query!(
+        "INSERT INTO worker_availability (worker_id, event_id, date, start_time, end_time)
+        SELECT $2, event_id, date, start_time, end_time FROM worker_availability WHERE worker_id = $1
+        ON CONFLICT DO NOTHING",
+        worker_id,
+        into
+    )
+    .execute(&mut *tran)
+    .await?;
+
+    sqlx::query!("DELETE FROM worker WHERE id = $1", worker_id)
+        .execute(&mut *tran)
+        .await?;
+    let overlaps = overlaps(into, &mut tran).await?;
+    tran.commit().await?;
+
+    info!(%worker_id, %into, "Merged worker");
+    if overlaps.is_empty() {
+        return Ok(Redirect::to(format!("/worker/{into}")).into_response());
+    }
+    Ok((
+        [("HX-Retarget", "#modal")],
+        Card::modal(
+            "Overlapping Shifts".to_owned(),
+            MergedTemplate {
+                worker_id: into,
+                overlaps,
+            },
+        ),
+    )
+        .into_response())
+}
+
+/// Pairs of the worker's shifts that are at the same time
+async fn overlaps(
+    worker_id: Uuid,
+    conn: &mut PgConnection,
+) -> Result<Vec<(Shift, Shift)>, sqlx::Error> {
+    let shifts = sqlx::query_as!(
+        Shift,
+        "SELECT s.* FROM shift as s
+        INNER JOIN shift_assignment as sa ON sa.shift_id = s.id
+        WHERE sa.worker_id = $1
+        ORDER BY s.date, s.start_time",
+        worker_id
+    )
+    .fetch_all(conn)
+    .await?;
+    let mut pairs = vec![];
+    for (i, a) in shifts.iter().enumerate() {
+        for b in &shifts[i + 1..] {
+            if a.event_id == b.event_id
+                && a.date == b.date
+                && a.start_time < b.end_time
+                && a.end_time > b.start_time
+            {
+                pairs.push((a.clone(), b.clone()));
+            }
+        }
+    }
+    Ok(pairs)
+}
@@ -1,22 +1,57 @@
-use axum::{routing::get, Router};
+use axum::{
+    middleware::from_extractor,
+    routing::{delete, get, post},
+    Router,
+};
 
+pub use access::{access_url, issue_token, token_worker};
 use list::worker_list;
 pub use model::Worker;
 
+use crate::accounts::Scheduler;
+
+mod access;
+mod calendar;
 mod list;
+mod merge;
 mod model;
+mod profile;
+mod search;
 mod shift_list;
 mod view;
 
 pub fn public_router() -> Router {
     Router::new()
+        .route(
+            "/access",
+            get(access::request_form).post(access::request_link),
+        )
+        .route("/access/:token", get(access::redeem))
         .route("/:id", get(view::view).patch(view::save))
         .route("/:id/details", get(view::details))
         .route("/:id/edit", get(view::edit))
         .route("/:id/shifts", get(shift_list::shift_list))
-        .route("/:id/cancel_shift", get(shift_list::cancel_shift))
+        .route("/:id/shifts.ics", get(calendar::shift_feed))
+        .route(
+            "/:id/cancel_shift",
+            get(shift_list::confirm_cancel).post(shift_list::cancel_shift),
+        )
 }
 
 pub fn protected_router() -> Router {
-    Router::new().route("/list", get(worker_list))
+    Router::new()
+        .route("/list", get(worker_list))
+        .route("/export", get(list::export))
+        .route(
+            "/duplicates",
+            get(merge::duplicates).route_layer(from_extractor::<Scheduler>()),
+        )
+        .route(
+            "/:id/merge",
+            post(merge::merge).route_layer(from_extractor::<Scheduler>()),
+        )
+        .route(
+            "/:id/access",
+            delete(access::revoke).route_layer(from_extractor::<Scheduler>()),
+        )
 }
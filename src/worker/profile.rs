@@ -0,0 +1,243 @@
+use cafe_website::{filters, AppError};
+use chrono::{NaiveDate, NaiveTime, Utc};
+use sqlx::PgConnection;
+use uuid::Uuid;
+
+use crate::{
+    config,
+    models::{AgeBracket, ShirtSize, Skill},
+    shift::requirement,
+};
+
+/// An upcoming event day, and whether the worker said they can help on it
+pub struct AvailabilityDay {
+    pub event_id: Uuid,
+    pub event_name: String,
+    pub date: NaiveDate,
+    pub available: bool,
+    pub start_time: Option<NaiveTime>,
+    pub end_time: Option<NaiveTime>,
+}
+
+impl AvailabilityDay {
+    /// Identifies the day in the edit form
+    pub fn key(&self) -> String {
+        format!("{}/{}", self.event_id, self.date)
+    }
+
+    pub fn hours(&self) -> String {
+        let time = |t: &NaiveTime| filters::time_short(t).expect("Infallible");
+        match (&self.start_time, &self.end_time) {
+            (None, None) => "All day".to_owned(),
+            (Some(start), None) => format!("From {}", time(start)),
+            (None, Some(end)) => format!("Until {}", time(end)),
+            (Some(start), Some(end)) => format!("{} - {}", time(start), time(end)),
+        }
+    }
+
+    pub fn start_value(&self) -> String {
+        self.start_time
+            .map(|t| t.format("%H:%M").to_string())
+            .unwrap_or_default()
+    }
+
+    pub fn end_value(&self) -> String {
+        self.end_time
+            .map(|t| t.format("%H:%M").to_string())
+            .unwrap_or_default()
+    }
+}
+
+/// Optional details about a worker, beyond what they give when signing up
+pub struct Profile {
+    pub shirt_size: Option<ShirtSize>,
+    pub age_bracket: Option<AgeBracket>,
+    /// Only shown to admins
+    pub notes: Option<String>,
+    pub skills: Vec<Skill>,
+    pub days: Vec<AvailabilityDay>,
+}
+
+impl Profile {
+    pub fn has_skill(&self, skill: &Skill) -> bool {
+        self.skills.contains(skill)
+    }
+
+    pub fn skill_list(&self) -> String {
+        if self.skills.is_empty() {
+            return " - ".to_owned();
+        }
+        requirement::labels(&self.skills)
+    }
+
+    pub fn shirt_label(&self) -> &'static str {
+        self.shirt_size.map_or(" - ", |s| s.label())
+    }
+
+    pub fn age_label(&self) -> &'static str {
+        self.age_bracket.map_or(" - ", |a| a.label())
+    }
+
+    pub fn available_days(&self) -> Vec<&AvailabilityDay> {
+        self.days.iter().filter(|d| d.available).collect()
+    }
+}
+
+pub async fn load(worker_id: Uuid) -> Result<Profile, AppError> {
+    let today = Utc::now().with_timezone(&config().timezone()).date_naive();
+    let row = sqlx::query!(
+        r#"SELECT shirt_size as "shirt_size: ShirtSize",
+            age_bracket as "age_bracket: AgeBracket",
+            notes
+        FROM worker_profile WHERE worker_id = $1"#,
+        worker_id
+    )
+    .fetch_optional(config().pool());
+    let skills = sqlx::query_scalar!(
+        r#"SELECT skill as "skill: Skill" FROM worker_skill WHERE worker_id = $1 ORDER BY skill"#,
+        worker_id
+    )
+    .fetch_all(config().pool());
+    let days = sqlx::query_as!(
+        AvailabilityDay,
+        r#"SELECT d.event_id, e.name as event_name, d.date,
+            a.worker_id IS NOT NULL as "available!",
+            a.start_time as "start_time?", a.end_time as "end_time?"
+        FROM day as d
+        INNER JOIN event as e ON e.id = d.event_id
+        LEFT JOIN worker_availability as a
+            ON a.event_id = d.event_id AND a.date = d.date AND a.worker_id = $1
+        WHERE d.date >= $2
+        ORDER BY d.date, e.name"#,
+        worker_id,
+        today
+    )
+    .fetch_all(config().pool());
+    let (row, skills, days) = tokio::try_join!(row, skills, days)?;
+
+    Ok(Profile {
+        shirt_size: row.as_ref().and_then(|r| r.shirt_size),
+        age_bracket: row.as_ref().and_then(|r| r.age_bracket),
+        notes: row.and_then(|r| r.notes),
+        skills,
+        days,
+    })
+}
+
+/// A day the worker can help, from the edit form
+pub struct Availability {
+    pub event_id: Uuid,
+    pub date: NaiveDate,
+    pub start_time: Option<NaiveTime>,
+    pub end_time: Option<NaiveTime>,
+}
+
+impl Availability {
+    /// Parses a day checked in the edit form, with its key and the times
+    /// entered for it
+    pub fn parse(key: &str, start: &str, end: &str) -> Result<Availability, &'static str> {
+        let invalid = "Invalid availability";
+        let (event_id, date) = key.split_once('/').ok_or(invalid)?;
+        let time = |t: &str| match t {
+            "" => Ok(None),
+            t => NaiveTime::parse_from_str(t, "%H:%M")
+                .map(Some)
+                .map_err(|_| invalid),
+        };
+        let availability = Availability {
+            event_id: Uuid::try_from(event_id).map_err(|_| invalid)?,
+            date: NaiveDate::parse_from_str(date, "%Y-%m-%d").map_err(|_| invalid)?,
+            start_time: time(start)?,
+            end_time: time(end)?,
+        };
+        if let (Some(start), Some(end)) = (availability.start_time, availability.end_time) {
+            if end <= start {
+                return Err("Available hours have to end after they start");
+            }
+        }
+        Ok(availability)
+    }
+}
+
+pub struct ProfileUpdate {
+    pub shirt_size: Option<ShirtSize>,
+    /// Left alone if `None`, since shifts can require an age workers could
+    /// otherwise give themselves
+    pub age_bracket: Option<Option<AgeBracket>>,
+    /// Left alone if `None`, since workers can't see their notes
+    pub notes: Option<Option<String>>,
+    /// Left alone if `None`, for the same reason as the age bracket
+    pub skills: Option<Vec<Skill>>,
+    pub availability: Vec<Availability>,
+}
+
+/// Replaces the worker's profile. Availability for days that have passed is
+/// kept, since those days aren't in the form.
+pub async fn save(
+    worker_id: Uuid,
+    update: ProfileUpdate,
+    conn: &mut PgConnection,
+) -> Result<(), AppError> {
+    let today = Utc::now().with_timezone(&config().timezone()).date_naive();
+    let (set_notes, notes) = match update.notes {
+        Some(notes) => (true, notes),
+        None => (false, None),
+    };
+    let (set_age, age_bracket) = match update.age_bracket {
+        Some(age_bracket) => (true, age_bracket),
+        None => (false, None),
+    };
+    sqlx::query!(
+        r#"INSERT INTO worker_profile (worker_id, shirt_size, age_bracket, notes)
+        VALUES ($1, $2, $6, $5)
+        ON CONFLICT (worker_id) DO UPDATE SET
+            shirt_size = $2,
+            age_bracket = CASE WHEN $3 THEN $6 ELSE worker_profile.age_bracket END,
+            notes = CASE WHEN $4 THEN $5 ELSE worker_profile.notes END"#,
+        worker_id,
+        update.shirt_size as Option<ShirtSize>,
+        set_age,
+        set_notes,
+        notes,
+        age_bracket as Option<AgeBracket>
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    if let Some(skills) = update.skills {
+        sqlx::query!("DELETE FROM worker_skill WHERE worker_id = $1", worker_id)
+            .execute(&mut *conn)
+            .await?;
+        for skill in skills {
+            sqlx::query!(
+                "INSERT INTO worker_skill (worker_id, skill) VALUES ($1, $2) ON CONFLICT DO NOTHING",
+                worker_id,
+                skill as Skill
+            )
+            .execute(&mut *conn)
+            .await?;
+        }
+    }
+
+    sqlx::query!(
+        "DELETE FROM worker_availability WHERE worker_id = $1 AND date >= $2",
+        worker_id,
+        today
+    )
+    .execute(&mut *conn)
+    .await?;
+    for day in update.availability {
+        sqlx::query!(
+            "INSERT INTO worker_availability (worker_id, event_id, date, start_time, end_time)
+            VALUES ($1, $2, $3, $4, $5)",
+            worker_id,
+            day.event_id,
+            day.date,
+            day.start_time,
+            day.end_time
+        )
+        .execute(&mut *conn)
+        .await?;
+    }
+    Ok(())
+}
@@ -0,0 +1,96 @@
+use strsim::jaro_winkler;
+
+use super::list::WorkerWithShiftAgg;
+
+/// How similar a fuzzy match has to be to count
+const MATCH_THRESHOLD: f64 = 0.85;
+/// How similar two names have to be to flag the workers as possible duplicates
+const DUPLICATE_NAME_THRESHOLD: f64 = 0.93;
+
+fn digits(s: &str) -> String {
+    s.chars().filter(|c| c.is_ascii_digit()).collect()
+}
+
+/// The part of an email before the @, ignoring dots and anything after a +,
+/// which some providers treat as the same address
+fn mailbox(email: &str) -> String {
+    let local = email.split('@').next().unwrap_or_default();
+    let local = local.split('+').next().unwrap_or_default();
+    local.replace('.', "").to_lowercase()
+}
+
+fn full_name(worker: &WorkerWithShiftAgg) -> String {
+    format!("{} {}", worker.name_first, worker.name_last).to_lowercase()
+}
+
+/// How well a worker matches a search, from 0 to 1. Exact substrings of the
+/// name, email or phone number are a perfect match, otherwise each word of the
+/// search is compared to the closest part of the worker's details so typos
+/// still turn them up.
+fn score(worker: &WorkerWithShiftAgg, search: &str) -> f64 {
+    let search = search.trim().to_lowercase();
+    let name = full_name(worker);
+    let email = worker.email.to_lowercase();
+    let search_digits = digits(&search);
+    let phone_match = search_digits.len() >= 3
+        && worker
+            .phone
+            .as_deref()
+            .is_some_and(|p| digits(p).contains(&search_digits));
+    if name.contains(&search) || email.contains(&search) || phone_match {
+        return 1.0;
+    }
+
+    let parts = [
+        worker.name_first.to_lowercase(),
+        worker.name_last.to_lowercase(),
+        mailbox(&email),
+    ];
+    let words: Vec<&str> = search.split_whitespace().collect();
+    if words.is_empty() {
+        return 0.0;
+    }
+    let total: f64 = words
+        .iter()
+        .map(|word| {
+            parts
+                .iter()
+                .map(|part| jaro_winkler(word, part))
+                .fold(0.0, f64::max)
+        })
+        .sum();
+    (total / words.len() as f64).max(jaro_winkler(&search, &name))
+}
+
+/// The workers matching the search, best matches first
+pub fn rank(workers: Vec<WorkerWithShiftAgg>, search: &str) -> Vec<WorkerWithShiftAgg> {
+    let mut scored: Vec<(f64, WorkerWithShiftAgg)> = workers
+        .into_iter()
+        .map(|worker| (score(&worker, search), worker))
+        .filter(|(score, _)| *score >= MATCH_THRESHOLD)
+        .collect();
+    scored.sort_by(|(a, _), (b, _)| b.total_cmp(a));
+    scored.into_iter().map(|(_, worker)| worker).collect()
+}
+
+/// Why two workers might be the same person, if they might be
+pub fn duplicate_reasons(a: &WorkerWithShiftAgg, b: &WorkerWithShiftAgg) -> Vec<&'static str> {
+    let mut reasons = vec![];
+    let (phone_a, phone_b) = (
+        a.phone.as_deref().map(digits).unwrap_or_default(),
+        b.phone.as_deref().map(digits).unwrap_or_default(),
+    );
+    if !phone_a.is_empty() && phone_a == phone_b {
+        reasons.push("Same phone number");
+    }
+    if mailbox(&a.email) == mailbox(&b.email) {
+        reasons.push("Similar email");
+    }
+    let (name_a, name_b) = (full_name(a), full_name(b));
+    if name_a == name_b {
+        reasons.push("Same name");
+    } else if jaro_winkler(&name_a, &name_b) >= DUPLICATE_NAME_THRESHOLD {
+        reasons.push("Similar name");
+    }
+    reasons
+}
@@ -1,17 +1,57 @@
 use askama::Template;
-use axum::extract::{Path, Query};
+use axum::{extract::Query, http::StatusCode, response::IntoResponse, Form};
 use cafe_website::{error, filters, AppError};
+use chrono::Utc;
 use serde::Deserialize;
+use sqlx::PgConnection;
 use std::borrow::Borrow;
 use tracing::info;
 use uuid::Uuid;
 
 use crate::{
-    config,
+    config::{config, Admin},
+    email,
     models::{Event, Shift},
-    session::Session,
+    notify::{self, NotificationKind},
+    shift::{assignment, waitlist},
 };
 
+use super::{access::WorkerAccess, calendar};
+
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum CancelPolicy {
+    Allowed,
+    /// The shift starts too soon for the worker to cancel it themselves
+    TooLate,
+    NotAllowed,
+}
+
+impl CancelPolicy {
+    /// Admins can cancel any shift that hasn't started. Workers can only cancel
+    /// while the event is taking signups, and have to give enough notice.
+    /// Read only users can't cancel anything.
+    fn of(shift: &Shift, event: &Event, access: &WorkerAccess) -> CancelPolicy {
+        let is_admin = access.is_admin;
+        let now = Utc::now().with_timezone(&config().timezone()).naive_local();
+        let start = shift.date.and_time(shift.start_time);
+        if access.read_only || start <= now || !(is_admin || event.allow_signups) {
+            CancelPolicy::NotAllowed
+        } else if !is_admin && start - config().website.cancel_cutoff() <= now {
+            CancelPolicy::TooLate
+        } else {
+            CancelPolicy::Allowed
+        }
+    }
+
+    fn is_allowed(&self) -> bool {
+        *self == CancelPolicy::Allowed
+    }
+
+    fn is_too_late(&self) -> bool {
+        *self == CancelPolicy::TooLate
+    }
+}
+
 #[derive(Template)]
 #[template(path = "worker/shift_list.html")]
 pub enum ShiftList {
@@ -19,8 +59,11 @@ pub enum ShiftList {
         worker_id: Uuid,
         event_id: Uuid,
         events: Vec<Event>,
-        shifts: Vec<Shift>,
-        may_cancel: bool,
+        shifts: Vec<(Shift, CancelPolicy)>,
+        any_too_late: bool,
+        cutoff_hours: i64,
+        admin: &'static Admin,
+        feed_url: String,
     },
     None,
 }
@@ -31,14 +74,15 @@ pub struct ShiftListQuery {
 }
 
 pub async fn shift_list(
-    session: Session,
-    Path(worker_id): Path<Uuid>,
+    access: WorkerAccess,
     Query(query): Query<ShiftListQuery>,
 ) -> Result<ShiftList, AppError> {
+    let worker_id = access.worker_id;
     let events = sqlx::query_as!(
         Event,
         "SELECT e.* FROM event e JOIN shift s ON e.id = s.event_id
-        WHERE s.worker_id = $1
+        JOIN shift_assignment sa ON sa.shift_id = s.id
+        WHERE sa.worker_id = $1
         GROUP BY e.id
         ORDER BY MIN(s.date) ASC",
         worker_id
@@ -55,53 +99,128 @@ pub async fn shift_list(
     };
     let event_id = selected_event.id;
 
-    let shifts = sqlx::query_as!(
-        Shift,
-        "SELECT * FROM shift WHERE event_id = $1 AND worker_id = $2 ORDER BY date, start_time",
-        event_id,
-        worker_id
-    )
-    .fetch_all(config().pool())
-    .await?;
-
-    let in_future = shifts.first().expect("Must have 1+ shift to be here").date
-        > chrono::Local::now().date_naive();
+    let shifts: Vec<(Shift, CancelPolicy)> =
+        assignment::shifts(worker_id, Some(event_id), config().pool())
+            .await?
+            .into_iter()
+            .map(|shift| {
+                let policy = CancelPolicy::of(&shift, selected_event, &access);
+                (shift, policy)
+            })
+            .collect();
 
     Ok(ShiftList::Some {
         worker_id,
         event_id,
-        may_cancel: in_future && (session.is_authenticated() || selected_event.allow_signups),
+        any_too_late: shifts.iter().any(|(_, policy)| policy.is_too_late()),
+        cutoff_hours: config().website.cancel_cutoff_hours,
+        admin: &config().admin,
+        feed_url: calendar::feed_url(worker_id, calendar::feed_token(worker_id).await?),
         events,
         shifts,
     })
 }
 
+/// The worker's shift, if the worker with this access may cancel it
+async fn cancellable_shift(
+    access: &WorkerAccess,
+    shift_id: Uuid,
+    conn: &mut PgConnection,
+) -> Result<Shift, AppError> {
+    access.check_writable()?;
+    let shift = sqlx::query_as!(
+        Shift,
+        "SELECT s.* FROM shift as s
+        INNER JOIN shift_assignment as sa ON sa.shift_id = s.id
+        WHERE s.id = $1 AND sa.worker_id = $2",
+        shift_id,
+        access.worker_id
+    )
+    .fetch_optional(&mut *conn)
+    .await?
+    .ok_or(error::NOT_FOUND)?;
+    let event = sqlx::query_as!(Event, "SELECT * FROM event WHERE id = $1", shift.event_id)
+        .fetch_one(&mut *conn)
+        .await?;
+
+    match CancelPolicy::of(&shift, &event, access) {
+        CancelPolicy::Allowed => Ok(shift),
+        CancelPolicy::TooLate => Err(AppError::inline(
+            StatusCode::BAD_REQUEST,
+            "This shift starts too soon to cancel here. Please contact us instead",
+        )),
+        CancelPolicy::NotAllowed => Err(AppError::inline(
+            StatusCode::BAD_REQUEST,
+            "This shift can no longer be canceled",
+        )),
+    }
+}
+
+#[derive(Template)]
+#[template(path = "worker/cancel_shift.html")]
+pub struct CancelShiftTemplate {
+    worker_id: Uuid,
+    shift: Shift,
+}
+
 #[derive(Deserialize)]
 pub struct CancelShiftParams {
     shift_id: Uuid,
 }
 
-pub async fn cancel_shift(
-    session: Session,
-    Path(worker_id): Path<Uuid>,
+/// Asks the worker to confirm, and why, before anything changes
+pub async fn confirm_cancel(
+    access: WorkerAccess,
     Query(CancelShiftParams { shift_id }): Query<CancelShiftParams>,
-) -> Result<ShiftList, AppError> {
+) -> Result<CancelShiftTemplate, AppError> {
+    let mut conn = config().pool().acquire().await?;
+    let shift = cancellable_shift(&access, shift_id, &mut conn).await?;
+    Ok(CancelShiftTemplate {
+        worker_id: access.worker_id,
+        shift,
+    })
+}
+
+#[derive(Deserialize)]
+pub struct CancelShiftForm {
+    shift_id: Uuid,
+    reason: String,
+}
+
+pub async fn cancel_shift(
+    access: WorkerAccess,
+    Form(CancelShiftForm { shift_id, reason }): Form<CancelShiftForm>,
+) -> Result<impl IntoResponse, AppError> {
+    let reason = reason.trim();
+    if reason.is_empty() {
+        return Err(AppError::inline(
+            StatusCode::BAD_REQUEST,
+            "Please let us know why you're canceling",
+        ));
+    }
+    let worker_id = access.worker_id;
     let mut tran = config().pool().begin().await?;
 
-    let shift = sqlx::query_as!(
-        Shift,
-        "UPDATE shift SET worker_id = NULL WHERE id = $1 AND worker_id = $2 RETURNING *",
+    cancellable_shift(&access, shift_id, &mut tran).await?;
+    let shift = assignment::unassign(shift_id, worker_id, &mut tran).await?;
+    notify::record(
+        NotificationKind::Cancellation,
         shift_id,
-        worker_id
+        worker_id,
+        Some(reason),
+        &mut tran,
     )
-    .fetch_one(&mut *tran)
     .await?;
+    waitlist::promote(shift_id, &mut tran).await?;
+    // Admins canceling for a worker already know about it
+    if !access.is_admin {
+        email::send_cancellation(worker_id, shift.clone(), reason.to_owned(), &mut tran).await?;
+    }
 
     tran.commit().await?;
 
     let list = shift_list(
-        session,
-        Path(worker_id),
+        access,
         Query(ShiftListQuery {
             event_id: Some(shift.event_id),
         }),
@@ -109,12 +228,17 @@ pub async fn cancel_shift(
     .await?;
 
     info!(
-        "Worker {} has canceled their shift: {} {}-{} on {}",
+        "Worker {} has canceled their shift: {} {}-{} on {}, because: {}",
         worker_id,
         shift.title,
         filters::time_short(&shift.start_time).expect("Infallible"),
         filters::time_short(&shift.end_time).expect("Infallible"),
         filters::date_short(&shift.date).expect("Infallible"),
+        reason,
     );
-    Ok(list)
+    // Errors go in the form, but the updated list replaces it
+    Ok((
+        [("HX-Retarget", "#shift-list"), ("HX-Reswap", "outerHTML")],
+        list,
+    ))
 }
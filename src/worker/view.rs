@@ -1,17 +1,25 @@
 use askama::Template;
-use axum::{extract::Path, Form};
-use cafe_website::AppError;
+use axum_extra::extract::Form;
+use cafe_website::{filters, AppError};
 use regex::Regex;
 use serde::Deserialize;
-use tracing::debug;
 use uuid::Uuid;
 
-use crate::{config, session::Session, worker::Worker};
+use crate::{
+    config,
+    models::{AgeBracket, ShirtSize, Skill},
+    worker::Worker,
+};
+
+use super::{
+    access::WorkerAccess,
+    profile::{self, Availability, Profile, ProfileUpdate},
+};
 
 #[derive(Template)]
 #[template(path = "worker/view.html")]
 pub struct WorkerView {
-    id: Uuid,
+    pub(super) id: Uuid,
 }
 
 #[derive(Template)]
@@ -22,63 +30,77 @@ pub struct WorkerDetails {
     name_last: String,
     email: String,
     phone: Option<String>,
+    profile: Profile,
     error: Option<&'static str>,
     edit: bool,
     is_admin: bool,
+    read_only: bool,
 }
 
-pub async fn view(Path(id): Path<Uuid>) -> Result<WorkerView, AppError> {
-    let _ = sqlx::query_as!(Worker, "SELECT * FROM worker WHERE id = $1", id)
-        .fetch_one(config().pool())
-        .await?;
+impl WorkerDetails {
+    async fn new(
+        worker: Worker,
+        access: WorkerAccess,
+        edit: bool,
+        error: Option<&'static str>,
+    ) -> Result<WorkerDetails, AppError> {
+        let Worker {
+            id,
+            email,
+            phone,
+            name_first,
+            name_last,
+        } = worker;
+        Ok(WorkerDetails {
+            id,
+            name_first,
+            name_last,
+            email,
+            phone,
+            profile: profile::load(id).await?,
+            error,
+            edit,
+            is_admin: access.is_admin,
+            read_only: access.read_only,
+        })
+    }
+}
+
+pub async fn view(access: WorkerAccess) -> Result<WorkerView, AppError> {
+    let Worker { id, .. } = sqlx::query_as!(
+        Worker,
+        "SELECT * FROM worker WHERE id = $1",
+        access.worker_id
+    )
+    .fetch_one(config().pool())
+    .await?;
 
     Ok(WorkerView { id })
 }
 
-pub async fn details(session: Session, Path(id): Path<Uuid>) -> Result<WorkerDetails, AppError> {
-    let Worker {
-        id,
-        email,
-        phone,
-        name_first,
-        name_last,
-    } = sqlx::query_as!(Worker, "SELECT * FROM worker WHERE id = $1", id)
-        .fetch_one(config().pool())
-        .await?;
-
-    Ok(WorkerDetails {
-        id,
-        name_first,
-        name_last,
-        email,
-        phone,
-        error: None,
-        is_admin: session.is_authenticated(),
-        edit: false,
-    })
+pub async fn details(access: WorkerAccess) -> Result<WorkerDetails, AppError> {
+    let worker = sqlx::query_as!(
+        Worker,
+        "SELECT * FROM worker WHERE id = $1",
+        access.worker_id
+    )
+    .fetch_one(config().pool())
+    .await?;
+
+    WorkerDetails::new(worker, access, false, None).await
 }
 
-pub async fn edit(session: Session, Path(id): Path<Uuid>) -> Result<WorkerDetails, AppError> {
-    let Worker {
-        id,
-        email,
-        phone,
-        name_first,
-        name_last,
-    } = sqlx::query_as!(Worker, "SELECT * FROM worker WHERE id = $1", id)
-        .fetch_one(config().pool())
-        .await?;
-
-    Ok(WorkerDetails {
-        id,
-        name_first,
-        name_last,
-        email,
-        phone,
-        is_admin: session.is_authenticated(),
-        error: None,
-        edit: true,
-    })
+pub async fn edit(access: WorkerAccess) -> Result<WorkerDetails, AppError> {
+    access.check_writable()?;
+    let worker = sqlx::query_as!(
+        Worker,
+        "SELECT * FROM worker WHERE id = $1",
+        access.worker_id
+    )
+    .fetch_one(config().pool())
+    .await?;
+
+    WorkerDetails::new(worker, access, true, None).await
 }
 
 #[derive(Deserialize)]
@@ -87,33 +109,58 @@ pub struct WorkerEdit {
     name_last: String,
     email: String,
     phone: Option<String>,
+    shirt_size: Option<ShirtSize>,
+    age_bracket: Option<AgeBracket>,
+    notes: Option<String>,
+    #[serde(default)]
+    skill: Vec<Skill>,
+    /// Every day in the form, lined up with `start` and `end`
+    #[serde(default)]
+    day: Vec<String>,
+    #[serde(default)]
+    start: Vec<String>,
+    #[serde(default)]
+    end: Vec<String>,
+    /// The days that were checked
+    #[serde(default)]
+    available: Vec<String>,
+}
+
+impl WorkerEdit {
+    fn availability(&self) -> Result<Vec<Availability>, &'static str> {
+        self.day
+            .iter()
+            .enumerate()
+            .filter(|(_, key)| self.available.contains(key))
+            .map(|(i, key)| {
+                let start = self.start.get(i).map_or("", String::as_str);
+                let end = self.end.get(i).map_or("", String::as_str);
+                Availability::parse(key, start, end)
+            })
+            .collect()
+    }
 }
 
 pub async fn save(
-    session: Session,
-    Path(id): Path<Uuid>,
+    access: WorkerAccess,
     Form(req): Form<WorkerEdit>,
 ) -> Result<WorkerDetails, AppError> {
-    let Worker {
-        id: _,
-        email,
-        phone,
-        name_first,
-        name_last,
-    } = sqlx::query_as!(Worker, "SELECT * FROM worker WHERE id = $1", id)
-        .fetch_one(config().pool())
-        .await?;
-    if !session.is_authenticated() && email != req.email {
-        return Ok(WorkerDetails {
-            id,
-            name_first,
-            name_last,
-            email,
-            phone,
-            error: Some("Unable to change email address. Contact an admin to change."),
-            is_admin: session.is_authenticated(),
-            edit: false,
-        });
+    access.check_writable()?;
+    let worker = sqlx::query_as!(
+        Worker,
+        "SELECT * FROM worker WHERE id = $1",
+        access.worker_id
+    )
+    .fetch_one(config().pool())
+    .await?;
+    if !access.is_admin && worker.email != req.email {
+        return WorkerDetails::new(
+            worker,
+            access,
+            false,
+            Some("Unable to change email address. Contact an admin to change."),
+        )
+        .await;
     }
 
     const PHONE_REGEX: &str = r#"^[2-9][0-9]{2}-[2-9][0-9]{2}-[0-9]{4}$"#;
@@ -121,45 +168,25 @@ pub async fn save(
     let em_rx = Regex::new(EMAIL_REGEX).expect("Email regex should be valid");
     let email_match = em_rx.is_match(&req.email);
     if !email_match {
-        return Ok(WorkerDetails {
-            id,
-            name_first,
-            name_last,
-            email,
-            phone,
-            error: Some("Invalid email"),
-            edit: true,
-            is_admin: session.is_authenticated(),
-        });
+        return WorkerDetails::new(worker, access, true, Some("Invalid email")).await;
     }
     let phone_match = match req.phone.as_deref() {
         None | Some("") => true,
         Some(ph) => {
-            debug!(?ph);
             let ph_rx = Regex::new(PHONE_REGEX).expect("Phone regex should be valid");
             ph_rx.is_match(ph)
         }
     };
     if !phone_match {
-        return Ok(WorkerDetails {
-            id,
-            name_first,
-            name_last,
-            email,
-            phone,
-            error: Some("Invalid phone number"),
-            edit: true,
-            is_admin: session.is_authenticated(),
-        });
+        return WorkerDetails::new(worker, access, true, Some("Invalid phone number")).await;
     }
+    let availability = match req.availability() {
+        Ok(availability) => availability,
+        Err(msg) => return WorkerDetails::new(worker, access, true, Some(msg)).await,
+    };
 
-    let Worker {
-        id,
-        email,
-        phone,
-        name_first,
-        name_last,
-    } = sqlx::query_as!(
+    let mut tran = config().pool().begin().await?;
+    let worker = sqlx::query_as!(
         Worker,
         "UPDATE worker 
         SET name_first = $1, name_last = $2, email = $3, phone = $4
@@ -169,19 +196,22 @@ pub async fn save(
         req.name_last,
         req.email,
         req.phone.filter(|ph| !ph.is_empty()),
-        id
+        worker.id
     )
-    .fetch_one(config().pool())
+    .fetch_one(&mut *tran)
     .await?;
+    // Only admins can vouch for the skills and age shifts can require
+    let update = ProfileUpdate {
+        shirt_size: req.shirt_size,
+        age_bracket: access.is_admin.then_some(req.age_bracket),
+        notes: access
+            .is_admin
+            .then(|| req.notes.filter(|n| !n.trim().is_empty())),
+        skills: access.is_admin.then_some(req.skill),
+        availability,
+    };
+    profile::save(worker.id, update, &mut tran).await?;
+    tran.commit().await?;
 
-    Ok(WorkerDetails {
-        id,
-        name_first,
-        name_last,
-        email,
-        phone,
-        is_admin: session.is_authenticated(),
-        error: None,
-        edit: false,
-    })
+    WorkerDetails::new(worker, access, false, None).await
 }